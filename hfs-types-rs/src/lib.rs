@@ -5,6 +5,8 @@
 
 #![forbid(dead_code, unsafe_code, unused)]
 #![forbid(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+// Code generated by deku's derive macros trips this lint.
+#![allow(clippy::manual_div_ceil)]
 
 use crate::FromSliceError::{MismatchedLength, SliceTooShort};
 use std::num::NonZeroU32;
@@ -28,15 +30,201 @@ pub enum VolumeVersion {
     HfsX = 5,
 }
 
-// DESIGN Would this be better as a NewType `DateTime(u32)`?
 /// Represents seconds since 01-01-1904 GMT, including a leap day for years evenly
 /// divisible by four.
 ///
 /// Exception for [`field@VolumeHeader::creation_date`],
-/// which is stored in local time.
+/// which is stored in local time. Use the `_local` conversions with the
+/// offset of the machine that created the volume for this field.
+///
+/// A value of zero usually means the date was never set, such as a file that
+/// has never been backed up. Conversions return `None` for these values.
 ///
 /// Described in TN1150 [HFS Plus Dates](https://developer.apple.com/library/archive/technotes/tn/tn1150.html#HFSPlusDates).
-pub type DateTime = u32;
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "deku", derive(deku::DekuRead))]
+#[cfg_attr(
+    feature = "deku",
+    deku(
+        endian = "endian",
+        ctx = "endian: deku::ctx::Endian",
+        ctx_default = "deku::ctx::Endian::Big"
+    )
+)]
+pub struct DateTime(pub u32);
+
+/// Seconds between the HFS Plus epoch (01-01-1904) and the Unix epoch (01-01-1970).
+pub const UNIX_EPOCH_OFFSET: i64 = 2_082_844_800;
+
+impl DateTime {
+    /// Whether the date holds a value other than zero.
+    pub fn is_set(self) -> bool {
+        self.0 != 0
+    }
+
+    /// Create a date from seconds since the Unix epoch, if it can be represented.
+    pub fn from_unix(seconds: i64) -> Option<Self> {
+        seconds
+            .checked_add(UNIX_EPOCH_OFFSET)
+            .and_then(|seconds| u32::try_from(seconds).ok())
+            .map(Self)
+    }
+
+    /// Seconds since the Unix epoch for a date stored in GMT. Dates before
+    /// 1970 are negative.
+    pub fn to_unix(self) -> Option<i64> {
+        self.is_set().then(|| self.0 as i64 - UNIX_EPOCH_OFFSET)
+    }
+
+    /// Seconds since the Unix epoch for a date stored in local time, where
+    /// `utc_offset` is the local timezone's offset east of UTC in seconds.
+    pub fn to_unix_local(self, utc_offset: i32) -> Option<i64> {
+        self.to_unix().map(|seconds| seconds - utc_offset as i64)
+    }
+
+    /// Convert a date stored in GMT to a [`std::time::SystemTime`].
+    pub fn to_system_time(self) -> Option<std::time::SystemTime> {
+        self.to_unix().and_then(unix_to_system_time)
+    }
+
+    /// Convert a date stored in local time to a [`std::time::SystemTime`].
+    pub fn to_system_time_local(self, utc_offset: i32) -> Option<std::time::SystemTime> {
+        self.to_unix_local(utc_offset).and_then(unix_to_system_time)
+    }
+
+    /// Format a date stored in local time as RFC 3339 with the given offset,
+    /// for example `2001-02-03T04:05:06+01:00`. Dates that were never set are
+    /// formatted as `never`.
+    pub fn display_local(self, utc_offset: i32) -> impl std::fmt::Display {
+        DateTimeDisplay {
+            date: self,
+            utc_offset: Some(utc_offset),
+        }
+    }
+
+    /// Convert a date stored in GMT to a [`chrono::DateTime`].
+    #[cfg(feature = "chrono")]
+    pub fn to_chrono(self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.to_unix()
+            .and_then(|seconds| chrono::DateTime::from_timestamp(seconds, 0))
+    }
+
+    /// Convert a date stored in local time to a [`chrono::DateTime`] carrying
+    /// the given offset.
+    #[cfg(feature = "chrono")]
+    pub fn to_chrono_local(self, utc_offset: i32) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+        let offset = chrono::FixedOffset::east_opt(utc_offset)?;
+        self.to_unix_local(utc_offset)
+            .and_then(|seconds| chrono::DateTime::from_timestamp(seconds, 0))
+            .map(|date| date.with_timezone(&offset))
+    }
+
+    /// Convert a date stored in GMT to a [`time::OffsetDateTime`] in UTC.
+    #[cfg(feature = "time")]
+    pub fn to_offset_date_time(self) -> Option<time::OffsetDateTime> {
+        self.to_unix()
+            .and_then(|seconds| time::OffsetDateTime::from_unix_timestamp(seconds).ok())
+    }
+
+    /// Convert a date stored in local time to a [`time::OffsetDateTime`]
+    /// carrying the given offset.
+    #[cfg(feature = "time")]
+    pub fn to_offset_date_time_local(self, utc_offset: i32) -> Option<time::OffsetDateTime> {
+        let offset = time::UtcOffset::from_whole_seconds(utc_offset).ok()?;
+        self.to_unix_local(utc_offset)
+            .and_then(|seconds| time::OffsetDateTime::from_unix_timestamp(seconds).ok())
+            .map(|date| date.to_offset(offset))
+    }
+}
+
+fn unix_to_system_time(seconds: i64) -> Option<std::time::SystemTime> {
+    let magnitude = std::time::Duration::from_secs(seconds.unsigned_abs());
+    if seconds < 0 {
+        std::time::UNIX_EPOCH.checked_sub(magnitude)
+    } else {
+        std::time::UNIX_EPOCH.checked_add(magnitude)
+    }
+}
+
+/// Formats a date stored in GMT as RFC 3339 in UTC, for example
+/// `2001-02-03T04:05:06Z`. Dates that were never set are formatted as `never`.
+impl std::fmt::Display for DateTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        DateTimeDisplay {
+            date: *self,
+            utc_offset: None,
+        }
+        .fmt(f)
+    }
+}
+
+/// Serializes a date stored in GMT as formatted by [`std::fmt::Display`], or
+/// as none if it was never set.
+#[cfg(feature = "serde")]
+impl serde::Serialize for DateTime {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.is_set() {
+            serializer.collect_str(self)
+        } else {
+            serializer.serialize_none()
+        }
+    }
+}
+
+struct DateTimeDisplay {
+    date: DateTime,
+    /// Offset of a date stored in local time, or `None` for dates in GMT.
+    utc_offset: Option<i32>,
+}
+
+impl std::fmt::Display for DateTimeDisplay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Local dates are formatted as stored, and labelled with their offset.
+        let Some(seconds) = self.date.to_unix() else {
+            return write!(f, "never");
+        };
+
+        let (days, time) = (seconds.div_euclid(86_400), seconds.rem_euclid(86_400));
+        let (year, month, day) = civil_from_days(days);
+        write!(
+            f,
+            "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}",
+            time / 3600,
+            time / 60 % 60,
+            time % 60
+        )?;
+
+        match self.utc_offset {
+            None => write!(f, "Z"),
+            Some(offset) => {
+                let sign = if offset < 0 { '-' } else { '+' };
+                let minutes = offset.unsigned_abs() / 60;
+                write!(f, "{sign}{:02}:{:02}", minutes / 60, minutes % 60)
+            }
+        }
+    }
+}
+
+/// Convert days since the Unix epoch to a proleptic Gregorian date. From
+/// Howard Hinnant's [chrono-Compatible Low-Level Date Algorithms](https://howardhinnant.github.io/date_algorithms.html#civil_from_days).
+/// Returns the year, month, and day of the month, counting from one.
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
 
 /// Catalog Node ID
 ///
@@ -312,6 +500,9 @@ pub enum BsdInfoOwnerFlags {
     Immutable = 2,
     AppendOnly = 4,
     Opaque = 8,
+    /// Not described in TN1150. Contents are stored compressed in the
+    /// `com.apple.decmpfs` extended attribute or resource fork.
+    Compressed = 0x20,
 }
 
 #[repr(u16)]
@@ -498,6 +689,7 @@ impl AllocationMapRecord {
     /// Determine if a block has been set in the bitmap.
     ///
     /// Returns whether the bit is set, or an error if block index is out of bounds.
+    #[allow(clippy::result_unit_err)]
     pub fn is_block_used(&self, block: u32) -> Result<bool, ()> {
         let offset = block / 8;
         let Some(byte) = self.0.get(offset as usize) else {
@@ -924,3 +1116,111 @@ pub fn calculate_checksum(bytes: &[u8]) -> u32 {
 
     !checksum
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn unset_dates() {
+        let date = DateTime(0);
+        assert!(!date.is_set());
+        assert_eq!(date.to_unix(), None);
+        assert_eq!(date.to_unix_local(3600), None);
+        assert_eq!(date.to_system_time(), None);
+        assert_eq!(date.to_string(), "never");
+        assert_eq!(date.display_local(3600).to_string(), "never");
+    }
+
+    #[test]
+    fn known_epoch_values() {
+        assert_eq!(DateTime(1).to_unix(), Some(-UNIX_EPOCH_OFFSET + 1));
+        assert_eq!(DateTime(1).to_string(), "1904-01-01T00:00:01Z");
+
+        let unix_epoch = DateTime(2_082_844_800);
+        assert_eq!(unix_epoch.to_unix(), Some(0));
+        assert_eq!(unix_epoch.to_system_time(), Some(UNIX_EPOCH));
+        assert_eq!(unix_epoch.to_string(), "1970-01-01T00:00:00Z");
+
+        // 2000-02-29T12:34:56Z, a leap day.
+        let leap_day = DateTime::from_unix(951_827_696);
+        assert_eq!(leap_day, Some(DateTime(3_034_672_496)));
+        assert_eq!(
+            leap_day.map(|date| date.to_string()).as_deref(),
+            Some("2000-02-29T12:34:56Z")
+        );
+
+        assert_eq!(DateTime(u32::MAX).to_unix(), Some(2_212_122_495));
+        assert_eq!(DateTime(u32::MAX).to_string(), "2040-02-06T06:28:15Z");
+    }
+
+    #[test]
+    fn unix_round_trip() {
+        for seconds in [-UNIX_EPOCH_OFFSET + 1, -1, 0, 1, 1_000_000_000] {
+            assert_eq!(
+                DateTime::from_unix(seconds).and_then(DateTime::to_unix),
+                Some(seconds)
+            );
+        }
+        assert_eq!(
+            DateTime::from_unix(1_000_000_000).and_then(DateTime::to_system_time),
+            Some(UNIX_EPOCH + Duration::from_secs(1_000_000_000))
+        );
+        assert_eq!(DateTime::from_unix(-UNIX_EPOCH_OFFSET - 1), None);
+        assert_eq!(DateTime::from_unix(2_212_122_496), None);
+        assert_eq!(DateTime::from_unix(i64::MAX), None);
+    }
+
+    #[test]
+    fn local_dates() {
+        // 1970-01-01T01:00:00 local time, one hour east of UTC.
+        let date = DateTime(2_082_844_800 + 3600);
+        assert_eq!(date.to_unix_local(3600), Some(0));
+        assert_eq!(date.to_system_time_local(3600), Some(UNIX_EPOCH));
+        assert_eq!(
+            date.display_local(3600).to_string(),
+            "1970-01-01T01:00:00+01:00"
+        );
+        assert_eq!(
+            date.display_local(-(5 * 3600 + 30 * 60)).to_string(),
+            "1970-01-01T01:00:00-05:30"
+        );
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn chrono_dates() {
+        let date = DateTime(2_082_844_800 + 3600);
+        assert_eq!(date.to_chrono().map(|date| date.timestamp()), Some(3600));
+        let local = date.to_chrono_local(3600);
+        assert_eq!(local.map(|date| date.timestamp()), Some(0));
+        assert_eq!(
+            local.map(|date| date.offset().local_minus_utc()),
+            Some(3600)
+        );
+        assert_eq!(DateTime(0).to_chrono(), None);
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn time_dates() {
+        let date = DateTime(2_082_844_800 + 3600);
+        assert_eq!(
+            date.to_offset_date_time().map(|date| date.unix_timestamp()),
+            Some(3600)
+        );
+        let local = date.to_offset_date_time_local(3600);
+        assert_eq!(local.map(|date| date.unix_timestamp()), Some(0));
+        assert_eq!(local.map(|date| date.hour()), Some(1));
+        assert_eq!(DateTime(0).to_offset_date_time(), None);
+    }
+
+    #[test]
+    fn civil_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(civil_from_days(-24_107), (1904, 1, 1));
+    }
+}
@@ -138,6 +138,10 @@ pub const UF_IMMUTABLE: u8 = 2;
 pub const UF_APPEND: u8 = 4;
 pub const UF_OPAQUE: u8 = 8;
 
+/// Not described in TN1150. Set by macOS on files whose contents are stored
+/// compressed in the `com.apple.decmpfs` extended attribute or resource fork.
+pub const UF_COMPRESSED: u8 = 0x20;
+
 /// set user id on execution
 pub const S_ISUID: u16 = 0o00_4000;
 
@@ -0,0 +1,479 @@
+//! Ownership of allocation blocks. Described in TN1150 > Allocation File.
+
+use crate::catalog::CatalogMap;
+use crate::extents::{OverflowExtents, fork_overflow_extents};
+use crate::fork::assemble_fork;
+use crate::*;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// Which fork of an item an extent belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForkKind {
+    Data,
+    Resource,
+}
+
+/// An extent claimed by a file, or by one of the volume's special files.
+#[derive(Debug, Clone)]
+pub struct OwnedExtent {
+    pub start_block: u32,
+    pub block_count: u32,
+    pub owner: CatalogNodeId,
+    pub fork: ForkKind,
+}
+
+impl OwnedExtent {
+    /// Whether this extent shares any blocks with the given range.
+    pub fn overlaps(&self, start_block: u32, block_count: u32) -> bool {
+        let (start, end) = (self.start_block as u64, self.end());
+        let (other_start, other_end) =
+            (start_block as u64, start_block as u64 + block_count as u64);
+        start < other_end && other_start < end
+    }
+
+    fn end(&self) -> u64 {
+        self.start_block as u64 + self.block_count as u64
+    }
+}
+
+/// Extents claimed by the special files in the volume header and by the
+/// files in a catalog, and optionally by the Extents Overflow File's records.
+#[derive(Debug, Default)]
+pub struct BlockOwnership {
+    extents: Vec<OwnedExtent>,
+}
+
+impl BlockOwnership {
+    pub fn from_catalog(volume_header: &VolumeHeader, map: &CatalogMap) -> Self {
+        let mut ownership = Self::default();
+
+        let special_files = [
+            (
+                StandardCnid::kHFSAllocationFileID,
+                &volume_header.allocation_file,
+            ),
+            (StandardCnid::kHFSExtentsFileID, &volume_header.extents_file),
+            (StandardCnid::kHFSCatalogFileID, &volume_header.catalog_file),
+            (
+                StandardCnid::kHFSAttributesFileID,
+                &volume_header.attributes_file,
+            ),
+            (StandardCnid::kHFSStartupFileID, &volume_header.startup_file),
+        ];
+        for (cnid, fork) in special_files {
+            ownership.add_fork(cnid as CatalogNodeId, ForkKind::Data, fork);
+        }
+
+        for record in map.values() {
+            if let CatalogLeafRecord::File(file) = record {
+                ownership.add_fork(file.file_id, ForkKind::Data, &file.data_fork);
+                ownership.add_fork(file.file_id, ForkKind::Resource, &file.resource_fork);
+            }
+        }
+
+        ownership.extents.sort_by_key(|extent| extent.start_block);
+        ownership
+    }
+
+    fn add_fork(&mut self, owner: CatalogNodeId, fork: ForkKind, fork_data: &ForkData) {
+        self.extents.extend(
+            fork_data
+                .extents
+                .iter()
+                .filter(|extent| extent.block_count != 0)
+                .map(|extent| OwnedExtent {
+                    start_block: extent.start_block,
+                    block_count: extent.block_count,
+                    owner,
+                    fork,
+                }),
+        );
+    }
+
+    /// Add the extents held in overflow records, which include those of the
+    /// bad block file.
+    pub fn add_overflow_extents(&mut self, overflow: &[OverflowExtents]) {
+        for record in overflow {
+            let (owner, fork) = (record.key.file_id, record.fork());
+            self.extents.extend(
+                record
+                    .extents
+                    .iter()
+                    .filter(|extent| extent.block_count != 0)
+                    .map(|extent| OwnedExtent {
+                        start_block: extent.start_block,
+                        block_count: extent.block_count,
+                        owner,
+                        fork,
+                    }),
+            );
+        }
+        self.extents.sort_by_key(|extent| extent.start_block);
+    }
+
+    /// All owned extents, sorted by start block.
+    pub fn extents(&self) -> &[OwnedExtent] {
+        &self.extents
+    }
+
+    /// Owned extents that share blocks with the given range.
+    pub fn overlapping(
+        &self,
+        start_block: u32,
+        block_count: u32,
+    ) -> impl Iterator<Item = &OwnedExtent> {
+        // Extents are sorted by start, so anything starting after the range
+        // cannot overlap it.
+        let end = start_block as u64 + block_count as u64;
+        let candidates = self
+            .extents
+            .partition_point(|extent| (extent.start_block as u64) < end);
+        self.extents[..candidates]
+            .iter()
+            .filter(move |extent| extent.overlaps(start_block, block_count))
+    }
+}
+
+/// The volume bitmap stored in the allocation file, with one bit per
+/// allocation block, most significant bit first.
+#[derive(Debug, Default)]
+pub struct AllocationBitmap {
+    bitmap: Vec<u8>,
+    total_blocks: u32,
+}
+
+impl AllocationBitmap {
+    /// Read the allocation file, including any extents in `overflow`. Only
+    /// the blocks that the header counts, the bitmap covers, and the volume
+    /// holds are considered.
+    pub fn read(
+        volume: &mut (impl Read + Seek),
+        volume_header: &VolumeHeader,
+        overflow: &[OverflowExtents],
+    ) -> Result<Self, io::Error> {
+        let block_size = volume_header.block_size.max(1) as u64;
+        let volume_blocks = volume.seek(SeekFrom::End(0))? / block_size;
+        let overflow_extents = fork_overflow_extents(
+            overflow,
+            StandardCnid::kHFSAllocationFileID as CatalogNodeId,
+            ForkKind::Data,
+        );
+        let bitmap = assemble_fork(
+            volume,
+            &volume_header.allocation_file,
+            &overflow_extents,
+            volume_header.block_size as usize,
+        )?;
+        let total_blocks = (volume_header.total_blocks as u64)
+            .min(bitmap.len() as u64 * 8)
+            .min(volume_blocks) as u32;
+        Ok(Self {
+            bitmap,
+            total_blocks,
+        })
+    }
+
+    /// Number of blocks covered by the bitmap, which may be fewer than the
+    /// volume header counts if the bitmap or volume is cut short.
+    pub fn total_blocks(&self) -> u32 {
+        self.total_blocks
+    }
+
+    /// Whether a block is marked in use. Blocks the bitmap does not cover are
+    /// reported as used, so they are never mistaken for free space.
+    pub fn is_block_used(&self, block: u32) -> bool {
+        if block >= self.total_blocks {
+            return true;
+        }
+        match self.bitmap.get(block as usize / 8) {
+            Some(byte) => byte & (0x80 >> (block % 8)) != 0,
+            None => true,
+        }
+    }
+
+    /// Runs of consecutive free blocks, in volume order.
+    pub fn free_extents(&self) -> impl Iterator<Item = ExtentDescriptor> + '_ {
+        self.runs(false)
+    }
+
+    /// Runs of consecutive used blocks, in volume order.
+    pub fn used_extents(&self) -> impl Iterator<Item = ExtentDescriptor> + '_ {
+        self.runs(true)
+    }
+
+    /// Number of blocks marked free, to compare with the volume header's
+    /// `free_blocks`.
+    pub fn free_block_count(&self) -> u32 {
+        self.free_extents().map(|extent| extent.block_count).sum()
+    }
+
+    pub fn used_block_count(&self) -> u32 {
+        self.total_blocks - self.free_block_count()
+    }
+
+    fn runs(&self, used: bool) -> impl Iterator<Item = ExtentDescriptor> + '_ {
+        let mut block = 0;
+        std::iter::from_fn(move || {
+            let start_block = self.next_block(block, used);
+            if start_block >= self.total_blocks {
+                return None;
+            }
+            block = self.next_block(start_block, !used);
+            Some(ExtentDescriptor {
+                start_block,
+                block_count: block - start_block,
+            })
+        })
+    }
+
+    /// The first block from `block` onwards in the given state, or the total
+    /// block count if there is none.
+    fn next_block(&self, mut block: u32, used: bool) -> u32 {
+        // Finish the current byte, then skip whole bytes in the other state.
+        while block < self.total_blocks && !block.is_multiple_of(8) {
+            if self.is_block_used(block) == used {
+                return block;
+            }
+            block += 1;
+        }
+        let other = if used { 0x00 } else { 0xFF };
+        let bytes = &self.bitmap[..(self.total_blocks as usize).div_ceil(8)];
+        let start_byte = (block as usize / 8).min(bytes.len());
+        let skipped = bytes[start_byte..]
+            .iter()
+            .position(|&byte| byte != other)
+            .unwrap_or(bytes.len() - start_byte);
+        block = ((start_byte + skipped) * 8).min(self.total_blocks as usize) as u32;
+        while block < self.total_blocks {
+            if self.is_block_used(block) == used {
+                return block;
+            }
+            block += 1;
+        }
+        self.total_blocks
+    }
+}
+
+/// A disagreement between the allocation bitmap and the extents that claim
+/// blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockProblemKind {
+    /// Marked used, but not claimed by any extent. Usually leaked space.
+    AllocatedUnowned,
+    /// Claimed by an extent, but marked free, so it may be reused.
+    OwnedFree,
+    /// Claimed by more than one extent.
+    MultiplyOwned,
+    /// Claimed by an extent, but past the end of the volume.
+    OutOfRange,
+    /// Within the volume, but past the end of the allocation bitmap or of
+    /// the image, so not checked.
+    Uncovered,
+}
+
+impl fmt::Display for BlockProblemKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::AllocatedUnowned => "allocated but unowned",
+            Self::OwnedFree => "owned but free",
+            Self::MultiplyOwned => "claimed more than once",
+            Self::OutOfRange => "past the end of the volume",
+            Self::Uncovered => "not covered by the allocation bitmap",
+        })
+    }
+}
+
+/// A run of blocks with the same problem and owners.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockProblem {
+    pub start_block: u32,
+    pub block_count: u32,
+    pub kind: BlockProblemKind,
+    /// The owner of each extent claiming the blocks, so an item claiming the
+    /// same blocks twice appears twice. Empty for unowned blocks and for the
+    /// blocks holding the volume headers.
+    pub owners: Vec<CatalogNodeId>,
+}
+
+impl fmt::Display for BlockProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "blocks {}..{} {}",
+            self.start_block,
+            self.start_block as u64 + self.block_count as u64,
+            self.kind
+        )?;
+        if !self.owners.is_empty() {
+            write!(f, " by {:?}", self.owners)?;
+        }
+        Ok(())
+    }
+}
+
+/// The result of checking the allocation bitmap against block ownership.
+#[derive(Debug)]
+pub struct AllocationReport {
+    /// Free blocks according to the volume header.
+    pub header_free_blocks: u32,
+    /// Free blocks according to the bitmap.
+    pub bitmap_free_blocks: u32,
+    pub problems: Vec<BlockProblem>,
+}
+
+/// A run of blocks claimed by the same set of extents.
+struct OwnedRun {
+    start: u64,
+    end: u64,
+    owners: Vec<CatalogNodeId>,
+}
+
+/// Split the owned extents into runs where the set of claiming extents does
+/// not change.
+fn owned_runs(extents: &[OwnedExtent]) -> Vec<OwnedRun> {
+    // Ends sort before starts, so that adjacent extents do not overlap.
+    let mut events = extents
+        .iter()
+        .enumerate()
+        .flat_map(|(index, extent)| {
+            [
+                (extent.start_block as u64, true, index),
+                (extent.end(), false, index),
+            ]
+        })
+        .collect::<Vec<_>>();
+    events.sort_unstable();
+
+    let mut runs = Vec::new();
+    let mut active = Vec::<usize>::new();
+    let mut previous = 0;
+    for (position, is_start, index) in events {
+        if position > previous && !active.is_empty() {
+            runs.push(OwnedRun {
+                start: previous,
+                end: position,
+                owners: active.iter().map(|&index| extents[index].owner).collect(),
+            });
+        }
+        previous = position;
+        if is_start {
+            active.push(index);
+        } else {
+            active.retain(|&active| active != index);
+        }
+    }
+    runs
+}
+
+/// Check every block claimed by an extent against the allocation bitmap, and
+/// every allocated block against the extents. The blocks holding the volume
+/// header and alternate volume header are allocated without an owner. Blocks
+/// the bitmap does not cover are reported once, as a single run.
+pub fn verify_allocation(
+    volume_header: &VolumeHeader,
+    bitmap: &AllocationBitmap,
+    ownership: &BlockOwnership,
+) -> AllocationReport {
+    let total_blocks = volume_header.total_blocks as u64;
+    let covered_blocks = bitmap.total_blocks() as u64;
+    let block_size = volume_header.block_size.max(1) as u64;
+    let mut problems = Vec::new();
+    let mut push = |start: u64, end: u64, kind, owners: &[CatalogNodeId]| {
+        if start < end {
+            problems.push(BlockProblem {
+                start_block: start as u32,
+                block_count: (end - start) as u32,
+                kind,
+                owners: owners.to_vec(),
+            });
+        }
+    };
+
+    // The first 1536 bytes hold the boot blocks and volume header, and the
+    // last 1024 bytes hold the alternate volume header.
+    let reserved = [
+        (0, 1536u64.div_ceil(block_size)),
+        (
+            (total_blocks * block_size).saturating_sub(1024) / block_size,
+            total_blocks,
+        ),
+    ];
+    for (start, end) in reserved {
+        for free in (start..end.min(covered_blocks)).filter(|&b| !bitmap.is_block_used(b as u32)) {
+            push(free, free + 1, BlockProblemKind::OwnedFree, &[]);
+        }
+    }
+    push(
+        covered_blocks,
+        total_blocks,
+        BlockProblemKind::Uncovered,
+        &[],
+    );
+
+    let runs = owned_runs(ownership.extents());
+    for run in &runs {
+        push(
+            run.start.max(total_blocks),
+            run.end,
+            BlockProblemKind::OutOfRange,
+            &run.owners,
+        );
+        if run.owners.len() > 1 {
+            push(
+                run.start,
+                run.end.min(total_blocks),
+                BlockProblemKind::MultiplyOwned,
+                &run.owners,
+            );
+        }
+
+        let end = run.end.min(covered_blocks);
+        let mut block = run.start;
+        while block < end {
+            let used = bitmap.is_block_used(block as u32);
+            let run_end = (bitmap.next_block(block as u32, !used) as u64).min(end);
+            if !used {
+                push(block, run_end, BlockProblemKind::OwnedFree, &run.owners);
+            }
+            block = run_end;
+        }
+    }
+
+    // Owned runs and reserved blocks are both sorted, so each used extent
+    // only needs to look past the ones that end before it.
+    let mut claimed = runs
+        .iter()
+        .map(|run| (run.start, run.end))
+        .chain(reserved)
+        .collect::<Vec<_>>();
+    claimed.sort_unstable();
+    let mut next_claim = 0;
+    for extent in bitmap.used_extents() {
+        let mut block = extent.start_block as u64;
+        let end = block + extent.block_count as u64;
+        while next_claim < claimed.len() && claimed[next_claim].1 <= block {
+            next_claim += 1;
+        }
+        for &(claim_start, claim_end) in &claimed[next_claim..] {
+            if claim_start >= end {
+                break;
+            }
+            push(
+                block,
+                claim_start.min(end),
+                BlockProblemKind::AllocatedUnowned,
+                &[],
+            );
+            block = block.max(claim_end);
+        }
+        push(block, end, BlockProblemKind::AllocatedUnowned, &[]);
+    }
+
+    problems.sort_by_key(|problem| (problem.start_block, problem.kind as u8));
+    AllocationReport {
+        header_free_blocks: volume_header.free_blocks,
+        bitmap_free_blocks: bitmap.free_block_count(),
+        problems,
+    }
+}
@@ -0,0 +1,81 @@
+//! AppleDouble files, which carry an item's Finder info and resource fork
+//! alongside its data on file systems and archives without them. macOS
+//! stores them next to the item as `._` and its name. Described in RFC 1740.
+
+use crate::*;
+
+const MAGIC: u32 = 0x0005_1607;
+const VERSION: u32 = 0x0002_0000;
+/// The filler written by macOS, in place of the home file system.
+const FILLER: &[u8; 16] = b"Mac OS X        ";
+
+const RESOURCE_FORK_ENTRY: u32 = 2;
+const FINDER_INFO_ENTRY: u32 = 9;
+
+const HEADER_SIZE: usize = 26;
+const ENTRY_DESCRIPTOR_SIZE: usize = 12;
+const FINDER_INFO_SIZE: usize = 32;
+
+/// Name of the AppleDouble file for an item.
+pub fn appledouble_name(name: &str) -> String {
+    format!("._{name}")
+}
+
+/// The 32 bytes of Finder info of a file or folder, as stored in its record,
+/// or `None` for thread records.
+pub fn finder_info(record: &CatalogLeafRecord) -> Option<[u8; FINDER_INFO_SIZE]> {
+    let (user_info, finder_info) = match record {
+        CatalogLeafRecord::File(file) => (file.user_info.to_bytes(), file.finder_info.to_bytes()),
+        CatalogLeafRecord::Folder(folder) => {
+            (folder.user_info.to_bytes(), folder.finder_info.to_bytes())
+        }
+        _ => return None,
+    };
+    let mut bytes = [0u8; FINDER_INFO_SIZE];
+    bytes[..16].copy_from_slice(&user_info);
+    bytes[16..].copy_from_slice(&finder_info);
+    Some(bytes)
+}
+
+/// The start of an AppleDouble file holding Finder info and a resource fork
+/// of `resource_fork_length` bytes. The resource fork follows immediately,
+/// so that it can be streamed after the header.
+pub fn appledouble_header(
+    finder_info: &[u8; FINDER_INFO_SIZE],
+    resource_fork_length: u32,
+) -> Vec<u8> {
+    let finder_info_offset = HEADER_SIZE + 2 * ENTRY_DESCRIPTOR_SIZE;
+    let resource_fork_offset = finder_info_offset + FINDER_INFO_SIZE;
+
+    let mut header = Vec::with_capacity(resource_fork_offset);
+    header.extend_from_slice(&MAGIC.to_be_bytes());
+    header.extend_from_slice(&VERSION.to_be_bytes());
+    header.extend_from_slice(FILLER);
+    header.extend_from_slice(&2u16.to_be_bytes());
+    for (id, offset, length) in [
+        (
+            FINDER_INFO_ENTRY,
+            finder_info_offset,
+            FINDER_INFO_SIZE as u32,
+        ),
+        (
+            RESOURCE_FORK_ENTRY,
+            resource_fork_offset,
+            resource_fork_length,
+        ),
+    ] {
+        header.extend_from_slice(&id.to_be_bytes());
+        header.extend_from_slice(&(offset as u32).to_be_bytes());
+        header.extend_from_slice(&length.to_be_bytes());
+    }
+    header.extend_from_slice(finder_info);
+    header
+}
+
+/// Whether an item has anything to put in an AppleDouble file: Finder info
+/// that is not all zeroes, or a resource fork.
+pub fn needs_appledouble(record: &CatalogLeafRecord) -> bool {
+    let has_resource_fork =
+        matches!(record, CatalogLeafRecord::File(file) if file.resource_fork.logical_size != 0);
+    has_resource_fork || finder_info(record).is_some_and(|info| info != [0; FINDER_INFO_SIZE])
+}
@@ -0,0 +1,182 @@
+//! Attributes File records, which hold extended attributes. Described in
+//! TN1150 > Attributes File.
+
+use crate::btree::{Diagnostic, salvage_leaf_records};
+use crate::fork::{ForkReader, assemble_extents};
+use crate::*;
+use deku::bitvec::BitSlice;
+use std::io::{self, Cursor, Read, Seek};
+
+/// Bytes of a key before the name: key length, padding, file ID, start
+/// block, and name length. Defined as `struct HFSPlusAttrKey`.
+const KEY_HEADER_SIZE: usize = 14;
+
+/// Contents of an Attributes File record.
+#[derive(Debug)]
+pub enum AttributeValue {
+    /// The value itself, for small attributes.
+    Inline(Vec<u8>),
+    /// A fork holding the value, for large attributes.
+    Fork(ForkData),
+    /// Further extents of a fork value, beyond the eight in its fork data.
+    Extents(ExtentRecord),
+}
+
+/// One record of the Attributes File.
+#[derive(Debug)]
+pub struct ExtendedAttribute {
+    pub file_id: CatalogNodeId,
+    /// For [`AttributeValue::Extents`] records, the offset of the first
+    /// extent in allocation blocks from the start of the value. Otherwise
+    /// zero.
+    pub start_block: u32,
+    /// Raw UTF-16 name, as stored.
+    pub name: Vec<u16>,
+    pub value: AttributeValue,
+}
+
+impl ExtendedAttribute {
+    pub fn name(&self) -> String {
+        String::from_utf16_lossy(&self.name)
+    }
+}
+
+/// Parse a single leaf record from the Attributes B-tree.
+pub fn parse_attribute_leaf(record: &[u8]) -> Result<ExtendedAttribute, io::Error> {
+    let u16_at = |offset: usize| {
+        record
+            .get(offset..offset + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .ok_or_else(|| invalid_data("Attribute record is truncated"))
+    };
+    let u32_at = |offset: usize| {
+        record
+            .get(offset..offset + 4)
+            .map(|b| u32::from_be_bytes(b.try_into().unwrap()))
+            .ok_or_else(|| invalid_data("Attribute record is truncated"))
+    };
+
+    // The key length excludes the length field itself.
+    let key_length = u16_at(0)? as usize;
+    let file_id = u32_at(4)?;
+    let start_block = u32_at(8)?;
+    let name_length = u16_at(12)? as usize;
+    if KEY_HEADER_SIZE + name_length * 2 > key_length + 2 || name_length > 127 {
+        return Err(invalid_data(format!(
+            "Attribute name of {name_length} characters does not fit key length {key_length}"
+        )));
+    }
+    let name = (0..name_length)
+        .map(|i| u16_at(KEY_HEADER_SIZE + i * 2))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Records are aligned to two bytes.
+    let data_start = (key_length + 2).next_multiple_of(2);
+    let record_type = u32_at(data_start)?;
+    let value = match record_type {
+        hfs_types::kHFSPlusAttrInlineData => {
+            // Two reserved words, then the size and the value.
+            let size = u32_at(data_start + 12)? as usize;
+            let start = data_start + 16;
+            let data = record
+                .get(start..start + size)
+                .ok_or_else(|| invalid_data("Inline attribute value is truncated"))?;
+            AttributeValue::Inline(data.to_vec())
+        }
+        hfs_types::kHFSPlusAttrForkData => {
+            let bits = record
+                .get(data_start + 8..)
+                .map(BitSlice::from_slice)
+                .ok_or_else(|| invalid_data("Attribute fork data is truncated"))?;
+            let (_rest, fork) = ForkData::read(bits, ())?;
+            AttributeValue::Fork(fork)
+        }
+        hfs_types::kHFSPlusAttrExtents => {
+            let bits = record
+                .get(data_start + 8..)
+                .map(BitSlice::from_slice)
+                .ok_or_else(|| invalid_data("Attribute extents are truncated"))?;
+            let (_rest, extents) = ExtentRecord::read(bits, ())?;
+            AttributeValue::Extents(extents)
+        }
+        other => {
+            return Err(invalid_data(format!(
+                "Unknown attribute record type {other:#x}"
+            )));
+        }
+    };
+
+    Ok(ExtendedAttribute {
+        file_id,
+        start_block,
+        name,
+        value,
+    })
+}
+
+/// Read every readable record of the Attributes File, in key order. Damaged
+/// nodes and records are skipped and reported as with
+/// [`crate::btree::salvage_btree_leaves`].
+pub fn read_attributes(
+    volume: &mut (impl Read + Seek),
+    volume_header: &VolumeHeader,
+) -> Result<(Vec<ExtendedAttribute>, Vec<Diagnostic>), io::Error> {
+    let block_size = volume_header.block_size as usize;
+    if volume_header.attributes_file.logical_size == 0 {
+        return Ok((Vec::new(), Vec::new()));
+    }
+
+    let attributes_file = assemble_extents(volume, &volume_header.attributes_file, block_size)?;
+    salvage_leaf_records(
+        &mut Cursor::new(attributes_file),
+        block_size,
+        parse_attribute_leaf,
+    )
+}
+
+/// The attributes of a file or folder, excluding records that only continue
+/// the extents of an earlier one.
+pub fn item_attributes(
+    attributes: &[ExtendedAttribute],
+    cnid: CatalogNodeId,
+) -> impl Iterator<Item = &ExtendedAttribute> {
+    attributes.iter().filter(move |attribute| {
+        attribute.file_id == cnid && !matches!(attribute.value, AttributeValue::Extents(_))
+    })
+}
+
+/// Read the value of an attribute. Fork values are read from the volume,
+/// including extents from later records of the same attribute.
+pub fn attribute_value(
+    volume: &mut (impl Read + Seek),
+    block_size: usize,
+    attributes: &[ExtendedAttribute],
+    attribute: &ExtendedAttribute,
+) -> Result<Vec<u8>, io::Error> {
+    let fork = match &attribute.value {
+        AttributeValue::Inline(data) => return Ok(data.clone()),
+        AttributeValue::Fork(fork) => fork,
+        AttributeValue::Extents(_) => {
+            return Err(invalid_data("Attribute record only holds further extents"));
+        }
+    };
+    let mut continuations = attributes
+        .iter()
+        .filter(|other| other.file_id == attribute.file_id && other.name == attribute.name)
+        .filter_map(|other| match &other.value {
+            AttributeValue::Extents(extents) => Some((other.start_block, extents)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    continuations.sort_by_key(|(start_block, _)| *start_block);
+    let extents = fork.extents.iter().chain(
+        continuations
+            .into_iter()
+            .flat_map(|(_, extents)| extents.iter()),
+    );
+
+    let mut value = Vec::new();
+    ForkReader::new(volume, block_size, extents.copied(), fork.logical_size)
+        .read_to_end(&mut value)?;
+    Ok(value)
+}
@@ -0,0 +1,559 @@
+//! Writing extracted items to an archive instead of a directory, for
+//! `extract --to-tar` and `--to-zip`. Names, modes, owners, and links are
+//! kept as stored, without passing through the local file system.
+
+use hfsprust::allocation::ForkKind;
+use hfsprust::appledouble::{appledouble_header, appledouble_name, finder_info, needs_appledouble};
+use hfsprust::attributes::{ExtendedAttribute, attribute_value, item_attributes};
+use hfsprust::catalog::{CatalogMap, children, hard_link_target, read_symlink_target};
+use hfsprust::damage::DamageMap;
+use hfsprust::extents::{OverflowExtents, fork_overflow_extents};
+use hfsprust::filter::Filter;
+use hfsprust::fork::ForkReader;
+use hfsprust::tar::{TarEntry, TarEntryKind, TarWriter};
+use hfsprust::zip::{ZipEntry, ZipEntryKind, ZipWriter};
+use hfsprust::*;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Read, Seek, Write};
+
+/// A file or folder to archive.
+pub struct ArchiveItem<'a> {
+    /// Path components, starting with the volume name.
+    pub path: Vec<String>,
+    pub record: &'a CatalogLeafRecord,
+}
+
+/// The items below `start` selected by the filter, in an order where each
+/// folder comes before its contents. Folders are included if anything in
+/// them is, or if the filter selects the folder itself.
+pub fn archive_items<'a>(
+    catalog: &'a CatalogMap,
+    start: CatalogNodeId,
+    start_path: Vec<String>,
+    filter: &Filter,
+) -> Vec<ArchiveItem<'a>> {
+    let mut items = Vec::new();
+    let Some(record) = catalog::record_for_cnid(catalog, start) else {
+        return items;
+    };
+    let start_item = ArchiveItem {
+        path: start_path,
+        record,
+    };
+    match record {
+        CatalogLeafRecord::File(file)
+            if filter.matches(start_item.path.get(1..).unwrap_or_default(), file) =>
+        {
+            items.push(start_item);
+        }
+        CatalogLeafRecord::Folder(_) => {
+            let mut walk = Walk {
+                catalog,
+                filter,
+                items,
+                unwritten: Vec::new(),
+                visited: HashSet::from([start]),
+            };
+            walk.folder(start, start_item);
+            items = walk.items;
+        }
+        _ => {}
+    }
+    items
+}
+
+struct Walk<'a, 'f> {
+    catalog: &'a CatalogMap,
+    filter: &'f Filter,
+    items: Vec<ArchiveItem<'a>>,
+    /// Enclosing folders that are not in `items` yet, outermost first.
+    unwritten: Vec<ArchiveItem<'a>>,
+    /// Damaged catalogs can contain cycles, so each folder is visited once.
+    visited: HashSet<CatalogNodeId>,
+}
+
+impl<'a> Walk<'a, '_> {
+    fn push(&mut self, item: ArchiveItem<'a>) {
+        self.items.append(&mut self.unwritten);
+        self.items.push(item);
+    }
+
+    fn folder(&mut self, cnid: CatalogNodeId, item: ArchiveItem<'a>) {
+        let path = item.path.clone();
+        self.unwritten.push(item);
+        for (name, record) in children(self.catalog, cnid) {
+            let mut child_path = path.clone();
+            child_path.push(name);
+            match record {
+                CatalogLeafRecord::File(file)
+                    if self
+                        .filter
+                        .matches(child_path.get(1..).unwrap_or_default(), file) =>
+                {
+                    self.push(ArchiveItem {
+                        path: child_path,
+                        record,
+                    });
+                }
+                CatalogLeafRecord::Folder(folder) if self.visited.insert(folder.folder_id) => {
+                    let child = ArchiveItem {
+                        path: child_path,
+                        record,
+                    };
+                    self.folder(folder.folder_id, child);
+                }
+                _ => {}
+            }
+        }
+        // Still unwritten, so nothing inside was selected.
+        if self.unwritten.last().is_some_and(|last| last.path == path) {
+            let item = self.unwritten.pop().unwrap();
+            if self.filter.includes_path(path.get(1..).unwrap_or_default()) {
+                self.push(item);
+            }
+        }
+    }
+}
+
+/// The parts of a volume that archiving reads, borrowed separately so that
+/// the items can stay borrowed from the catalog.
+pub struct ArchiveSource<'a, R> {
+    pub stream: &'a mut R,
+    /// Regions of the volume whose bytes are replaced with `fill`.
+    pub damage: &'a DamageMap,
+    pub fill: &'a [u8],
+    pub catalog: &'a CatalogMap,
+    pub overflow: &'a [OverflowExtents],
+    pub attributes: &'a [ExtendedAttribute],
+    pub block_size: usize,
+}
+
+impl<R: Read + Seek> ArchiveSource<'_, R> {
+    /// All extents of a fork, including those from overflow records.
+    fn fork_extents(&self, file: &CatalogFile, fork: ForkKind) -> Vec<ExtentDescriptor> {
+        let fork_data = match fork {
+            ForkKind::Data => &file.data_fork,
+            ForkKind::Resource => &file.resource_fork,
+        };
+        fork_data
+            .extents
+            .iter()
+            .copied()
+            .chain(fork_overflow_extents(self.overflow, file.file_id, fork))
+            .collect()
+    }
+
+    fn fork_reader(&mut self, file: &CatalogFile, fork: ForkKind) -> ForkReader<'_, R> {
+        let extents = self.fork_extents(file, fork);
+        let logical_size = match fork {
+            ForkKind::Data => file.data_fork.logical_size,
+            ForkKind::Resource => file.resource_fork.logical_size,
+        };
+        ForkReader::new(self.stream, self.block_size, extents, logical_size)
+            .with_damage(self.damage, self.fill)
+    }
+
+    /// The number of bytes of a fork to archive: its logical size, less
+    /// anything its extents cannot supply.
+    fn fork_length(&mut self, file: &CatalogFile, fork: ForkKind) -> Result<u64, io::Error> {
+        self.fork_reader(file, fork).available()
+    }
+
+    /// Extended attributes of an item. Values that cannot be read are
+    /// reported and left out.
+    fn xattrs(&mut self, record: &CatalogLeafRecord) -> Vec<(String, Vec<u8>)> {
+        let cnid = match record {
+            CatalogLeafRecord::File(file) => file.file_id,
+            CatalogLeafRecord::Folder(folder) => folder.folder_id,
+            _ => return Vec::new(),
+        };
+        let mut xattrs = Vec::new();
+        for attribute in item_attributes(self.attributes, cnid) {
+            match attribute_value(self.stream, self.block_size, self.attributes, attribute) {
+                Ok(value) => xattrs.push((attribute.name(), value)),
+                Err(err) => eprintln!(
+                    "Could not read attribute {} of CNID {cnid}: {err}",
+                    attribute.name()
+                ),
+            }
+        }
+        xattrs
+    }
+}
+
+/// Totals from writing an archive.
+#[derive(Default)]
+pub struct ArchiveSummary {
+    pub files: usize,
+    pub folders: usize,
+    pub links: usize,
+    /// Files whose contents could not be fully read, and were cut short or
+    /// padded.
+    pub incomplete: usize,
+}
+
+/// A path within the archive. Slashes within names are stored as colons, as
+/// macOS shows them.
+fn archive_path(path: &[String]) -> String {
+    path.iter()
+        .map(|component| component.replace('/', ":"))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The owner, mode, and dates of a record.
+struct ItemMetadata<'a> {
+    permissions: &'a BsdInfo,
+    modified: i64,
+    accessed: Option<i64>,
+}
+
+fn item_metadata(record: &CatalogLeafRecord) -> ItemMetadata<'_> {
+    let (permissions, modified, accessed) = match record {
+        CatalogLeafRecord::File(file) => {
+            (&file.permissions, file.content_mod_date, file.access_date)
+        }
+        CatalogLeafRecord::Folder(folder) => (
+            &folder.permissions,
+            folder.content_mod_date,
+            folder.access_date,
+        ),
+        _ => unreachable!("only files and folders are archived"),
+    };
+    ItemMetadata {
+        permissions,
+        modified: modified.to_unix().unwrap_or(0),
+        accessed: accessed.to_unix(),
+    }
+}
+
+/// An entry with the owner, mode, and dates of a record.
+fn tar_entry(path: String, kind: TarEntryKind, record: &CatalogLeafRecord) -> TarEntry {
+    let metadata = item_metadata(record);
+    let default_mode = match kind {
+        TarEntryKind::File | TarEntryKind::HardLink(_) => 0o644,
+        TarEntryKind::Directory | TarEntryKind::Symlink(_) => 0o755,
+    };
+    let mut entry = TarEntry::new(path, kind);
+    // TN1150 specifies that a file type of zero means the permissions were
+    // never set.
+    if metadata.permissions.is_set() {
+        entry.mode = u32::from(metadata.permissions.permission_bits());
+        entry.uid = metadata.permissions.owner_id();
+        entry.gid = metadata.permissions.group_id();
+    } else {
+        entry.mode = default_mode;
+    }
+    entry.modified = metadata.modified;
+    entry.accessed = metadata.accessed;
+    entry
+}
+
+/// An entry with the owner, mode, and dates of a record.
+fn zip_entry(path: String, kind: ZipEntryKind, record: &CatalogLeafRecord) -> ZipEntry {
+    let metadata = item_metadata(record);
+    let default_mode = match kind {
+        ZipEntryKind::File => 0o644,
+        ZipEntryKind::Directory | ZipEntryKind::Symlink(_) => 0o755,
+    };
+    let mut entry = ZipEntry::new(path, kind);
+    if metadata.permissions.is_set() {
+        entry.mode = u32::from(metadata.permissions.permission_bits());
+        entry.uid = metadata.permissions.owner_id();
+        entry.gid = metadata.permissions.group_id();
+    } else {
+        entry.mode = default_mode;
+    }
+    entry.modified = metadata.modified;
+    entry.accessed = metadata.accessed;
+    entry
+}
+
+/// Write items to a pax archive: folders, files with their data forks,
+/// symbolic links, and hard links to the first path of each inode. Extended
+/// attributes are stored as `SCHILY.xattr` records, and Finder info and
+/// resource forks in AppleDouble `._` entries before their items.
+pub fn write_tar<R: Read + Seek>(
+    source: &mut ArchiveSource<'_, R>,
+    items: &[ArchiveItem],
+    output: impl Write,
+    verbose: bool,
+) -> Result<ArchiveSummary, io::Error> {
+    let mut tar = TarWriter::new(output);
+    let mut summary = ArchiveSummary::default();
+    // Archive paths of hard links already written, by the CNID of their
+    // indirect node file.
+    let mut inodes = HashMap::<CatalogNodeId, String>::new();
+
+    for item in items {
+        let path = archive_path(&item.path);
+        if verbose {
+            eprintln!("{path}");
+        }
+        let file = match item.record {
+            CatalogLeafRecord::Folder(_) => {
+                let mut entry = tar_entry(path, TarEntryKind::Directory, item.record);
+                entry.xattrs = source.xattrs(item.record);
+                write_appledouble(&mut tar, source, &item.path, item.record)?;
+                tar.append(&entry, &mut io::empty())?;
+                summary.folders += 1;
+                continue;
+            }
+            CatalogLeafRecord::File(file) => file,
+            _ => continue,
+        };
+
+        if file.is_symlink() {
+            let target = read_symlink_target(source.stream, source.block_size, file)?;
+            let entry = tar_entry(path, TarEntryKind::Symlink(target), item.record);
+            tar.append(&entry, &mut io::empty())?;
+            summary.links += 1;
+            continue;
+        }
+
+        // Hard links take their contents and metadata from the indirect
+        // node file. Links whose node is missing are archived as they are.
+        let mut record = item.record;
+        if let Some(target) = hard_link_target(source.catalog, file) {
+            if let Some(first) = inodes.get(&target.file_id) {
+                let kind = TarEntryKind::HardLink(first.clone());
+                let entry = tar_entry(path, kind, item.record);
+                tar.append(&entry, &mut io::empty())?;
+                summary.links += 1;
+                continue;
+            }
+            inodes.insert(target.file_id, path.clone());
+            record = catalog::record_for_cnid(source.catalog, target.file_id).unwrap_or(record);
+        }
+        let CatalogLeafRecord::File(file) = record else {
+            continue;
+        };
+
+        let mut entry = tar_entry(path, TarEntryKind::File, record);
+        entry.size = source.fork_length(file, ForkKind::Data)?;
+        entry.xattrs = source.xattrs(record);
+        write_appledouble(&mut tar, source, &item.path, record)?;
+        let mut reader = source.fork_reader(file, ForkKind::Data);
+        let (copied, error) = tar.append(&entry, &mut reader)?;
+        let undamaged = check_damaged(&entry.path, reader.damaged_bytes());
+        let whole_fork = check_fork_length(&entry.path, file.data_fork.logical_size, entry.size);
+        if !check_copied(&entry.path, entry.size, copied, error) || !whole_fork || !undamaged {
+            summary.incomplete += 1;
+        }
+        summary.files += 1;
+    }
+    tar.finish()?;
+    Ok(summary)
+}
+
+/// Report a fork whose extents cannot supply its logical size. Returns
+/// whether the whole fork is archived.
+fn check_fork_length(path: &str, logical_size: u64, length: u64) -> bool {
+    if length < logical_size {
+        eprintln!(
+            "Could not read all of {path}: its extents end {} bytes before its logical size; archived without them",
+            logical_size - length
+        );
+    }
+    length == logical_size
+}
+
+/// Report an entry whose data ended early, and was padded. Returns whether
+/// all of it was copied.
+fn check_copied(path: &str, size: u64, copied: u64, error: Option<io::Error>) -> bool {
+    if copied < size {
+        let reason = error.map_or_else(|| "data ends early".to_string(), |err| err.to_string());
+        eprintln!(
+            "Could not read all of {path}: {reason}; {} bytes padded with zeroes",
+            size - copied
+        );
+    }
+    copied == size
+}
+
+/// Report an entry whose data was read from damaged regions of the volume.
+/// Returns whether none of it was.
+fn check_damaged(path: &str, damaged_bytes: u64) -> bool {
+    if damaged_bytes != 0 {
+        eprintln!("{path} has {damaged_bytes} damaged bytes, replaced with the fill pattern");
+    }
+    damaged_bytes == 0
+}
+
+/// An AppleDouble entry, whose data is the header followed by the resource
+/// fork.
+struct AppleDoubleEntry {
+    path: Vec<String>,
+    header: Vec<u8>,
+    resource_size: u64,
+}
+
+/// The AppleDouble entry for an item, if it has Finder info or a resource
+/// fork.
+fn appledouble_entry<R: Read + Seek>(
+    source: &mut ArchiveSource<'_, R>,
+    path: &[String],
+    record: &CatalogLeafRecord,
+) -> Result<Option<AppleDoubleEntry>, io::Error> {
+    let Some(info) = finder_info(record).filter(|_| needs_appledouble(record)) else {
+        return Ok(None);
+    };
+    let Some((name, parent)) = path.split_last() else {
+        return Ok(None);
+    };
+    let mut appledouble_path = parent.to_vec();
+    appledouble_path.push(appledouble_name(name));
+
+    let resource_size = match record {
+        CatalogLeafRecord::File(file) => {
+            let length = source.fork_length(file, ForkKind::Resource)?;
+            check_fork_length(
+                &archive_path(&appledouble_path),
+                file.resource_fork.logical_size,
+                length,
+            );
+            length
+        }
+        _ => 0,
+    };
+    let resource_length = u32::try_from(resource_size).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "The resource fork of {} is too large for AppleDouble",
+                archive_path(path)
+            ),
+        )
+    })?;
+    let header = appledouble_header(&info, resource_length);
+    Ok(Some(AppleDoubleEntry {
+        path: appledouble_path,
+        header,
+        resource_size,
+    }))
+}
+
+/// Write the AppleDouble entry for an item, if it has Finder info or a
+/// resource fork.
+fn write_appledouble<R: Read + Seek, W: Write>(
+    tar: &mut TarWriter<W>,
+    source: &mut ArchiveSource<'_, R>,
+    path: &[String],
+    record: &CatalogLeafRecord,
+) -> Result<(), io::Error> {
+    let Some(AppleDoubleEntry {
+        path,
+        header,
+        resource_size,
+    }) = appledouble_entry(source, path, record)?
+    else {
+        return Ok(());
+    };
+    let mut entry = tar_entry(archive_path(&path), TarEntryKind::File, record);
+    entry.size = header.len() as u64 + resource_size;
+    entry.accessed = None;
+    let (copied, error) = match record {
+        CatalogLeafRecord::File(file) => {
+            let resource_fork = source.fork_reader(file, ForkKind::Resource);
+            tar.append(&entry, &mut header.as_slice().chain(resource_fork))?
+        }
+        _ => tar.append(&entry, &mut header.as_slice())?,
+    };
+    check_copied(&entry.path, entry.size, copied, error);
+    Ok(())
+}
+
+/// Write items to a ZIP archive, as macOS Archive Utility writes them:
+/// folders, files with their data forks, and symbolic links, with Finder
+/// info and resource forks in AppleDouble entries under `__MACOSX`. ZIP has
+/// no hard links, so each path of a hard link gets a copy of its contents.
+/// Extended attributes are not kept.
+pub fn write_zip<R: Read + Seek>(
+    source: &mut ArchiveSource<'_, R>,
+    items: &[ArchiveItem],
+    output: impl Write + Seek,
+    verbose: bool,
+) -> Result<ArchiveSummary, io::Error> {
+    let mut zip = ZipWriter::new(output)?;
+    let mut summary = ArchiveSummary::default();
+
+    for item in items {
+        let path = archive_path(&item.path);
+        if verbose {
+            eprintln!("{path}");
+        }
+        let file = match item.record {
+            CatalogLeafRecord::Folder(_) => {
+                let entry = zip_entry(path, ZipEntryKind::Directory, item.record);
+                zip.append(&entry, &mut io::empty())?;
+                write_macosx_appledouble(&mut zip, source, &item.path, item.record)?;
+                summary.folders += 1;
+                continue;
+            }
+            CatalogLeafRecord::File(file) => file,
+            _ => continue,
+        };
+
+        if file.is_symlink() {
+            let target = read_symlink_target(source.stream, source.block_size, file)?;
+            let entry = zip_entry(path, ZipEntryKind::Symlink(target), item.record);
+            zip.append(&entry, &mut io::empty())?;
+            summary.links += 1;
+            continue;
+        }
+
+        // Hard links take their contents and metadata from the indirect
+        // node file. Links whose node is missing are archived as they are.
+        let record = hard_link_target(source.catalog, file)
+            .and_then(|target| catalog::record_for_cnid(source.catalog, target.file_id))
+            .unwrap_or(item.record);
+        let CatalogLeafRecord::File(file) = record else {
+            continue;
+        };
+
+        let mut entry = zip_entry(path, ZipEntryKind::File, record);
+        entry.size = source.fork_length(file, ForkKind::Data)?;
+        let mut reader = source.fork_reader(file, ForkKind::Data);
+        let (copied, error) = zip.append(&entry, &mut reader)?;
+        let undamaged = check_damaged(&entry.path, reader.damaged_bytes());
+        let whole_fork = check_fork_length(&entry.path, file.data_fork.logical_size, entry.size);
+        if !check_copied(&entry.path, entry.size, copied, error) || !whole_fork || !undamaged {
+            summary.incomplete += 1;
+        }
+        write_macosx_appledouble(&mut zip, source, &item.path, record)?;
+        summary.files += 1;
+    }
+    zip.finish()?;
+    Ok(summary)
+}
+
+/// Write the AppleDouble entry for an item under `__MACOSX`, if it has
+/// Finder info or a resource fork.
+fn write_macosx_appledouble<R: Read + Seek, W: Write + Seek>(
+    zip: &mut ZipWriter<W>,
+    source: &mut ArchiveSource<'_, R>,
+    path: &[String],
+    record: &CatalogLeafRecord,
+) -> Result<(), io::Error> {
+    let Some(AppleDoubleEntry {
+        mut path,
+        header,
+        resource_size,
+    }) = appledouble_entry(source, path, record)?
+    else {
+        return Ok(());
+    };
+    path.insert(0, "__MACOSX".to_string());
+    let mut entry = zip_entry(archive_path(&path), ZipEntryKind::File, record);
+    entry.size = header.len() as u64 + resource_size;
+    entry.accessed = None;
+    let (copied, error) = match record {
+        CatalogLeafRecord::File(file) => {
+            let resource_fork = source.fork_reader(file, ForkKind::Resource);
+            zip.append(&entry, &mut header.as_slice().chain(resource_fork))?
+        }
+        _ => zip.append(&entry, &mut header.as_slice())?,
+    };
+    check_copied(&entry.path, entry.size, copied, error);
+    Ok(())
+}
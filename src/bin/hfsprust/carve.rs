@@ -0,0 +1,90 @@
+//! The `carve` command, which recovers files of known types from unallocated
+//! blocks by their signatures.
+
+use crate::{CatalogArgs, ImageArgs, volume_path};
+use clap::Args;
+use hfsprust::allocation::{AllocationBitmap, BlockOwnership};
+use hfsprust::carve::{DEFAULT_MAX_CARVE_SIZE, carve_free_space, write_carved_file};
+use hfsprust::recovery::orphan_path;
+use hfsprust::*;
+use std::fs::{self, File};
+use std::io;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+#[derive(Args)]
+pub struct CarveArgs {
+    #[command(flatten)]
+    image: ImageArgs,
+    #[command(flatten)]
+    catalog: CatalogArgs,
+
+    /// Directory to write carved files to.
+    output: PathBuf,
+
+    /// Largest file to carve, in bytes. Files without a recognisable end are
+    /// cut off here.
+    #[arg(long, value_name = "BYTES", default_value_t = DEFAULT_MAX_CARVE_SIZE)]
+    max_size: u64,
+}
+
+pub fn carve(args: CarveArgs, verbose: bool) -> Result<ExitCode, io::Error> {
+    let mut volume = args.image.open_volume(&args.catalog, verbose)?;
+    let block_size = volume.block_size();
+    let bitmap = AllocationBitmap::read(&mut volume.stream, &volume.header, &volume.overflow)?;
+    let carved = carve_free_space(&mut volume.stream, block_size, &bitmap, args.max_size)?;
+
+    // Matches are labelled with the live files that overwrote their blocks,
+    // and with any deleted record that started at the same block.
+    let mut ownership = BlockOwnership::from_catalog(&volume.header, &volume.catalog);
+    ownership.add_overflow_extents(&volume.overflow);
+    let (orphans, _) = volume.orphans()?;
+
+    fs::create_dir_all(&args.output)?;
+    let mut failed = 0;
+    for file in &carved {
+        let start_block = file.start_block(block_size);
+        let suffix = if file.complete { "" } else { "-partial" };
+        let output_path = args.output.join(format!(
+            "f{start_block:010}{suffix}.{}",
+            file.kind.extension()
+        ));
+        let owners = file
+            .owners(block_size, &ownership)
+            .into_iter()
+            .map(|cnid| volume_path(&volume.path(cnid)))
+            .collect::<Vec<_>>();
+        let deleted = orphans
+            .iter()
+            .filter(|orphan| match &orphan.leaf {
+                CatalogLeafRecord::File(file_record) => {
+                    file_record.data_fork.extents[0].start_block == start_block
+                }
+                _ => false,
+            })
+            .map(|orphan| orphan_path(&volume.catalog, &orphans, orphan).join("/"))
+            .collect::<Vec<_>>();
+        println!(
+            "{} {:?} block {start_block} size={} complete={} overwritten by {owners:?} deleted {deleted:?}",
+            output_path.display(),
+            file.kind,
+            file.length,
+            file.complete
+        );
+
+        // Carving finds the same files each time, so a rerun rewrites them.
+        let result = File::create(&output_path).and_then(|mut output_file| {
+            write_carved_file(&mut volume.stream, file, &mut output_file)
+        });
+        if let Err(err) = result {
+            eprintln!("Could not write {}: {err}", output_path.display());
+            failed += 1;
+        }
+    }
+
+    eprintln!("Carved {} files from free space.", carved.len() - failed);
+    Ok(match failed {
+        0 => ExitCode::SUCCESS,
+        _ => ExitCode::from(1),
+    })
+}
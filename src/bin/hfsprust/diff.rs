@@ -0,0 +1,109 @@
+//! The `diff` command, which compares a volume with another image of it, or
+//! with a directory it was extracted to.
+
+use crate::{CatalogArgs, DamageArgs, ImageArgs};
+use clap::{Args, ValueEnum};
+use hfsprust::diff::{DiffOptions, DiffSource, diff as diff_sources};
+use hfsprust::filter::Filter;
+use hfsprust::fork::HashAlgorithm;
+use hfsprust::partition::Slice;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+#[derive(Args)]
+pub struct DiffArgs {
+    #[command(flatten)]
+    image: ImageArgs,
+    #[command(flatten)]
+    catalog: CatalogArgs,
+
+    /// Image to compare with, or a directory holding the contents of the
+    /// volume's root folder as written by `extract`, such as
+    /// `recovered/Macintosh HD`.
+    other: PathBuf,
+
+    /// Byte offset of the volume within the second image.
+    #[arg(long, value_name = "BYTES", conflicts_with = "other_partition")]
+    other_offset: Option<u64>,
+
+    /// Partition holding the volume in the second image, numbered from 1.
+    #[arg(long, value_name = "NUMBER")]
+    other_partition: Option<usize>,
+
+    /// Match items only by path, not by CNID. Use this for volumes that
+    /// were copied rather than imaged from the same disk.
+    #[arg(long)]
+    no_cnid: bool,
+
+    /// Digest for comparing contents: md5, sha1, or sha256.
+    #[arg(long, value_name = "ALGORITHM", default_value_t = HashAlgorithm::Sha256)]
+    hash: HashAlgorithm,
+
+    /// Compare only sizes and dates, without reading contents.
+    #[arg(long)]
+    no_hash: bool,
+
+    /// Also compare the file system's own files at the root of the volume,
+    /// such as `.journal` and `.Spotlight-V100`.
+    #[arg(long)]
+    no_default_excludes: bool,
+
+    /// `text` writes one tab-separated line per change, and `jsonl` one
+    /// JSON object per change.
+    #[arg(long, value_enum, default_value_t = DiffFormat::Text)]
+    format: DiffFormat,
+
+    #[command(flatten)]
+    damage: DamageArgs,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum DiffFormat {
+    Text,
+    Jsonl,
+}
+
+pub fn diff(args: DiffArgs, verbose: bool) -> Result<ExitCode, io::Error> {
+    let mut volume = args.image.open_volume(&args.catalog, verbose)?;
+    args.damage.apply(&mut volume)?;
+    let options = DiffOptions {
+        filter: match args.no_default_excludes {
+            true => Filter::default(),
+            false => Filter::system_files(),
+        },
+        match_cnids: !args.no_cnid,
+        hash: (!args.no_hash).then_some(args.hash),
+    };
+
+    let mut left = DiffSource::Volume(&mut volume);
+    let differences = if args.other.is_dir() {
+        let mut right = DiffSource::<Slice<File>>::Directory(&args.other);
+        diff_sources(&mut left, &mut right, &options)?
+    } else {
+        let other_image = ImageArgs {
+            image: args.other.clone(),
+            offset: args.other_offset,
+            partition: args.other_partition,
+        };
+        let mut other = other_image.open_volume(&args.catalog, verbose)?;
+        diff_sources(&mut left, &mut DiffSource::Volume(&mut other), &options)?
+    };
+
+    let mut stdout = io::BufWriter::new(io::stdout().lock());
+    for difference in &differences {
+        match args.format {
+            DiffFormat::Text => writeln!(stdout, "{difference}")?,
+            DiffFormat::Jsonl => {
+                serde_json::to_writer(&mut stdout, difference)?;
+                writeln!(stdout)?;
+            }
+        }
+    }
+    stdout.flush()?;
+    Ok(match differences.is_empty() {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::from(1),
+    })
+}
@@ -0,0 +1,768 @@
+//! The `extract` command, which copies files and folders out of the volume
+//! along with their metadata.
+
+use crate::archive::{ArchiveSource, ArchiveSummary, archive_items, write_tar, write_zip};
+use crate::{CatalogArgs, DamageArgs, ImageArgs, ImageVolume, lookup, volume_path};
+use clap::Args;
+use hfsprust::allocation::{BlockOwnership, ForkKind};
+use hfsprust::catalog::{hard_link_target, read_symlink_target};
+use hfsprust::damage::fork_damage;
+use hfsprust::extents::fork_overflow_extents;
+use hfsprust::extract::{MetadataOptions, apply_file_metadata, apply_folder_metadata};
+use hfsprust::filter::{Filter, PathPattern, parse_cnid_range, parse_date, parse_os_type};
+use hfsprust::fork::{CopyOptions, HashAlgorithm, copy_file_data_from_extents, hash_reader};
+use hfsprust::recovery::{conflicting_extents, orphan_path};
+use hfsprust::report::{ExtractedFile, ExtractionReport, ReportFormat};
+use hfsprust::resume::ExtractionState;
+use hfsprust::*;
+use itertools::Itertools;
+use rustix::fs::OFlags;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, Write};
+use std::ops::RangeInclusive;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt, symlink};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+#[derive(Args)]
+pub struct ExtractArgs {
+    #[command(flatten)]
+    image: ImageArgs,
+    #[command(flatten)]
+    catalog: CatalogArgs,
+
+    /// Directory to write to. Files are written under a folder named after
+    /// the volume. Not given with `--to-tar` or `--to-zip`.
+    #[arg(required_unless_present_any = ["to_tar", "to_zip"])]
+    output: Option<PathBuf>,
+
+    /// Folder or file within the volume to extract. Defaults to the root
+    /// folder.
+    path: Option<String>,
+
+    /// Write a pax archive to a file, or to standard output for `-`,
+    /// instead of to a directory. Extended attributes are stored as
+    /// `SCHILY.xattr` records, and Finder info and resource forks as
+    /// AppleDouble `._` entries.
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = [
+            "preserve_owner",
+            "preserve_flags",
+            "no_creation_xattr",
+            "recover_deleted",
+            "report",
+            "state",
+        ]
+    )]
+    to_tar: Option<PathBuf>,
+
+    /// Write a ZIP archive instead of to a directory, which macOS Archive
+    /// Utility expands with Finder info and resource forks restored from
+    /// `__MACOSX/._` entries. Hard links are stored as copies, and extended
+    /// attributes are not kept.
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = [
+            "to_tar",
+            "preserve_owner",
+            "preserve_flags",
+            "no_creation_xattr",
+            "recover_deleted",
+            "report",
+            "state",
+        ]
+    )]
+    to_zip: Option<PathBuf>,
+
+    /// Restore the owner and group. Usually needs root.
+    #[arg(long)]
+    preserve_owner: bool,
+
+    /// Restore BSD flags such as `uchg`.
+    #[arg(long)]
+    preserve_flags: bool,
+
+    /// Do not record creation dates in an extended attribute.
+    #[arg(long)]
+    no_creation_xattr: bool,
+
+    /// Also extract deleted files whose blocks have not been reused, under
+    /// `Possibly Deleted`.
+    #[arg(long)]
+    recover_deleted: bool,
+
+    /// Hash recorded for each file in the report: md5, sha1, or sha256.
+    #[arg(long, value_name = "ALGORITHM", default_value = "sha256")]
+    hash: HashAlgorithm,
+
+    /// Where to write the extraction report. Defaults to
+    /// `OUTPUT.extraction-report.jsonl` or `.csv` beside the output
+    /// directory.
+    #[arg(long, value_name = "FILE")]
+    report: Option<PathBuf>,
+
+    /// Format of the extraction report: jsonl or csv.
+    #[arg(long, value_name = "FORMAT", default_value = "jsonl")]
+    report_format: ReportFormat,
+
+    /// State journal recording completed files, so that a rerun skips them.
+    /// Defaults to `OUTPUT.extraction-state.tsv` beside the output directory.
+    #[arg(long, value_name = "FILE")]
+    state: Option<PathBuf>,
+
+    // Options under their own headings come last, so that the options above
+    // are not listed under them.
+    #[command(flatten)]
+    filter: FilterArgs,
+    #[command(flatten)]
+    damage: DamageArgs,
+}
+
+/// Which files to extract.
+#[derive(Args)]
+#[command(next_help_heading = "Filters")]
+struct FilterArgs {
+    /// Only extract paths matching a glob, and the contents of matching
+    /// folders. Globs without a `/` match names in any folder.
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+
+    /// Skip paths matching a glob, and the contents of matching folders.
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// Only extract paths matching a regular expression.
+    #[arg(long, value_name = "REGEX")]
+    include_regex: Vec<String>,
+
+    /// Skip paths matching a regular expression.
+    #[arg(long, value_name = "REGEX")]
+    exclude_regex: Vec<String>,
+
+    /// Also extract the file system's own files at the root of the volume,
+    /// such as `.journal` and `.Spotlight-V100`.
+    #[arg(long)]
+    no_default_excludes: bool,
+
+    /// Smallest data fork to extract, in bytes.
+    #[arg(long, value_name = "BYTES")]
+    min_size: Option<u64>,
+
+    /// Largest data fork to extract, in bytes.
+    #[arg(long, value_name = "BYTES")]
+    max_size: Option<u64>,
+
+    /// Only files modified at or after a date in UTC, as
+    /// `YYYY-MM-DD[THH:MM:SS]`.
+    #[arg(long, value_name = "DATE", value_parser = parse_date)]
+    modified_after: Option<Date>,
+
+    /// Only files modified at or before a date in UTC.
+    #[arg(long, value_name = "DATE", value_parser = parse_date)]
+    modified_before: Option<Date>,
+
+    /// Only files with a CNID, or in a range such as `100-200`.
+    #[arg(long, value_name = "CNID", value_parser = parse_cnid_range)]
+    cnid: Vec<RangeInclusive<CatalogNodeId>>,
+
+    /// Only files with a Finder type, such as `TEXT`.
+    #[arg(long, value_name = "CODE", value_parser = parse_os_type)]
+    file_type: Vec<OSType>,
+
+    /// Only files with a Finder creator, such as `ttxt`.
+    #[arg(long, value_name = "CODE", value_parser = parse_os_type)]
+    creator: Vec<OSType>,
+}
+
+impl FilterArgs {
+    fn filter(self) -> Result<Filter, io::Error> {
+        let mut filter = match self.no_default_excludes {
+            true => Filter::default(),
+            false => Filter::system_files(),
+        };
+        for pattern in &self.include {
+            filter.include.push(PathPattern::glob(pattern)?);
+        }
+        for pattern in &self.include_regex {
+            filter.include.push(PathPattern::regex(pattern)?);
+        }
+        for pattern in &self.exclude {
+            filter.exclude.push(PathPattern::glob(pattern)?);
+        }
+        for pattern in &self.exclude_regex {
+            filter.exclude.push(PathPattern::regex(pattern)?);
+        }
+        filter.min_size = self.min_size;
+        filter.max_size = self.max_size;
+        filter.modified_after = self.modified_after;
+        filter.modified_before = self.modified_before;
+        filter.cnids = self.cnid;
+        filter.file_types = self.file_type;
+        filter.creators = self.creator;
+        Ok(filter)
+    }
+}
+
+/// Totals printed when the extraction finishes.
+#[derive(Default)]
+struct Summary {
+    complete: usize,
+    resumed: usize,
+    incomplete: usize,
+    failed: usize,
+}
+
+impl Summary {
+    fn add(&mut self, file: &ExtractedFile, resumed: bool) {
+        if !file.errors.is_empty() {
+            self.failed += 1;
+        } else if !file.is_complete() {
+            self.incomplete += 1;
+        } else if resumed {
+            self.resumed += 1;
+        } else {
+            self.complete += 1;
+        }
+    }
+}
+
+pub fn extract(args: ExtractArgs, verbose: bool) -> Result<ExitCode, io::Error> {
+    let metadata_options = MetadataOptions {
+        ownership: args.preserve_owner,
+        bsd_flags: args.preserve_flags,
+        creation_date_xattr: !args.no_creation_xattr,
+    };
+    let filter = args.filter.filter()?;
+
+    let mut volume = args.image.open_volume(&args.catalog, verbose)?;
+    args.damage.apply(&mut volume)?;
+    let archive = match (&args.to_tar, &args.to_zip) {
+        (Some(to_tar), _) => Some((ArchiveFormat::Tar, to_tar)),
+        (None, Some(to_zip)) => Some((ArchiveFormat::Zip, to_zip)),
+        (None, None) => None,
+    };
+    if let Some((format, archive_path)) = archive {
+        // The only positional argument after the image is the path within
+        // the volume.
+        let path = match (&args.output, &args.path) {
+            (Some(path), None) => path.to_string_lossy().into_owned(),
+            (None, _) => args.path.clone().unwrap_or_else(|| "/".to_string()),
+            (Some(_), Some(_)) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} takes no output directory", format.flag()),
+                ));
+            }
+        };
+        return extract_to_archive(&mut volume, &path, &filter, format, archive_path, verbose);
+    }
+    let path = args.path.as_deref().unwrap_or("/");
+    let block_size = volume.block_size();
+    let copy_options = CopyOptions {
+        damage: volume.damage.clone(),
+        fill: volume.fill.clone(),
+        hash: args.hash,
+    };
+    let start = lookup(&mut volume, path, false)?;
+    let subtree = volume.path(start);
+
+    let output_root = args.output.expect("required without --to-tar");
+    fs::create_dir_all(&output_root)?;
+    // Every copied file is recorded in the report, including failures.
+    // The report and journal are kept out of the output directory, so that
+    // it only holds the volume's contents.
+    let report_path = match args.report {
+        Some(path) => path,
+        None => beside(
+            &output_root,
+            match args.report_format {
+                ReportFormat::Jsonl => "extraction-report.jsonl",
+                ReportFormat::Csv => "extraction-report.csv",
+            },
+        )?,
+    };
+    let mut report = ExtractionReport::new(File::create(&report_path)?, args.report_format)?;
+    let state_path = match args.state {
+        Some(path) => path,
+        None => beside(&output_root, "extraction-state.tsv")?,
+    };
+    let mut state = ExtractionState::open(&state_path, &output_root)?;
+    if !state.is_empty() {
+        eprintln!(
+            "Resuming from {}: {} files already extracted",
+            state_path.display(),
+            state.len()
+        );
+    }
+
+    let mut summary = Summary::default();
+    // A failure only affects its own file. It is reported, and retried by
+    // the next run.
+    let files = volume
+        .catalog
+        .values()
+        .filter_map(|record| match record {
+            CatalogLeafRecord::File(file_record) => Some(file_record),
+            _ => None,
+        })
+        .map(|file_record| (volume.path(file_record.file_id), file_record))
+        .filter(|(path, _)| path.starts_with(&subtree))
+        .collect_vec();
+    for (path, file_record) in files {
+        // The first component is the volume name.
+        if !filter.matches(path.get(1..).unwrap_or_default(), file_record) {
+            if verbose {
+                eprintln!("Skipping {}", volume_path(&path));
+            }
+            continue;
+        }
+        // Hard links take their contents and metadata from the indirect node
+        // file, and are written as copies of it. Links whose node is missing
+        // are extracted as they are.
+        let link_cnid = file_record.file_id;
+        let file_record = hard_link_target(&volume.catalog, file_record).unwrap_or(file_record);
+        let overflow_extents =
+            fork_overflow_extents(&volume.overflow, file_record.file_id, ForkKind::Data);
+        let output_path = match output_path(&output_root, &path) {
+            Ok(output_path) => output_path,
+            Err(err) => {
+                let mut extracted = ExtractedFile::new(
+                    path,
+                    file_record,
+                    &overflow_extents,
+                    PathBuf::new(),
+                    copy_options.hash,
+                );
+                extracted.cnid = link_cnid;
+                finish_file(&mut extracted, &Err(err), file_record, &metadata_options);
+                summary.add(&extracted, false);
+                report.write(&extracted)?;
+                state.record(&extracted)?;
+                continue;
+            }
+        };
+        let mut extracted = ExtractedFile::new(
+            path.clone(),
+            file_record,
+            &overflow_extents,
+            output_path,
+            copy_options.hash,
+        );
+        extracted.cnid = link_cnid;
+
+        // Recreate symbolic links rather than writing out the target path.
+        // The target is what is hashed, as it is the link's data fork.
+        if file_record.is_symlink() {
+            let result = create_parent_dir(&output_root, &extracted.output_path)
+                .and_then(|_| read_symlink_target(&mut volume.stream, block_size, file_record))
+                .and_then(|target| {
+                    replace_symlink(&target, &extracted.output_path)?;
+                    let (size, hash) = hash_reader(&mut target.as_bytes(), copy_options.hash)?;
+                    extracted.bytes_written = size;
+                    extracted.hash = Some(hash);
+                    Ok(false)
+                });
+            finish_file(&mut extracted, &result, file_record, &metadata_options);
+            summary.add(&extracted, false);
+            report.write(&extracted)?;
+            state.record(&extracted)?;
+            continue;
+        }
+
+        if verbose {
+            eprintln!(
+                "{} size={}",
+                volume_path(&path),
+                file_record.data_fork.logical_size
+            );
+            let extents = file_record
+                .data_fork
+                .extents
+                .iter()
+                .chain(&overflow_extents)
+                .copied()
+                .collect_vec();
+            let damaged = fork_damage(
+                &copy_options.damage,
+                block_size,
+                &extents,
+                file_record.data_fork.logical_size,
+            );
+            for extent_damage in &damaged.extents {
+                eprintln!(
+                    "\tExtent {} at block {} has {} damaged bytes",
+                    extent_damage.index,
+                    extent_damage.extent.start_block,
+                    extent_damage.damaged_bytes
+                );
+            }
+        }
+
+        let resumed = create_parent_dir(&output_root, &extracted.output_path).and_then(|_| {
+            copy_file(
+                &mut volume.stream,
+                block_size,
+                file_record,
+                overflow_extents,
+                &copy_options,
+                &mut state,
+                &mut extracted,
+            )
+        });
+        finish_file(&mut extracted, &resumed, file_record, &metadata_options);
+        summary.add(&extracted, matches!(resumed, Ok(true)));
+        report.write(&extracted)?;
+        state.record(&extracted)?;
+    }
+
+    // Deleted files go in their own tree, as their paths may collide with
+    // live files. Only those whose blocks are not claimed by live items are
+    // extracted. Failures are expected, and do not stop the extraction.
+    if args.recover_deleted {
+        let (orphans, _) = volume.orphans()?;
+        let mut ownership = BlockOwnership::from_catalog(&volume.header, &volume.catalog);
+        ownership.add_overflow_extents(&volume.overflow);
+        let deleted_root = output_root.join("Possibly Deleted");
+        for orphan in &orphans {
+            let CatalogLeafRecord::File(file_record) = &orphan.leaf else {
+                continue;
+            };
+            let path = orphan_path(&volume.catalog, &orphans, orphan);
+            if file_record.is_symlink()
+                || !path.starts_with(&subtree)
+                || !filter.matches(path.get(1..).unwrap_or_default(), file_record)
+                || !conflicting_extents(file_record, &ownership).is_empty()
+            {
+                continue;
+            }
+            let output_path = match output_path(&deleted_root, &path) {
+                Ok(output_path) => output_path,
+                Err(err) => {
+                    let mut extracted = ExtractedFile::new(
+                        path,
+                        file_record,
+                        &[],
+                        PathBuf::new(),
+                        copy_options.hash,
+                    );
+                    extracted.deleted = true;
+                    finish_file(&mut extracted, &Err(err), file_record, &metadata_options);
+                    summary.add(&extracted, false);
+                    report.write(&extracted)?;
+                    state.record(&extracted)?;
+                    continue;
+                }
+            };
+            if verbose {
+                eprintln!(
+                    "Recovering {} size={}",
+                    path.join("/"),
+                    file_record.data_fork.logical_size
+                );
+            }
+            let mut extracted =
+                ExtractedFile::new(path, file_record, &[], output_path, copy_options.hash);
+            extracted.deleted = true;
+
+            let resumed = create_parent_dir(&output_root, &extracted.output_path).and_then(|_| {
+                copy_file(
+                    &mut volume.stream,
+                    block_size,
+                    file_record,
+                    Vec::new(),
+                    &copy_options,
+                    &mut state,
+                    &mut extracted,
+                )
+            });
+            finish_file(&mut extracted, &resumed, file_record, &metadata_options);
+            summary.add(&extracted, matches!(resumed, Ok(true)));
+            report.write(&extracted)?;
+            state.record(&extracted)?;
+        }
+    }
+
+    // Apply folder metadata once their contents are written, deepest first so
+    // that restoring a child does not disturb its parent's modification time.
+    let folders = volume
+        .catalog
+        .values()
+        .filter_map(|record| match record {
+            CatalogLeafRecord::Folder(folder_record) => Some(folder_record),
+            _ => None,
+        })
+        .filter_map(|folder_record| {
+            let output_path =
+                output_path(&output_root, &volume.path(folder_record.folder_id)).ok()?;
+            Some((output_path, folder_record))
+        })
+        .filter(|(output_path, _)| is_folder_below(&output_root, output_path))
+        .sorted_by_key(|(output_path, _)| std::cmp::Reverse(output_path.components().count()));
+    for (output_path, folder_record) in folders {
+        if let Err(err) = apply_folder_metadata(&output_path, folder_record, &metadata_options) {
+            eprintln!(
+                "Could not apply metadata to {}: {err}",
+                output_path.display()
+            );
+        }
+    }
+
+    eprintln!(
+        "Extracted {} files ({} already extracted), {} incomplete, {} failed. Report: {}",
+        summary.complete + summary.resumed,
+        summary.resumed,
+        summary.incomplete,
+        summary.failed,
+        report_path.display()
+    );
+    Ok(match summary.incomplete + summary.failed {
+        0 => ExitCode::SUCCESS,
+        _ => ExitCode::from(1),
+    })
+}
+
+#[derive(Clone, Copy)]
+enum ArchiveFormat {
+    Tar,
+    Zip,
+}
+
+impl ArchiveFormat {
+    fn flag(self) -> &'static str {
+        match self {
+            Self::Tar => "--to-tar",
+            Self::Zip => "--to-zip",
+        }
+    }
+}
+
+/// Write the selected items to an archive instead of a directory.
+fn extract_to_archive(
+    volume: &mut ImageVolume,
+    path: &str,
+    filter: &Filter,
+    format: ArchiveFormat,
+    archive_path: &Path,
+    verbose: bool,
+) -> Result<ExitCode, io::Error> {
+    let start = lookup(volume, path, false)?;
+    let start_path = volume.path(start);
+    let to_stdout = archive_path.as_os_str() == "-";
+    let items = archive_items(&volume.catalog, start, start_path, filter);
+    let mut source = ArchiveSource {
+        stream: &mut volume.stream,
+        damage: &volume.damage,
+        fill: &volume.fill,
+        catalog: &volume.catalog,
+        overflow: &volume.overflow,
+        attributes: &volume.attributes,
+        block_size: volume.header.block_size as usize,
+    };
+    let summary: ArchiveSummary = match format {
+        ArchiveFormat::Tar => {
+            let output: Box<dyn Write> = match to_stdout {
+                true => Box::new(io::stdout().lock()),
+                false => Box::new(File::create(archive_path)?),
+            };
+            write_tar(&mut source, &items, io::BufWriter::new(output), verbose)?
+        }
+        // Each entry's CRC is written after its data, which needs a file.
+        ArchiveFormat::Zip if to_stdout => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--to-zip needs a file to write to, not standard output",
+            ));
+        }
+        ArchiveFormat::Zip => {
+            let output = File::create(archive_path)?;
+            write_zip(&mut source, &items, io::BufWriter::new(output), verbose)?
+        }
+    };
+
+    eprintln!(
+        "Archived {} files, {} folders, and {} links; {} incomplete",
+        summary.files, summary.folders, summary.links, summary.incomplete
+    );
+    Ok(match summary.incomplete {
+        0 => ExitCode::SUCCESS,
+        _ => ExitCode::from(1),
+    })
+}
+
+/// Where an item is written below `root`. Each catalog name becomes a single
+/// component, with slashes shown as colons as on macOS. Names that would
+/// refer to another folder are rejected.
+fn output_path(root: &Path, path: &[String]) -> Result<PathBuf, io::Error> {
+    let mut output_path = root.to_path_buf();
+    for component in path {
+        let name = component.replace('/', ":");
+        if matches!(name.as_str(), "" | "." | "..") {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unsafe name {component:?}"),
+            ));
+        }
+        output_path.push(name);
+    }
+    Ok(output_path)
+}
+
+/// A file next to `output_root`, named after it with `suffix` appended.
+fn beside(output_root: &Path, suffix: &str) -> Result<PathBuf, io::Error> {
+    // Resolve names like `.` to find the directory's own name.
+    let output_root = fs::canonicalize(output_root)?;
+    match (output_root.parent(), output_root.file_name()) {
+        (Some(parent), Some(name)) => {
+            let mut name = name.to_os_string();
+            name.push(".");
+            name.push(suffix);
+            Ok(parent.join(name))
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Give a location for the {suffix} file with the root as output"),
+        )),
+    }
+}
+
+/// Record the outcome of copying a file, and apply its metadata if it was
+/// copied. Metadata is best-effort and should not abort the extraction.
+fn finish_file(
+    extracted: &mut ExtractedFile,
+    copied: &Result<bool, io::Error>,
+    file_record: &CatalogFile,
+    metadata_options: &MetadataOptions,
+) {
+    match copied {
+        Ok(_) => {
+            if let Err(err) =
+                apply_file_metadata(&extracted.output_path, file_record, metadata_options)
+            {
+                extracted.errors.push(format!("metadata: {err}"));
+            }
+        }
+        Err(err) => extracted.errors.push(err.to_string()),
+    }
+    if let Some(err) = extracted.errors.last() {
+        eprintln!("Could not extract {}: {err}", extracted.path.join("/"));
+    }
+}
+
+/// Copy a file's data fork to `extracted.output_path`, unless an earlier run
+/// already did and the output still matches. Output created by this or an
+/// earlier run is replaced, as it may be left over from a failed run. Any
+/// other existing file is left alone. Returns whether the earlier output was
+/// kept.
+fn copy_file(
+    volume: &mut (impl Read + Seek),
+    block_size: usize,
+    file_record: &CatalogFile,
+    overflow_extents: Vec<ExtentDescriptor>,
+    copy_options: &CopyOptions,
+    state: &mut ExtractionState,
+    extracted: &mut ExtractedFile,
+) -> Result<bool, io::Error> {
+    let output_path = &extracted.output_path;
+    if let Some((size, hash)) =
+        state.verified(output_path, extracted.logical_size, copy_options.hash)?
+    {
+        extracted.bytes_written = size;
+        extracted.hash = Some(hash);
+        return Ok(true);
+    }
+
+    // Earlier output is removed rather than truncated, as its restored mode
+    // may not allow writing.
+    if state.is_created(output_path) {
+        match fs::remove_file(output_path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+    } else if output_path.symlink_metadata().is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "Not replacing a file that was not written by an extraction",
+        ));
+    } else {
+        state.create(output_path)?;
+    }
+    let mut output_file = File::options()
+        .write(true)
+        .create_new(true)
+        .custom_flags(OFlags::NOFOLLOW.bits() as i32)
+        .open(output_path)?;
+    let copied = copy_file_data_from_extents(
+        volume,
+        block_size,
+        file_record,
+        overflow_extents,
+        copy_options,
+        &mut output_file,
+    )?;
+    extracted.copied(&copied);
+    Ok(false)
+}
+
+/// Create the folders between `root` and `path`. Symbolic links are not
+/// followed, as one written earlier in the run could lead outside of `root`.
+/// Existing folders are made writable by their owner, as an earlier run may
+/// have restored a read-only mode to them. Their modes are restored again
+/// once the run's files are written.
+fn create_parent_dir(root: &Path, path: &Path) -> Result<(), io::Error> {
+    let Some(parent) = path.parent() else {
+        return Ok(());
+    };
+    let relative = parent
+        .strip_prefix(root)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let mut folder = root.to_path_buf();
+    for component in relative.components() {
+        folder.push(component);
+        match fs::create_dir(&folder) {
+            Ok(()) => continue,
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(err) => return Err(err),
+        }
+        let metadata = folder.symlink_metadata()?;
+        if !metadata.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} is not a folder", folder.display()),
+            ));
+        }
+        let mode = metadata.permissions().mode();
+        if mode & 0o700 != 0o700 {
+            fs::set_permissions(&folder, fs::Permissions::from_mode(mode | 0o700))?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether `path` is a folder reached from `root` without following symbolic
+/// links.
+fn is_folder_below(root: &Path, path: &Path) -> bool {
+    let Ok(relative) = path.strip_prefix(root) else {
+        return false;
+    };
+    let mut folder = root.to_path_buf();
+    relative.components().all(|component| {
+        folder.push(component);
+        folder
+            .symlink_metadata()
+            .is_ok_and(|metadata| metadata.is_dir())
+    })
+}
+
+/// Create a symbolic link, replacing one left by an earlier run.
+fn replace_symlink(target: &str, path: &Path) -> Result<(), io::Error> {
+    if path
+        .symlink_metadata()
+        .is_ok_and(|metadata| metadata.is_symlink())
+    {
+        fs::remove_file(path)?;
+    }
+    symlink(target, path)
+}
@@ -0,0 +1,284 @@
+//! Commands describing the volume and single records: `info`, `stat`, `cat`,
+//! `check`, and `journal`.
+
+use crate::{CatalogArgs, DamageArgs, ImageArgs, lookup};
+use clap::{Args, ValueEnum};
+use hfsprust::alias::volume_name;
+use hfsprust::allocation::ForkKind;
+use hfsprust::catalog::hard_link_target;
+use hfsprust::check::{Severity, check_volume};
+use hfsprust::journal::{read_journal_header, read_journal_info_block};
+use hfsprust::stat::record_details;
+use hfsprust::*;
+use std::io::{self, Write};
+use std::process::ExitCode;
+
+#[derive(Args)]
+pub struct InfoArgs {
+    #[command(flatten)]
+    image: ImageArgs,
+    #[command(flatten)]
+    catalog: CatalogArgs,
+}
+
+#[derive(Args)]
+pub struct StatArgs {
+    #[command(flatten)]
+    image: ImageArgs,
+    #[command(flatten)]
+    catalog: CatalogArgs,
+    /// Path within the volume, such as `/Users/me/file.txt`.
+    #[arg(required_unless_present = "cnid")]
+    path: Option<String>,
+    /// Show the record with this CNID instead of looking up a path.
+    #[arg(long, conflicts_with = "path")]
+    cnid: Option<CatalogNodeId>,
+}
+
+#[derive(Args)]
+pub struct CatArgs {
+    #[command(flatten)]
+    image: ImageArgs,
+    #[command(flatten)]
+    catalog: CatalogArgs,
+    /// Path within the volume. Symbolic links and hard links are followed.
+    path: String,
+    /// Write the resource fork instead of the data fork.
+    #[arg(long)]
+    resource: bool,
+    #[command(flatten)]
+    damage: DamageArgs,
+}
+
+#[derive(Args)]
+pub struct CheckArgs {
+    #[command(flatten)]
+    image: ImageArgs,
+    #[arg(long, value_enum, default_value_t = CheckFormat::Text)]
+    format: CheckFormat,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum CheckFormat {
+    Text,
+    Jsonl,
+}
+
+#[derive(Args)]
+pub struct JournalArgs {
+    #[command(flatten)]
+    image: ImageArgs,
+}
+
+/// Format a date stored in local time, whose offset is not recorded.
+fn local_date(date: Date) -> String {
+    let text = date.display_local(0).to_string();
+    match text.strip_suffix("+00:00") {
+        Some(text) => format!("{text} local time"),
+        None => text,
+    }
+}
+
+pub fn info(args: InfoArgs, verbose: bool) -> Result<ExitCode, io::Error> {
+    let (_, partition_map) = args.image.open()?;
+    let mut stdout = io::stdout().lock();
+    if let Some(map) = &partition_map {
+        writeln!(stdout, "Partition map: {}", map.scheme)?;
+        for partition in &map.partitions {
+            writeln!(
+                stdout,
+                "  {:>3}  offset {:>14}  length {:>14}  {}{}  {}",
+                partition.number,
+                partition.offset,
+                partition.length,
+                partition.kind,
+                if partition.is_hfs { " (HFS+)" } else { "" },
+                partition.name
+            )?;
+        }
+        writeln!(stdout)?;
+    }
+
+    let volume = args.image.open_volume(&args.catalog, verbose)?;
+    let header = &volume.header;
+    let attributes = [
+        (VolumeAttributeBit::HardwareLock, "hardware locked"),
+        (VolumeAttributeBit::Unmounted, "unmounted cleanly"),
+        (VolumeAttributeBit::SparedBlocks, "spared blocks"),
+        (VolumeAttributeBit::NoCacheRequired, "no cache required"),
+        (VolumeAttributeBit::BootVolumeInconsistent, "inconsistent"),
+        (VolumeAttributeBit::CatalogNodeIdsReused, "CNIDs reused"),
+        (VolumeAttributeBit::Journaled, "journaled"),
+        (VolumeAttributeBit::SoftwareLock, "software locked"),
+    ]
+    .into_iter()
+    .filter(|(bit, _)| header.has_attribute(*bit))
+    .map(|(_, name)| name)
+    .collect::<Vec<_>>();
+
+    writeln!(stdout, "Volume offset: {}", volume.stream.offset())?;
+    writeln!(
+        stdout,
+        "Name: {}",
+        volume_name(&volume.catalog).unwrap_or_else(|| "(unknown)".to_string())
+    )?;
+    writeln!(
+        stdout,
+        "Signature: {} version {}",
+        String::from_utf8_lossy(&header.signature),
+        header.version
+    )?;
+    writeln!(
+        stdout,
+        "Last mounted by: {}",
+        String::from_utf8_lossy(&header.last_mounted_version.to_be_bytes())
+    )?;
+    writeln!(stdout, "Attributes: {}", attributes.join(", "))?;
+    writeln!(stdout, "Created: {}", local_date(header.create_date))?;
+    writeln!(stdout, "Modified: {}", header.modify_date)?;
+    writeln!(stdout, "Backed up: {}", header.backup_date)?;
+    writeln!(stdout, "Checked: {}", header.checked_date)?;
+    writeln!(stdout, "Block size: {}", header.block_size)?;
+    writeln!(
+        stdout,
+        "Blocks: {} total, {} free",
+        header.total_blocks, header.free_blocks
+    )?;
+    writeln!(
+        stdout,
+        "Files: {}, folders: {}",
+        header.file_count, header.folder_count
+    )?;
+    writeln!(stdout, "Next CNID: {}", header.next_catalog_id)?;
+    writeln!(stdout, "Write count: {}", header.write_count)?;
+    writeln!(
+        stdout,
+        "Catalog: {} records from {:?}",
+        volume.catalog.len(),
+        volume.catalog_source
+    )?;
+    writeln!(stdout, "Overflow extent records: {}", volume.overflow.len())?;
+    Ok(ExitCode::SUCCESS)
+}
+
+pub fn stat(args: StatArgs, verbose: bool) -> Result<ExitCode, io::Error> {
+    let mut volume = args.image.open_volume(&args.catalog, verbose)?;
+    let cnid = match (args.cnid, &args.path) {
+        (Some(cnid), _) => cnid,
+        (None, path) => lookup(&mut volume, path.as_deref().unwrap_or("/"), false)?,
+    };
+    let details = record_details(&mut volume, cnid).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No file or folder has CNID {cnid}"),
+        )
+    })?;
+    write!(io::stdout().lock(), "{details}")?;
+    Ok(ExitCode::SUCCESS)
+}
+
+pub fn cat(args: CatArgs, verbose: bool) -> Result<ExitCode, io::Error> {
+    let mut volume = args.image.open_volume(&args.catalog, verbose)?;
+    args.damage.apply(&mut volume)?;
+    let cnid = lookup(&mut volume, &args.path, true)?;
+    let Some(CatalogLeafRecord::File(file)) = volume.record(cnid) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a file", args.path),
+        ));
+    };
+    let cnid = hard_link_target(&volume.catalog, file).map_or(cnid, |target| target.file_id);
+    let fork = if args.resource {
+        ForkKind::Resource
+    } else {
+        ForkKind::Data
+    };
+    io::copy(
+        &mut volume.fork_reader(cnid, fork)?,
+        &mut io::stdout().lock(),
+    )?;
+    Ok(ExitCode::SUCCESS)
+}
+
+pub fn check(args: CheckArgs) -> Result<ExitCode, io::Error> {
+    let (mut stream, _) = args.image.open()?;
+    let report = check_volume(&mut stream)?;
+
+    let mut stdout = io::stdout().lock();
+    match args.format {
+        CheckFormat::Text => {
+            for finding in &report.findings {
+                writeln!(stdout, "{finding}")?;
+            }
+        }
+        CheckFormat::Jsonl => report.write_jsonl(&mut stdout)?,
+    }
+
+    Ok(match report.worst() {
+        None | Some(Severity::Info) => ExitCode::SUCCESS,
+        Some(Severity::Warning) => ExitCode::from(1),
+        Some(Severity::Error) => ExitCode::from(2),
+    })
+}
+
+pub fn journal(args: JournalArgs) -> Result<ExitCode, io::Error> {
+    let (mut stream, _) = args.image.open()?;
+    let header = VolumeHeader::read_from(&mut stream)?;
+    let mut stdout = io::stdout().lock();
+    if !header.has_attribute(VolumeAttributeBit::Journaled) {
+        writeln!(stdout, "The volume is not journaled.")?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let info_block = read_journal_info_block(&mut stream, &header)?;
+    writeln!(
+        stdout,
+        "Journal info block: block {}",
+        header.journal_info_block
+    )?;
+    writeln!(
+        stdout,
+        "Location: {}",
+        match (info_block.flags.in_fs, info_block.flags.on_other_device) {
+            (true, _) => "on this volume",
+            (false, true) => "on another device",
+            (false, false) => "unknown",
+        }
+    )?;
+    writeln!(stdout, "Offset: {}", info_block.offset)?;
+    writeln!(stdout, "Size: {}", info_block.size)?;
+    if info_block.flags.needs_init {
+        writeln!(stdout, "The journal needs to be initialized.")?;
+    }
+
+    let Some(journal) = read_journal_header(&mut stream, &info_block)? else {
+        return Ok(ExitCode::SUCCESS);
+    };
+    writeln!(
+        stdout,
+        "Byte order: {}",
+        if journal.little_endian {
+            "little-endian"
+        } else {
+            "big-endian"
+        }
+    )?;
+    writeln!(stdout, "Start: {}", journal.start)?;
+    writeln!(stdout, "End: {}", journal.end)?;
+    writeln!(stdout, "Journal size: {}", journal.size)?;
+    writeln!(
+        stdout,
+        "Block list header size: {}",
+        journal.block_list_header_size
+    )?;
+    if journal.needs_replay() {
+        writeln!(
+            stdout,
+            "The journal holds transactions that have not been replayed. \
+            The volume may be out of date."
+        )?;
+        return Ok(ExitCode::from(1));
+    }
+    writeln!(stdout, "The journal is empty.")?;
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,588 @@
+//! Commands listing the catalog: `ls`, `tree`, `find`, `catalog`,
+//! `timeline`, and `dfxml`.
+
+use crate::{CatalogArgs, DamageArgs, ImageArgs, ImageVolume, lookup, volume_path};
+use clap::{Args, ValueEnum};
+use hfsprust::catalog::{children, record_for_cnid};
+use hfsprust::dfxml::{DfxmlOptions, write_dfxml};
+use hfsprust::export::{CatalogEntry, catalog_entries, catalog_entry, write_csv};
+use hfsprust::filter::{PathPattern, parse_date, parse_os_type};
+use hfsprust::fork::HashAlgorithm;
+use hfsprust::query::{DateRange, ItemKind, PermissionMatch, Query, find_records, is_fragmented};
+use hfsprust::recovery::orphan_path;
+use hfsprust::stat::{LinkTarget, link_target};
+use hfsprust::timeline::{TimelineOptions, timeline_entries, timeline_events, write_mactime};
+use hfsprust::*;
+use std::collections::HashSet;
+use std::io::{self, Write};
+use std::process::ExitCode;
+
+#[derive(Args)]
+pub struct LsArgs {
+    #[command(flatten)]
+    image: ImageArgs,
+    #[command(flatten)]
+    catalog: CatalogArgs,
+    /// Folder to list, or a single file.
+    #[arg(default_value = "/")]
+    path: String,
+    /// Show the mode, owner, size, and modification date of each item.
+    #[arg(short, long)]
+    long: bool,
+    /// Include hidden items, whose names start with `.`, and the private
+    /// metadata folders.
+    #[arg(short, long)]
+    all: bool,
+}
+
+#[derive(Args)]
+pub struct TreeArgs {
+    #[command(flatten)]
+    image: ImageArgs,
+    #[command(flatten)]
+    catalog: CatalogArgs,
+    /// Folder to start from.
+    #[arg(default_value = "/")]
+    path: String,
+    /// Include hidden items and the private metadata folders.
+    #[arg(short, long)]
+    all: bool,
+}
+
+#[derive(Args)]
+pub struct FindArgs {
+    #[command(flatten)]
+    image: ImageArgs,
+    #[command(flatten)]
+    catalog: CatalogArgs,
+    /// Folder to search in.
+    #[arg(default_value = "/")]
+    path: String,
+    #[command(flatten)]
+    query: QueryArgs,
+    /// Search records left behind by deleted items, instead of the live
+    /// catalog. Their paths may be incomplete.
+    #[arg(long)]
+    deleted: bool,
+    /// Print matching paths, or one JSON object per match with its metadata.
+    #[arg(long, value_enum, default_value_t = FindFormat::Paths)]
+    format: FindFormat,
+}
+
+#[derive(Args)]
+pub struct ExportArgs {
+    #[command(flatten)]
+    image: ImageArgs,
+    #[command(flatten)]
+    catalog: CatalogArgs,
+    /// `json` writes one document with the volume header and every entry,
+    /// `jsonl` one entry per line, and `csv` one row per entry.
+    #[arg(long, value_enum, default_value_t = ExportFormat::Jsonl)]
+    format: ExportFormat,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    Json,
+    Jsonl,
+    Csv,
+}
+
+#[derive(Args)]
+pub struct TimelineArgs {
+    #[command(flatten)]
+    image: ImageArgs,
+    #[command(flatten)]
+    catalog: CatalogArgs,
+    /// `bodyfile` writes the Sleuth Kit's format for `mactime`, and
+    /// `mactime` one line per date sorted as `mactime` would.
+    #[arg(long, value_enum, default_value_t = TimelineFormat::Bodyfile)]
+    format: TimelineFormat,
+    /// Leave out records of deleted items found in free catalog nodes.
+    #[arg(long)]
+    no_deleted: bool,
+    /// Hash the data fork of each file with MD5.
+    #[arg(long)]
+    md5: bool,
+    /// With `--format mactime`, only times at or after a date in UTC, as
+    /// `YYYY-MM-DD[THH:MM:SS]`.
+    #[arg(long, value_name = "DATE", value_parser = parse_date)]
+    after: Option<Date>,
+    /// With `--format mactime`, only times at or before a date in UTC.
+    #[arg(long, value_name = "DATE", value_parser = parse_date)]
+    before: Option<Date>,
+    #[command(flatten)]
+    damage: DamageArgs,
+}
+
+#[derive(Args)]
+pub struct DfxmlArgs {
+    #[command(flatten)]
+    image: ImageArgs,
+    #[command(flatten)]
+    catalog: CatalogArgs,
+    /// Leave out records of deleted items found in free catalog nodes.
+    #[arg(long)]
+    no_deleted: bool,
+    /// Digest of each file's data fork to include: md5, sha1, or sha256.
+    /// May be repeated.
+    #[arg(
+        long = "hash",
+        value_name = "ALGORITHM",
+        default_values = ["md5", "sha1"]
+    )]
+    hashes: Vec<HashAlgorithm>,
+    /// Leave out digests, without reading file contents.
+    #[arg(long, conflicts_with = "hashes")]
+    no_hash: bool,
+    #[command(flatten)]
+    damage: DamageArgs,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum TimelineFormat {
+    Bodyfile,
+    Mactime,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum FindFormat {
+    Paths,
+    Jsonl,
+}
+
+/// Which items to print. Every condition given must hold.
+#[derive(Args)]
+#[command(next_help_heading = "Conditions")]
+struct QueryArgs {
+    /// Only items whose name matches a glob, such as `*.txt`. Globs with a
+    /// `/` match the whole path instead.
+    #[arg(long, value_name = "GLOB", conflicts_with = "regex")]
+    name: Option<String>,
+
+    /// Only items whose path matches a regular expression.
+    #[arg(long, value_name = "REGEX")]
+    regex: Option<String>,
+
+    /// Only items of this type: `f` for files, `d` for folders, or `l` for
+    /// symbolic links.
+    #[arg(long = "type", value_name = "TYPE")]
+    kind: Option<ItemKind>,
+
+    /// Only files with a Finder type, such as `TEXT`.
+    #[arg(long, value_name = "CODE", value_parser = parse_os_type)]
+    file_type: Vec<OSType>,
+
+    /// Only files with a Finder creator, such as `ttxt`.
+    #[arg(long, value_name = "CODE", value_parser = parse_os_type)]
+    creator: Vec<OSType>,
+
+    /// Only files whose data fork is at least this many bytes.
+    #[arg(long, value_name = "BYTES")]
+    min_size: Option<u64>,
+
+    /// Only files whose data fork is at most this many bytes.
+    #[arg(long, value_name = "BYTES")]
+    max_size: Option<u64>,
+
+    /// Only items created at or after a date in UTC, as
+    /// `YYYY-MM-DD[THH:MM:SS]`.
+    #[arg(long, value_name = "DATE", value_parser = parse_date)]
+    created_after: Option<Date>,
+
+    /// Only items created at or before a date in UTC.
+    #[arg(long, value_name = "DATE", value_parser = parse_date)]
+    created_before: Option<Date>,
+
+    /// Only items whose contents were modified at or after a date.
+    #[arg(long, value_name = "DATE", value_parser = parse_date)]
+    modified_after: Option<Date>,
+
+    /// Only items whose contents were modified at or before a date.
+    #[arg(long, value_name = "DATE", value_parser = parse_date)]
+    modified_before: Option<Date>,
+
+    /// Only items whose attributes were changed at or after a date.
+    #[arg(long, value_name = "DATE", value_parser = parse_date)]
+    changed_after: Option<Date>,
+
+    /// Only items whose attributes were changed at or before a date.
+    #[arg(long, value_name = "DATE", value_parser = parse_date)]
+    changed_before: Option<Date>,
+
+    /// Only items accessed at or after a date.
+    #[arg(long, value_name = "DATE", value_parser = parse_date)]
+    accessed_after: Option<Date>,
+
+    /// Only items accessed at or before a date.
+    #[arg(long, value_name = "DATE", value_parser = parse_date)]
+    accessed_before: Option<Date>,
+
+    /// Only items backed up at or after a date.
+    #[arg(long, value_name = "DATE", value_parser = parse_date)]
+    backed_up_after: Option<Date>,
+
+    /// Only items backed up at or before a date.
+    #[arg(long, value_name = "DATE", value_parser = parse_date)]
+    backed_up_before: Option<Date>,
+
+    /// Only items owned by this user ID.
+    #[arg(long, value_name = "UID")]
+    owner: Option<u32>,
+
+    /// Only items owned by this group ID.
+    #[arg(long, value_name = "GID")]
+    group: Option<u32>,
+
+    /// Only items with these permission bits in octal, as for `find -perm`:
+    /// exactly `644`, all of `-644`, or any of `/022`.
+    #[arg(long, value_name = "MODE", allow_hyphen_values = true)]
+    perm: Option<PermissionMatch>,
+
+    /// Only items hidden from the Finder.
+    #[arg(long)]
+    invisible: bool,
+
+    /// Only items locked in the Finder.
+    #[arg(long)]
+    locked: bool,
+
+    /// Only Finder aliases.
+    #[arg(long)]
+    alias: bool,
+
+    /// Only files with a non-empty resource fork.
+    #[arg(long)]
+    resource_fork: bool,
+
+    /// Only files compressed by the file system.
+    #[arg(long)]
+    compressed: bool,
+
+    /// Only files with a fork stored in more than one extent.
+    #[arg(long)]
+    fragmented: bool,
+}
+
+impl QueryArgs {
+    fn query(self) -> Result<Query, io::Error> {
+        let name = match (&self.name, &self.regex) {
+            (Some(glob), _) => Some(PathPattern::glob(glob)?),
+            (None, Some(regex)) => Some(PathPattern::regex(regex)?),
+            (None, None) => None,
+        };
+        let range = |after, before| DateRange { after, before };
+        Ok(Query {
+            name,
+            kind: self.kind,
+            file_types: self.file_type,
+            creators: self.creator,
+            min_size: self.min_size,
+            max_size: self.max_size,
+            created: range(self.created_after, self.created_before),
+            modified: range(self.modified_after, self.modified_before),
+            changed: range(self.changed_after, self.changed_before),
+            accessed: range(self.accessed_after, self.accessed_before),
+            backed_up: range(self.backed_up_after, self.backed_up_before),
+            owner: self.owner,
+            group: self.group,
+            permissions: self.perm,
+            invisible: self.invisible.then_some(true),
+            locked: self.locked.then_some(true),
+            alias: self.alias.then_some(true),
+            resource_fork: self.resource_fork.then_some(true),
+            compressed: self.compressed.then_some(true),
+            fragmented: self.fragmented.then_some(true),
+        })
+    }
+}
+
+/// Hidden by default: dot files, and the metadata folders whose names start
+/// with NUL characters.
+fn is_hidden(name: &str) -> bool {
+    name.starts_with('.') || name.starts_with('\0')
+}
+
+/// The items in a folder, ordered by name ignoring case. Catalog keys are
+/// ordered by name length first, which is not useful for listings.
+fn sorted_children(
+    volume: &ImageVolume,
+    folder: CatalogNodeId,
+    all: bool,
+) -> Vec<(String, &CatalogLeafRecord)> {
+    let mut items = children(&volume.catalog, folder)
+        .filter(|(name, _)| all || !is_hidden(name))
+        .collect::<Vec<_>>();
+    items.sort_by_cached_key(|(name, _)| (name.to_lowercase(), name.clone()));
+    items
+}
+
+fn record_cnid(record: &CatalogLeafRecord) -> Option<CatalogNodeId> {
+    match record {
+        CatalogLeafRecord::File(file) => Some(file.file_id),
+        CatalogLeafRecord::Folder(folder) => Some(folder.folder_id),
+        _ => None,
+    }
+}
+
+pub fn ls(args: LsArgs, verbose: bool) -> Result<ExitCode, io::Error> {
+    let mut volume = args.image.open_volume(&args.catalog, verbose)?;
+    let cnid = lookup(&mut volume, &args.path, true)?;
+    let mut stdout = io::stdout().lock();
+
+    let entries = match volume.record(cnid) {
+        Some(CatalogLeafRecord::Folder(_)) => sorted_children(&volume, cnid, args.all)
+            .into_iter()
+            .filter_map(|(name, record)| Some((name, record_cnid(record)?)))
+            .collect::<Vec<_>>(),
+        _ => vec![(volume.path(cnid).pop().unwrap_or_default(), cnid)],
+    };
+    for (name, cnid) in entries {
+        if args.long {
+            write_long_entry(&mut stdout, &mut volume, &name, cnid)?;
+        } else {
+            writeln!(stdout, "{name}")?;
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// One line of `ls -l`: mode, owner, group, data fork size, modification
+/// date, name, and the target of a symbolic link or alias.
+fn write_long_entry(
+    output: &mut impl Write,
+    volume: &mut ImageVolume,
+    name: &str,
+    cnid: CatalogNodeId,
+) -> Result<(), io::Error> {
+    let block_size = volume.block_size();
+    let (permissions, size, modified, target) = match record_for_cnid(&volume.catalog, cnid) {
+        Some(CatalogLeafRecord::File(file)) => {
+            let target = link_target(&volume.catalog, &mut volume.stream, block_size, file);
+            (
+                &file.permissions,
+                file.data_fork.logical_size,
+                file.content_mod_date,
+                target,
+            )
+        }
+        Some(CatalogLeafRecord::Folder(folder)) => (
+            &folder.permissions,
+            folder.valence as u64,
+            folder.content_mod_date,
+            None,
+        ),
+        _ => return Ok(()),
+    };
+    write!(
+        output,
+        "{} {:>5} {:>5} {:>12} {} {name}",
+        permissions.mode_string(),
+        permissions.owner_id(),
+        permissions.group_id(),
+        size,
+        modified
+    )?;
+    // Hard links are shown as the files they are.
+    match target {
+        Some(target @ LinkTarget::Symlink(_)) => writeln!(output, " -> {target}"),
+        Some(target @ (LinkTarget::Alias { .. } | LinkTarget::UnreadableAlias(_))) => {
+            writeln!(output, " => alias to {target}")
+        }
+        Some(LinkTarget::HardLink { .. }) | None => writeln!(output),
+    }
+}
+
+pub fn tree(args: TreeArgs, verbose: bool) -> Result<ExitCode, io::Error> {
+    let mut volume = args.image.open_volume(&args.catalog, verbose)?;
+    let cnid = lookup(&mut volume, &args.path, true)?;
+    let mut stdout = io::stdout().lock();
+    writeln!(stdout, "{}", volume_path(&volume.path(cnid)))?;
+    // Damaged catalogs can contain cycles, so each folder is listed once.
+    let mut visited = HashSet::from([cnid]);
+    write_tree(&mut stdout, &volume, cnid, 1, args.all, &mut visited)?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn write_tree(
+    output: &mut impl Write,
+    volume: &ImageVolume,
+    folder: CatalogNodeId,
+    depth: usize,
+    all: bool,
+    visited: &mut HashSet<CatalogNodeId>,
+) -> Result<(), io::Error> {
+    for (name, record) in sorted_children(volume, folder, all) {
+        let indent = "  ".repeat(depth);
+        match record {
+            CatalogLeafRecord::Folder(child) => {
+                writeln!(output, "{indent}{name}/")?;
+                if visited.insert(child.folder_id) {
+                    write_tree(output, volume, child.folder_id, depth + 1, all, visited)?;
+                }
+            }
+            _ => writeln!(output, "{indent}{name}")?,
+        }
+    }
+    Ok(())
+}
+
+pub fn find(args: FindArgs, verbose: bool) -> Result<ExitCode, io::Error> {
+    let mut volume = args.image.open_volume(&args.catalog, verbose)?;
+    let start = lookup(&mut volume, &args.path, false)?;
+    let query = args.query.query()?;
+    let mut stdout = io::stdout().lock();
+    // Matches are written as the catalog export writes entries, with whether
+    // they were recovered and whether they are fragmented.
+    #[derive(serde::Serialize)]
+    struct Match<'a> {
+        #[serde(flatten)]
+        entry: CatalogEntry<'a>,
+        deleted: bool,
+        fragmented: bool,
+    }
+    let mut write_match = |path: &[String],
+                           shown: String,
+                           parent_id,
+                           record: &CatalogLeafRecord,
+                           deleted|
+     -> Result<(), io::Error> {
+        match args.format {
+            FindFormat::Paths => writeln!(stdout, "{shown}"),
+            FindFormat::Jsonl => {
+                let Some(entry) = catalog_entry(&volume.catalog, path, parent_id, record) else {
+                    return Ok(());
+                };
+                let fragmented = match record {
+                    CatalogLeafRecord::File(file) => is_fragmented(file, &volume.overflow),
+                    _ => false,
+                };
+                let found = Match {
+                    entry,
+                    deleted,
+                    fragmented,
+                };
+                serde_json::to_writer(&mut stdout, &found)?;
+                writeln!(stdout)
+            }
+        }
+    };
+
+    if args.deleted {
+        let (orphans, _) = volume.orphans()?;
+        let start_path = volume.path(start);
+        for orphan in &orphans {
+            let path = orphan_path(&volume.catalog, &orphans, orphan);
+            let parent_id = orphan.parent_id().unwrap_or_default();
+            if orphan.cnid().is_none()
+                || !query.matches(
+                    path.get(1..).unwrap_or_default(),
+                    &orphan.leaf,
+                    &volume.overflow,
+                )
+            {
+                continue;
+            }
+            // Paths under an unknown folder start with its CNID, and are
+            // only listed when searching the whole volume.
+            if path.first().is_some_and(|first| first.starts_with('#')) {
+                if start_path.len() <= 1 {
+                    write_match(&path, path.join("/"), parent_id, &orphan.leaf, true)?;
+                }
+            } else if path.starts_with(&start_path) {
+                write_match(&path, volume_path(&path), parent_id, &orphan.leaf, true)?;
+            }
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    for found in find_records(&volume, start, &query) {
+        write_match(
+            &found.path,
+            volume_path(&found.path),
+            found.parent_id,
+            found.record,
+            false,
+        )?;
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+pub fn catalog(args: ExportArgs, verbose: bool) -> Result<ExitCode, io::Error> {
+    let volume = args.image.open_volume(&args.catalog, verbose)?;
+    let entries = catalog_entries(&volume.catalog);
+    let mut stdout = io::BufWriter::new(io::stdout().lock());
+    match args.format {
+        ExportFormat::Json => {
+            #[derive(serde::Serialize)]
+            struct Document<'a> {
+                volume: &'a VolumeHeader,
+                entries: &'a [CatalogEntry<'a>],
+            }
+            let document = Document {
+                volume: &volume.header,
+                entries: &entries,
+            };
+            serde_json::to_writer(&mut stdout, &document)?;
+            writeln!(stdout)?;
+        }
+        ExportFormat::Jsonl => {
+            for entry in &entries {
+                serde_json::to_writer(&mut stdout, entry)?;
+                writeln!(stdout)?;
+            }
+        }
+        ExportFormat::Csv => write_csv(&mut stdout, &entries)?,
+    }
+    stdout.flush()?;
+    Ok(ExitCode::SUCCESS)
+}
+
+pub fn timeline(args: TimelineArgs, verbose: bool) -> Result<ExitCode, io::Error> {
+    let mut volume = args.image.open_volume(&args.catalog, verbose)?;
+    args.damage.apply(&mut volume)?;
+    let options = TimelineOptions {
+        deleted: !args.no_deleted,
+        md5: args.md5,
+    };
+    let entries = timeline_entries(&mut volume, &options)?;
+    let mut stdout = io::BufWriter::new(io::stdout().lock());
+    match args.format {
+        TimelineFormat::Bodyfile => {
+            for entry in &entries {
+                writeln!(stdout, "{}", entry.bodyfile_line())?;
+            }
+        }
+        TimelineFormat::Mactime => {
+            let range = DateRange {
+                after: args.after,
+                before: args.before,
+            };
+            write_mactime(&mut stdout, &timeline_events(&entries, range))?;
+        }
+    }
+    stdout.flush()?;
+    Ok(ExitCode::SUCCESS)
+}
+
+pub fn dfxml(args: DfxmlArgs, verbose: bool) -> Result<ExitCode, io::Error> {
+    let mut volume = args.image.open_volume(&args.catalog, verbose)?;
+    args.damage.apply(&mut volume)?;
+    let options = DfxmlOptions {
+        image_filename: args.image.image.to_string_lossy().into_owned(),
+        image_size: std::fs::metadata(&args.image.image)
+            .ok()
+            .map(|metadata| metadata.len()),
+        volume_offset: volume.stream.offset(),
+        command_line: Some(std::env::args().collect::<Vec<_>>().join(" ")),
+        deleted: !args.no_deleted,
+        hashes: match args.no_hash {
+            true => Vec::new(),
+            false => args.hashes,
+        },
+    };
+    let mut stdout = io::BufWriter::new(io::stdout().lock());
+    write_dfxml(&mut stdout, &mut volume, &options)?;
+    stdout.flush()?;
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,310 @@
+mod archive;
+mod carve;
+mod diff;
+mod extract;
+mod inspect;
+mod list;
+
+use clap::{Args, Parser, Subcommand};
+use hfsprust::CatalogNodeId;
+use hfsprust::damage::DamageMap;
+use hfsprust::partition::{PartitionMap, Slice, read_partition_map};
+use hfsprust::volume::{OpenOptions, Volume};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+/// Read (or attempt to read) damaged HFS+ volume images.
+#[derive(Parser)]
+#[command(
+    version,
+    after_help = "Exit status:
+  0  success
+  1  partial success: warnings from check, files that could not be fully
+     extracted, or a journal waiting to be replayed; or differences found
+     by diff
+  2  errors found by check
+  3  the command could not run"
+)]
+struct Cli {
+    /// List every damaged structure that was skipped, not just how many.
+    #[arg(short, long, global = true)]
+    verbose: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Describe the volume, and the partition map if there is one.
+    Info(inspect::InfoArgs),
+    /// List the contents of a folder.
+    Ls(list::LsArgs),
+    /// List the contents of a folder and all of its subfolders.
+    Tree(list::TreeArgs),
+    /// Show the catalog record of a file or folder.
+    Stat(inspect::StatArgs),
+    /// Write the contents of a file to standard output.
+    Cat(inspect::CatArgs),
+    /// Copy files and folders out of the volume.
+    Extract(Box<extract::ExtractArgs>),
+    /// Search for files and folders by name, type, size, dates, and flags.
+    Find(Box<list::FindArgs>),
+    /// Export every file and folder record as JSON or CSV.
+    Catalog(list::ExportArgs),
+    /// Write a timeline of every item, including deleted ones, as a bodyfile.
+    Timeline(list::TimelineArgs),
+    /// Describe the volume and every item, with where its contents are
+    /// stored, as Digital Forensics XML.
+    Dfxml(list::DfxmlArgs),
+    /// Compare the volume with another image of it, or with a directory it
+    /// was extracted to.
+    Diff(diff::DiffArgs),
+    /// Validate the volume's structures without modifying it.
+    Check(inspect::CheckArgs),
+    /// Show the state of the journal.
+    Journal(inspect::JournalArgs),
+    /// Recover files of known types from unallocated blocks.
+    Carve(carve::CarveArgs),
+}
+
+/// Where to find the volume.
+#[derive(Args)]
+struct ImageArgs {
+    /// Image of a volume or a whole disk.
+    image: PathBuf,
+
+    /// Byte offset of the volume within the image.
+    #[arg(long, value_name = "BYTES", conflicts_with = "partition")]
+    offset: Option<u64>,
+
+    /// Partition holding the volume, numbered from 1 as in the partition
+    /// map. Defaults to the only HFS+ partition, if the image does not start
+    /// with a volume.
+    #[arg(long, value_name = "NUMBER")]
+    partition: Option<usize>,
+}
+
+/// How to read a damaged catalog.
+#[derive(Args)]
+struct CatalogArgs {
+    /// Rebuild the catalog from its leaf nodes, even if its header node is
+    /// intact.
+    #[arg(long)]
+    rebuild_catalog: bool,
+
+    /// Carve catalog leaf nodes from the whole volume, ignoring the catalog
+    /// file's extents.
+    #[arg(long)]
+    carve_catalog: bool,
+}
+
+/// Regions of the image that could not be read. Bytes read from them are
+/// replaced with the fill pattern, and files holding them are reported as
+/// incomplete.
+#[derive(Args)]
+#[command(next_help_heading = "Damaged regions")]
+struct DamageArgs {
+    /// ddrescue mapfile giving the regions of the image that could not be
+    /// read.
+    #[arg(long, value_name = "MAPFILE", conflicts_with = "bad_ranges")]
+    ddrescue_map: Option<PathBuf>,
+
+    /// File of unreadable byte ranges of the image, one `offset length` pair
+    /// per line.
+    #[arg(long, value_name = "FILE")]
+    bad_ranges: Option<PathBuf>,
+
+    /// Pattern written in place of unreadable data: `0x` followed by
+    /// hexadecimal bytes, or literal text. Defaults to zeroes.
+    #[arg(long, value_name = "PATTERN")]
+    fill: Option<String>,
+}
+
+impl DamageArgs {
+    /// Mark the regions on the volume, along with the fill pattern.
+    fn apply(&self, volume: &mut ImageVolume) -> Result<(), io::Error> {
+        let damage = match (&self.ddrescue_map, &self.bad_ranges) {
+            (Some(path), _) => DamageMap::parse_ddrescue_mapfile(&fs::read_to_string(path)?)?,
+            (None, Some(path)) => DamageMap::parse_range_list(&fs::read_to_string(path)?)?,
+            (None, None) => DamageMap::default(),
+        };
+        // The map gives offsets within the image, rather than the volume.
+        volume.add_damage(&damage.relative_to(volume.stream.offset()));
+        if let Some(pattern) = &self.fill {
+            volume.fill = parse_fill_pattern(pattern)?;
+        }
+        Ok(())
+    }
+}
+
+type ImageVolume = Volume<Slice<File>>;
+
+fn main() -> ExitCode {
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(err) => {
+            let _ = err.print();
+            return match err.use_stderr() {
+                true => ExitCode::from(3),
+                // Help and version requests.
+                false => ExitCode::SUCCESS,
+            };
+        }
+    };
+
+    let verbose = cli.verbose;
+    let result = match cli.command {
+        Command::Info(args) => inspect::info(args, verbose),
+        Command::Ls(args) => list::ls(args, verbose),
+        Command::Tree(args) => list::tree(args, verbose),
+        Command::Stat(args) => inspect::stat(args, verbose),
+        Command::Cat(args) => inspect::cat(args, verbose),
+        Command::Extract(args) => extract::extract(*args, verbose),
+        Command::Find(args) => list::find(*args, verbose),
+        Command::Catalog(args) => list::catalog(args, verbose),
+        Command::Timeline(args) => list::timeline(args, verbose),
+        Command::Dfxml(args) => list::dfxml(args, verbose),
+        Command::Diff(args) => diff::diff(args, verbose),
+        Command::Check(args) => inspect::check(args),
+        Command::Journal(args) => inspect::journal(args),
+        Command::Carve(args) => carve::carve(args, verbose),
+    };
+    match result {
+        Ok(code) => code,
+        // Output piped to a command that has exited, such as `head`.
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("hfsprust: {err}");
+            ExitCode::from(3)
+        }
+    }
+}
+
+impl ImageArgs {
+    /// Open the image and find the volume in it.
+    fn open(&self) -> Result<(Slice<File>, Option<PartitionMap>), io::Error> {
+        let mut image = File::open(&self.image)?;
+        let partition_map = read_partition_map(&mut image)?;
+
+        let (offset, length) = match (self.offset, self.partition) {
+            (Some(offset), _) => (offset, None),
+            (None, Some(number)) => {
+                let partition = partition_map
+                    .as_ref()
+                    .and_then(|map| map.partition(number))
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::NotFound,
+                            format!("The image has no partition {number}"),
+                        )
+                    })?;
+                (partition.offset, Some(partition.length))
+            }
+            (None, None) if starts_with_volume(&mut image, 0)? => (0, None),
+            (None, None) => {
+                let hfs_partitions = partition_map
+                    .iter()
+                    .flat_map(|map| &map.partitions)
+                    .filter(|partition| partition.is_hfs)
+                    .collect::<Vec<_>>();
+                match hfs_partitions[..] {
+                    [partition] => (partition.offset, Some(partition.length)),
+                    _ => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::NotFound,
+                            "The image does not start with an HFS+ volume. \
+                            Choose one with --partition or --offset; \
+                            `hfsprust info` lists the partitions.",
+                        ));
+                    }
+                }
+            }
+        };
+        Ok((Slice::new(image, offset, length)?, partition_map))
+    }
+
+    /// Open the image, and the volume with its catalog.
+    fn open_volume(&self, catalog: &CatalogArgs, verbose: bool) -> Result<ImageVolume, io::Error> {
+        let (stream, _) = self.open()?;
+        let options = OpenOptions {
+            rebuild_catalog: catalog.rebuild_catalog,
+            carve_catalog: catalog.carve_catalog,
+        };
+        let volume = Volume::open(stream, &options)?;
+        report_warnings(&volume.warnings, verbose);
+        Ok(volume)
+    }
+}
+
+/// Whether an HFS+ or HFSX volume header signature is at `offset`.
+fn starts_with_volume(image: &mut File, offset: u64) -> Result<bool, io::Error> {
+    let mut signature = [0u8; 2];
+    image.seek(SeekFrom::Start(offset + 1024))?;
+    match image.read_exact(&mut signature) {
+        Ok(()) => Ok(&signature == b"H+" || &signature == b"HX"),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+/// Damage is expected on the volumes this tool is for, so it is summarised
+/// unless asked for.
+fn report_warnings(warnings: &[String], verbose: bool) {
+    if verbose {
+        for warning in warnings {
+            eprintln!("warning: {warning}");
+        }
+    } else if !warnings.is_empty() {
+        eprintln!(
+            "warning: skipped {} damaged structures; use --verbose to list them",
+            warnings.len()
+        );
+    }
+}
+
+/// Find the CNID of a path within the volume, which must exist.
+fn lookup(
+    volume: &mut ImageVolume,
+    path: &str,
+    follow_symlinks: bool,
+) -> Result<CatalogNodeId, io::Error> {
+    volume
+        .lookup(path, follow_symlinks)?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{path} was not found")))
+}
+
+/// Format a path from the catalog, which starts with the volume name, as an
+/// absolute path within the volume.
+fn volume_path(path: &[String]) -> String {
+    format!(
+        "/{}",
+        path.iter()
+            .skip(1)
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join("/")
+    )
+}
+
+/// Parse a fill pattern: `0x` followed by hexadecimal bytes, or literal text.
+fn parse_fill_pattern(pattern: &str) -> Result<Vec<u8>, io::Error> {
+    let Some(hex) = pattern.strip_prefix("0x") else {
+        return Ok(pattern.as_bytes().to_vec());
+    };
+    if hex.len() % 2 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Hexadecimal fill pattern needs whole bytes",
+        ));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+        })
+        .collect()
+}
@@ -0,0 +1,427 @@
+//! Reading B-tree nodes and records. Described in TN1150 > B-Trees.
+
+use crate::catalog::{CatalogMap, parse_catalog_leaf};
+use crate::*;
+use deku::bitvec::BitSlice;
+use itertools::Itertools;
+use std::collections::BTreeSet;
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// A problem found while reading a B-tree. Offsets are in bytes from the start
+/// of the B-tree file.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub node: u32,
+    /// Index of the record within the node, if the problem is with a record.
+    pub record: Option<usize>,
+    pub offset: u64,
+    pub reason: String,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "node {}", self.node)?;
+        if let Some(record) = self.record {
+            write!(f, " record {record}")?;
+        }
+        write!(f, " at offset {:#x}: {}", self.offset, self.reason)
+    }
+}
+
+/// A record's bytes, or the reason they could not be located.
+pub struct RawRecord {
+    pub index: usize,
+    /// Offset of the record from the start of its node.
+    pub offset: usize,
+    pub data: Result<Vec<u8>, io::Error>,
+}
+
+/// Split an in-memory node into its descriptor and records. Fails only if the
+/// descriptor or offset table is unreadable; records with out-of-bounds
+/// offsets are reported individually.
+pub fn split_btree_node(node: &[u8]) -> Result<(BTreeNodeDescriptor, Vec<RawRecord>), io::Error> {
+    let descriptor = node
+        .get(..BTreeNodeDescriptor::SIZE)
+        .ok_or_else(|| invalid_data("Node is smaller than its descriptor"))?;
+    let (_rest, node_descriptor) = BTreeNodeDescriptor::read(BitSlice::from_slice(descriptor), ())?;
+
+    // Record offsets are stored in reverse order at the end of the node,
+    // followed by the offset of the free space.
+    let offset_count = node_descriptor.num_records as usize + 1;
+    let table_start = node
+        .len()
+        .checked_sub(2 * offset_count)
+        .filter(|&start| start >= BTreeNodeDescriptor::SIZE)
+        .ok_or_else(|| invalid_data(format!("{offset_count} record offsets do not fit in node")))?;
+    let offsets = node[table_start..]
+        .chunks_exact(2)
+        .rev()
+        .map(|b| u16::from_be_bytes([b[0], b[1]]) as usize)
+        .collect_vec();
+
+    let records = offsets
+        .into_iter()
+        .tuple_windows()
+        .enumerate()
+        .map(|(index, (start, end))| {
+            let data = if start < BTreeNodeDescriptor::SIZE || end < start || end > table_start {
+                Err(invalid_data(format!(
+                    "Record spans {start}..{end}, outside of node"
+                )))
+            } else {
+                Ok(node[start..end].to_vec())
+            };
+            RawRecord {
+                index,
+                offset: start,
+                data,
+            }
+        })
+        .collect();
+
+    Ok((node_descriptor, records))
+}
+
+/// Whether a buffer looks like a B-tree node of its length: a known kind, a
+/// height that suits the kind, and at least one record, with offsets that
+/// start after the descriptor and increase within the node.
+pub fn is_plausible_node(node: &[u8]) -> bool {
+    let Ok((descriptor, records)) = split_btree_node(node) else {
+        return false;
+    };
+
+    let height_matches_kind = match descriptor.kind {
+        BTreeNodeKind::kBTLeafNode => descriptor.height == 1,
+        BTreeNodeKind::kBTIndexNode => descriptor.height > 1,
+        BTreeNodeKind::kBTHeaderNode | BTreeNodeKind::kBTMapNode => descriptor.height == 0,
+    };
+
+    height_matches_kind
+        && descriptor.reserved == 0
+        && records
+            .first()
+            .is_some_and(|record| record.offset == BTreeNodeDescriptor::SIZE)
+        && records
+            .iter()
+            .all(|record| record.data.as_ref().is_ok_and(|data| !data.is_empty()))
+}
+
+/// Read a single node from the stream, returning its descriptor and the raw
+/// bytes of each record.
+pub fn read_btree_node(
+    stream: &mut (impl Read + Seek),
+    _block_size: usize,
+    record_size: usize,
+) -> Result<(BTreeNodeDescriptor, Vec<Vec<u8>>), io::Error> {
+    // Consume entire record and operate on in-memory buffer.
+    let mut node = vec![0u8; record_size];
+    stream.read_exact(&mut node)?;
+
+    let (node_descriptor, records) = split_btree_node(&node)?;
+    let records = records
+        .into_iter()
+        .map(|record| record.data)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok((node_descriptor, records))
+}
+
+/// Read a whole node by number, relative to the start of the B-tree file.
+pub fn read_node_at(
+    stream: &mut (impl Read + Seek),
+    node: u32,
+    node_size: usize,
+) -> Result<Vec<u8>, io::Error> {
+    let mut buf = vec![0u8; node_size];
+    stream.seek(SeekFrom::Start(node as u64 * node_size as u64))?;
+    stream.read_exact(&mut buf)?;
+
+    Ok(buf)
+}
+
+/// Read the node allocation bitmap. It starts with the map record of the
+/// header node, and continues in map nodes chained by their forward links.
+/// Problems with map nodes are reported, returning the bitmap read so far.
+pub fn read_btree_map(
+    stream: &mut (impl Read + Seek),
+    node_size: usize,
+) -> Result<(BTreeAllocationMapRecord, Vec<Diagnostic>), io::Error> {
+    let (header_descriptor, records) = split_btree_node(&read_node_at(stream, 0, node_size)?)?;
+    let mut bitmap = records
+        .into_iter()
+        .nth(2)
+        .ok_or_else(|| invalid_data("Header node has no map record"))?
+        .data?;
+
+    let mut diagnostics = Vec::new();
+    let mut visited = BTreeSet::from([0]);
+    let mut next = header_descriptor.forward_link;
+    while next != 0 {
+        let node_offset = next as u64 * node_size as u64;
+        let diagnostic = |reason: String| Diagnostic {
+            node: next,
+            record: None,
+            offset: node_offset,
+            reason,
+        };
+        if !visited.insert(next) {
+            diagnostics.push(diagnostic("Map node chain loops".to_string()));
+            break;
+        }
+
+        let map_record = read_node_at(stream, next, node_size)
+            .and_then(|node| split_btree_node(&node))
+            .and_then(|(descriptor, records)| {
+                if descriptor.kind != BTreeNodeKind::kBTMapNode {
+                    return Err(invalid_data(format!(
+                        "Expected map node, found {:?}",
+                        descriptor.kind
+                    )));
+                }
+                let record = records
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| invalid_data("Map node has no records"))?;
+                Ok((descriptor.forward_link, record.data?))
+            });
+        match map_record {
+            Ok((forward_link, data)) => {
+                bitmap.extend(data);
+                next = forward_link;
+            }
+            Err(err) => {
+                diagnostics.push(diagnostic(err.to_string()));
+                break;
+            }
+        }
+    }
+
+    Ok((BTreeAllocationMapRecord { bitmap }, diagnostics))
+}
+
+/// Follow the chain of leaf nodes from `first_leaf_node`, returning the node
+/// numbers that are reachable. Stops at the first broken link.
+pub fn read_leaf_chain(
+    stream: &mut (impl Read + Seek),
+    header: &BTreeHeaderRecord,
+) -> (BTreeSet<u32>, Vec<Diagnostic>) {
+    let node_size = header.node_size as usize;
+    let mut leaves = BTreeSet::new();
+    let mut diagnostics = Vec::new();
+
+    let mut next = header.first_leaf_node;
+    while next != 0 {
+        let diagnostic = |reason: String| Diagnostic {
+            node: next,
+            record: None,
+            offset: next as u64 * node_size as u64,
+            reason,
+        };
+        if next >= header.total_nodes {
+            diagnostics.push(diagnostic(
+                "Leaf link points past the last node".to_string(),
+            ));
+            break;
+        }
+        if leaves.contains(&next) {
+            diagnostics.push(diagnostic("Leaf node chain loops".to_string()));
+            break;
+        }
+
+        match read_node_at(stream, next, node_size).and_then(|node| split_btree_node(&node)) {
+            Ok((descriptor, _)) if descriptor.kind == BTreeNodeKind::kBTLeafNode => {
+                leaves.insert(next);
+                next = descriptor.forward_link;
+            }
+            Ok((descriptor, _)) => {
+                diagnostics.push(diagnostic(format!(
+                    "Expected leaf node, found {:?}",
+                    descriptor.kind
+                )));
+                break;
+            }
+            Err(err) => {
+                diagnostics.push(diagnostic(err.to_string()));
+                break;
+            }
+        }
+    }
+
+    (leaves, diagnostics)
+}
+
+/// Manually read the BTree header to bootstrap the rest of the read.
+pub fn read_btree_header(
+    stream: &mut (impl Read + Seek),
+    _block_size: usize,
+) -> Result<(BTreeNodeDescriptor, BTreeHeaderRecord), io::Error> {
+    // Read BTree Descriptor
+    let mut buf = [0; BTreeNodeDescriptor::SIZE];
+    stream.read_exact(&mut buf)?;
+    let buf = BitSlice::from_slice(&buf);
+
+    let (_rest, node_descriptor) = BTreeNodeDescriptor::read(buf, ())?;
+
+    // Read Header Record
+    let mut buf = [0; BTreeHeaderRecord::SIZE];
+    stream.read_exact(&mut buf)?;
+    let buf = BitSlice::from_slice(&buf);
+    let (_rest, btree_header) = BTreeHeaderRecord::read(buf, ())?;
+
+    // User Data is 128 bytes of reserved data. Skip it for now.
+    let mut buf = [0; BTreeUserDataRecord::SIZE];
+    stream.read_exact(&mut buf)?;
+    let (_rest, _user_data) = BTreeUserDataRecord::from_bytes((&buf, 0))?;
+
+    // Node sizes are powers of two from 512 to 32,768 bytes. Anything else
+    // means the header is damaged, and the remaining sizes cannot be trusted.
+    let node_size = btree_header.node_size;
+    if !node_size.is_power_of_two() || node_size < 512 {
+        return Err(invalid_data(format!("Implausible node size {node_size}")));
+    }
+
+    // The Map Record consumes all space until the record offsets at the end of the node.
+    // This can be derived from the node size (specified in the node header) and the size
+    // of all other structures (totals 256 bytes).
+    let size_of_structures = 256;
+    let map_record_size = node_size - size_of_structures;
+    let mut buf = vec![0u8; map_record_size as usize];
+    stream.read_exact(&mut buf)?;
+
+    // Parse offsets at end of header node
+    let mut offsets = Vec::<u16>::with_capacity((node_descriptor.num_records) as usize);
+    for _ in 0..=node_descriptor.num_records {
+        let mut buf = [0u8; 2];
+        stream.read_exact(&mut buf)?;
+        let offset = u16::from_be_bytes(buf);
+        offsets.push(offset);
+    }
+    offsets.reverse();
+
+    Ok((node_descriptor, btree_header))
+}
+
+/// Read every readable leaf record of the Catalog B-tree, keyed by raw
+/// catalog key. Damaged nodes and records are reported on standard error and
+/// skipped; see [`salvage_btree_leaves`] to collect them instead.
+pub fn read_btree_leaves(
+    stream: &mut (impl Read + Seek),
+    block_size: usize,
+) -> Result<CatalogMap, io::Error> {
+    let (btree, diagnostics) = salvage_btree_leaves(stream, block_size)?;
+    for diagnostic in diagnostics {
+        eprintln!("{diagnostic}");
+    }
+    Ok(btree)
+}
+
+/// Read every readable leaf record of the Catalog B-tree, continuing past
+/// damaged nodes and records. Each problem is returned as a [`Diagnostic`].
+/// Only an unreadable header node is fatal.
+///
+/// Nodes marked free in the allocation map are skipped, as they may hold stale
+/// copies of records. See [`crate::recovery::find_orphan_records`].
+pub fn salvage_btree_leaves(
+    stream: &mut (impl Read + Seek),
+    block_size: usize,
+) -> Result<(CatalogMap, Vec<Diagnostic>), io::Error> {
+    let (records, diagnostics) = salvage_leaf_records(stream, block_size, parse_catalog_leaf)?;
+    Ok((records.into_iter().collect(), diagnostics))
+}
+
+/// Parse every readable leaf record of a B-tree in node order, as
+/// [`salvage_btree_leaves`] does for the Catalog.
+pub fn salvage_leaf_records<T>(
+    mut stream: &mut (impl Read + Seek),
+    block_size: usize,
+    mut parse: impl FnMut(&[u8]) -> Result<T, io::Error>,
+) -> Result<(Vec<T>, Vec<Diagnostic>), io::Error> {
+    stream.seek(SeekFrom::Start(0))?;
+    let (_node_descriptor, btree_header_record) = read_btree_header(&mut stream, block_size)?;
+    let node_size = btree_header_record.node_size as u64;
+    let total_nodes = btree_header_record.total_nodes as u64;
+
+    let mut diagnostics = Vec::new();
+
+    // Don't trust the node count further than the data that is present.
+    let available_nodes = stream.seek(SeekFrom::End(0))? / node_size;
+    if total_nodes > available_nodes {
+        diagnostics.push(Diagnostic {
+            node: 0,
+            record: None,
+            offset: 0,
+            reason: format!(
+                "Header claims {total_nodes} nodes, but only {available_nodes} are present"
+            ),
+        });
+    }
+
+    // Without a map, every node has to be treated as in use.
+    let node_map = match read_btree_map(stream, node_size as usize) {
+        Ok((node_map, map_diagnostics)) => {
+            diagnostics.extend(map_diagnostics);
+            node_map
+        }
+        Err(err) => {
+            diagnostics.push(Diagnostic {
+                node: 0,
+                record: Some(2),
+                offset: 0,
+                reason: format!("Unreadable node allocation map: {err}"),
+            });
+            BTreeAllocationMapRecord::default()
+        }
+    };
+
+    // Read all nodes and extract leaves.
+    let mut leaves = Vec::new();
+    for n in 1..total_nodes.min(available_nodes) {
+        if node_map.is_node_used(n as u32) == Some(false) {
+            continue;
+        }
+
+        let node_offset = n * node_size;
+        let diagnostic = |record, offset, reason: String| Diagnostic {
+            node: n as u32,
+            record,
+            offset,
+            reason,
+        };
+
+        let mut node = vec![0u8; node_size as usize];
+        if let Err(err) = stream
+            .seek(SeekFrom::Start(node_offset))
+            .and_then(|_| stream.read_exact(&mut node))
+        {
+            diagnostics.push(diagnostic(None, node_offset, err.to_string()));
+            continue;
+        }
+
+        let (node_header, records) = match split_btree_node(&node) {
+            Ok(node) => node,
+            Err(err) => {
+                diagnostics.push(diagnostic(None, node_offset, err.to_string()));
+                continue;
+            }
+        };
+
+        // WIP: Focus on Leaf Nodes
+        if node_header.kind != BTreeNodeKind::kBTLeafNode {
+            continue;
+        }
+
+        for record in records {
+            let record_offset = node_offset + record.offset as u64;
+            match record.data.and_then(|data| parse(&data)) {
+                Ok(leaf) => leaves.push(leaf),
+                Err(err) => diagnostics.push(diagnostic(
+                    Some(record.index),
+                    record_offset,
+                    err.to_string(),
+                )),
+            }
+        }
+    }
+
+    Ok((leaves, diagnostics))
+}
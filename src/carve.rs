@@ -0,0 +1,531 @@
+//! Carving files out of unallocated space by their signatures.
+//!
+//! HFS+ allocates files in whole blocks, so only the start of each free block
+//! is checked for a known header. A match is followed forward through the
+//! volume until the format's end is found, which assumes the file was stored
+//! contiguously. Fragmented files are carved up to the end of their first
+//! extent at best.
+
+use crate::allocation::{AllocationBitmap, BlockOwnership};
+use crate::*;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+/// Upper bound on the size of a carved file, unless the caller chooses
+/// another.
+pub const DEFAULT_MAX_CARVE_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Bytes at the start of a block used to recognise a file.
+const HEADER_LENGTH: usize = 512;
+
+/// Free space is read in chunks of whole blocks when looking for headers.
+const CHUNK_SIZE: usize = 8 * 1024 * 1024;
+
+/// Bytes read at a time while following a file to its end.
+const WINDOW_SIZE: usize = 1024 * 1024;
+
+/// File formats recognised by the carver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CarvedKind {
+    Jpeg,
+    Png,
+    Pdf,
+    /// ZIP archives, including Office Open XML and iWork documents.
+    Zip,
+    /// QuickTime movies, recognised by their atom structure.
+    QuickTime,
+    /// MPEG-4 files, which share the QuickTime atom structure.
+    Mp4,
+    XmlPlist,
+    BinaryPlist,
+    Sqlite,
+}
+
+impl CarvedKind {
+    /// Recognise a format from the first bytes of a block.
+    pub fn detect(header: &[u8]) -> Option<Self> {
+        let atom = |kind: &[u8]| {
+            header.get(4..8) == Some(kind)
+                && header
+                    .get(..4)
+                    .is_some_and(|size| u32::from_be_bytes(size.try_into().unwrap()) >= 8)
+        };
+
+        if header.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            Some(Self::Jpeg)
+        } else if header.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(Self::Png)
+        } else if header.starts_with(b"%PDF-") {
+            Some(Self::Pdf)
+        } else if header.starts_with(b"PK\x03\x04") {
+            Some(Self::Zip)
+        } else if atom(b"ftyp") {
+            // The major brand distinguishes QuickTime from other MPEG-4 files.
+            match header.get(8..12) {
+                Some(b"qt  ") => Some(Self::QuickTime),
+                _ => Some(Self::Mp4),
+            }
+        } else if atom(b"moov") || atom(b"wide") || atom(b"mdat") {
+            // Older QuickTime movies have no file type atom.
+            Some(Self::QuickTime)
+        } else if header.starts_with(b"bplist00") {
+            Some(Self::BinaryPlist)
+        } else if header.starts_with(b"SQLite format 3\0") {
+            Some(Self::Sqlite)
+        } else if header.starts_with(b"<?xml") && contains(header, b"<!DOCTYPE plist")
+            || header.starts_with(b"<plist")
+        {
+            Some(Self::XmlPlist)
+        } else {
+            None
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Jpeg => "jpg",
+            Self::Png => "png",
+            Self::Pdf => "pdf",
+            Self::Zip => "zip",
+            Self::QuickTime => "mov",
+            Self::Mp4 => "mp4",
+            Self::XmlPlist | Self::BinaryPlist => "plist",
+            Self::Sqlite => "sqlite",
+        }
+    }
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
+
+/// A file found in free space.
+#[derive(Debug, Clone)]
+pub struct CarvedFile {
+    pub kind: CarvedKind,
+    /// Offset of the file from the start of the volume.
+    pub offset: u64,
+    pub length: u64,
+    /// Whether the end of the file was found. Incomplete files run to the
+    /// end of the free space they start in, or to the size limit.
+    pub complete: bool,
+}
+
+impl CarvedFile {
+    pub fn start_block(&self, block_size: usize) -> u32 {
+        (self.offset / block_size as u64) as u32
+    }
+
+    pub fn block_count(&self, block_size: usize) -> u32 {
+        self.length.div_ceil(block_size as u64) as u32
+    }
+
+    /// Catalog items that claim any of the carved blocks. The carver only
+    /// starts files in free blocks, so these are files that were written over
+    /// the end of the carved one, or that the carved file ran into.
+    pub fn owners(&self, block_size: usize, ownership: &BlockOwnership) -> Vec<CatalogNodeId> {
+        let mut owners = ownership
+            .overlapping(self.start_block(block_size), self.block_count(block_size))
+            .map(|extent| extent.owner)
+            .collect::<Vec<_>>();
+        owners.sort_unstable();
+        owners.dedup();
+        owners
+    }
+}
+
+/// Look for known file formats at the start of every free block, returning
+/// the files found in volume order. A complete file's blocks are not
+/// searched again, so embedded thumbnails and attachments are not reported
+/// separately.
+pub fn carve_free_space(
+    volume: &mut (impl Read + Seek),
+    block_size: usize,
+    bitmap: &AllocationBitmap,
+    max_size: u64,
+) -> Result<Vec<CarvedFile>, io::Error> {
+    let volume_length = volume.seek(SeekFrom::End(0))?;
+    let total_blocks = (volume_length / block_size as u64).min(bitmap.total_blocks() as u64) as u32;
+    let header_length = HEADER_LENGTH.min(block_size);
+    let blocks_per_chunk = (CHUNK_SIZE / block_size).max(1) as u32;
+
+    let mut carved = Vec::new();
+    let mut chunk = Vec::new();
+    let mut chunk_start = 0u32;
+    let mut block = 0u32;
+    while block < total_blocks {
+        if bitmap.is_block_used(block) {
+            block += 1;
+            continue;
+        }
+
+        // Only read chunks once free space is reached.
+        let chunk_blocks = (chunk.len() / block_size) as u32;
+        if block < chunk_start || block >= chunk_start + chunk_blocks {
+            let count = blocks_per_chunk.min(total_blocks - block);
+            chunk.resize(count as usize * block_size, 0);
+            volume.seek(SeekFrom::Start(block as u64 * block_size as u64))?;
+            volume.read_exact(&mut chunk)?;
+            chunk_start = block;
+        }
+        let at = (block - chunk_start) as usize * block_size;
+        let Some(kind) = CarvedKind::detect(&chunk[at..at + header_length]) else {
+            block += 1;
+            continue;
+        };
+
+        let offset = block as u64 * block_size as u64;
+        let limit = volume_length.min(offset.saturating_add(max_size));
+        let end = find_end(&mut Window::new(volume, limit), kind, offset)?;
+        let file = match end {
+            Some(end) => CarvedFile {
+                kind,
+                offset,
+                length: end - offset,
+                complete: true,
+            },
+            None => {
+                let max_blocks = (limit - offset).div_ceil(block_size as u64);
+                let free_blocks = (block..total_blocks)
+                    .take(max_blocks as usize)
+                    .take_while(|block| !bitmap.is_block_used(*block))
+                    .count() as u64;
+                CarvedFile {
+                    kind,
+                    offset,
+                    length: (free_blocks * block_size as u64).min(limit - offset),
+                    complete: false,
+                }
+            }
+        };
+
+        // An incomplete file may hide the start of another, so only skip
+        // past files whose end was found.
+        block += if file.complete {
+            file.block_count(block_size).max(1)
+        } else {
+            1
+        };
+        carved.push(file);
+    }
+
+    Ok(carved)
+}
+
+/// Copy a carved file to the output, returning the number of bytes written.
+pub fn write_carved_file(
+    volume: &mut (impl Read + Seek),
+    file: &CarvedFile,
+    output: &mut impl Write,
+) -> Result<u64, io::Error> {
+    volume.seek(SeekFrom::Start(file.offset))?;
+    io::copy(&mut volume.take(file.length), output)
+}
+
+/// Buffered random access to the volume, up to a limit.
+struct Window<'a, R> {
+    volume: &'a mut R,
+    limit: u64,
+    start: u64,
+    data: Vec<u8>,
+}
+
+impl<'a, R: Read + Seek> Window<'a, R> {
+    fn new(volume: &'a mut R, limit: u64) -> Self {
+        Self {
+            volume,
+            limit,
+            start: 0,
+            data: Vec::new(),
+        }
+    }
+
+    /// Buffered bytes from the offset onwards, holding at least the minimum
+    /// length unless the limit is reached first.
+    fn chunk(&mut self, offset: u64, min_length: usize) -> Result<&[u8], io::Error> {
+        let buffered = offset >= self.start
+            && offset + min_length as u64 <= self.start + self.data.len() as u64;
+        if !buffered {
+            let length = self
+                .limit
+                .saturating_sub(offset)
+                .min(WINDOW_SIZE.max(min_length) as u64);
+            self.data.resize(length as usize, 0);
+            self.volume.seek(SeekFrom::Start(offset))?;
+            self.volume.read_exact(&mut self.data)?;
+            self.start = offset;
+        }
+        Ok(&self.data[(offset - self.start) as usize..])
+    }
+
+    /// Bytes at the offset, or None when they extend past the limit.
+    fn bytes(&mut self, offset: u64, length: usize) -> Result<Option<&[u8]>, io::Error> {
+        if offset
+            .checked_add(length as u64)
+            .is_none_or(|end| end > self.limit)
+        {
+            return Ok(None);
+        }
+        Ok(Some(&self.chunk(offset, length)?[..length]))
+    }
+
+    fn u16(&mut self, offset: u64) -> Result<Option<u16>, io::Error> {
+        Ok(self
+            .bytes(offset, 2)?
+            .map(|bytes| u16::from_be_bytes(bytes.try_into().unwrap())))
+    }
+
+    fn u32(&mut self, offset: u64) -> Result<Option<u32>, io::Error> {
+        Ok(self
+            .bytes(offset, 4)?
+            .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap())))
+    }
+
+    fn u64(&mut self, offset: u64) -> Result<Option<u64>, io::Error> {
+        Ok(self
+            .bytes(offset, 8)?
+            .map(|bytes| u64::from_be_bytes(bytes.try_into().unwrap())))
+    }
+
+    /// Offset of the next occurrence of the needle at or after the offset.
+    fn find(&mut self, mut offset: u64, needle: &[u8]) -> Result<Option<u64>, io::Error> {
+        while offset.saturating_add(needle.len() as u64) <= self.limit {
+            let chunk = self.chunk(offset, needle.len())?;
+            if let Some(at) = chunk
+                .windows(needle.len())
+                .position(|window| window == needle)
+            {
+                return Ok(Some(offset + at as u64));
+            }
+            offset += (chunk.len() - needle.len() + 1) as u64;
+        }
+        Ok(None)
+    }
+}
+
+/// Offset just past the end of a file, or None if it could not be found
+/// before the limit.
+fn find_end(
+    window: &mut Window<impl Read + Seek>,
+    kind: CarvedKind,
+    start: u64,
+) -> Result<Option<u64>, io::Error> {
+    match kind {
+        CarvedKind::Jpeg => jpeg_end(window, start),
+        CarvedKind::Png => png_end(window, start),
+        CarvedKind::Pdf => footer_end(window, start, b"%%EOF"),
+        CarvedKind::Zip => zip_end(window, start),
+        CarvedKind::QuickTime | CarvedKind::Mp4 => atom_end(window, start),
+        CarvedKind::XmlPlist => footer_end(window, start, b"</plist>"),
+        CarvedKind::BinaryPlist => binary_plist_end(window, start),
+        CarvedKind::Sqlite => sqlite_end(window, start),
+    }
+}
+
+/// End of the first footer, including a line ending that follows it.
+fn footer_end(
+    window: &mut Window<impl Read + Seek>,
+    start: u64,
+    footer: &[u8],
+) -> Result<Option<u64>, io::Error> {
+    let Some(at) = window.find(start, footer)? else {
+        return Ok(None);
+    };
+    let mut end = at + footer.len() as u64;
+    if window.bytes(end, 1)? == Some(b"\r") {
+        end += 1;
+    }
+    if window.bytes(end, 1)? == Some(b"\n") {
+        end += 1;
+    }
+    Ok(Some(end))
+}
+
+/// Walk the JPEG segments rather than searching for the end of image marker,
+/// which also appears at the end of embedded thumbnails.
+fn jpeg_end(window: &mut Window<impl Read + Seek>, start: u64) -> Result<Option<u64>, io::Error> {
+    let mut position = start + 2;
+    loop {
+        let Some(&[0xFF, marker]) = window.bytes(position, 2)? else {
+            return Ok(None);
+        };
+        match marker {
+            // End of image.
+            0xD9 => return Ok(Some(position + 2)),
+            // Fill bytes may precede a marker.
+            0xFF => {
+                position += 1;
+                continue;
+            }
+            // Markers without a length.
+            0x01 | 0xD0..=0xD7 => {
+                position += 2;
+                continue;
+            }
+            _ => {}
+        }
+
+        let Some(length) = window.u16(position + 2)? else {
+            return Ok(None);
+        };
+        if length < 2 {
+            return Ok(None);
+        }
+        position += 2 + length as u64;
+
+        // Entropy-coded data follows the start of scan, and runs until a
+        // marker other than a stuffed zero or a restart marker.
+        if marker == 0xDA {
+            loop {
+                let Some(at) = window.find(position, &[0xFF])? else {
+                    return Ok(None);
+                };
+                match window.bytes(at + 1, 1)? {
+                    None => return Ok(None),
+                    Some([0x00 | 0xD0..=0xD7]) => position = at + 2,
+                    Some(_) => {
+                        position = at;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Walk the PNG chunks until the image end chunk.
+fn png_end(window: &mut Window<impl Read + Seek>, start: u64) -> Result<Option<u64>, io::Error> {
+    let mut position = start + 8;
+    loop {
+        let Some(length) = window.u32(position)? else {
+            return Ok(None);
+        };
+        let Some(chunk_type) = window.bytes(position + 4, 4)? else {
+            return Ok(None);
+        };
+        if !chunk_type.iter().all(u8::is_ascii_alphabetic) {
+            return Ok(None);
+        }
+        let is_end = chunk_type == b"IEND";
+
+        // Length, type, data, and CRC.
+        position += 12 + length as u64;
+        if is_end {
+            return Ok((position <= window.limit).then_some(position));
+        }
+    }
+}
+
+/// Find the end of central directory record, which closes the archive after
+/// a variable-length comment.
+fn zip_end(window: &mut Window<impl Read + Seek>, start: u64) -> Result<Option<u64>, io::Error> {
+    let Some(at) = window.find(start, b"PK\x05\x06")? else {
+        return Ok(None);
+    };
+    let Some(comment_length) = window.u16(at + 20)? else {
+        return Ok(None);
+    };
+    let end = at + 22 + comment_length as u64;
+    Ok((end <= window.limit).then_some(end))
+}
+
+/// Walk the top-level atoms of a QuickTime or MPEG-4 file. The file is only
+/// considered complete once the movie atom has been seen, as the media data
+/// is unusable without it.
+fn atom_end(window: &mut Window<impl Read + Seek>, start: u64) -> Result<Option<u64>, io::Error> {
+    let mut position = start;
+    let mut has_movie = false;
+    while let Some(size) = window.u32(position)? {
+        let Some(atom_type) = window.bytes(position + 4, 4)? else {
+            break;
+        };
+        if !atom_type
+            .iter()
+            .all(|byte| byte.is_ascii_alphanumeric() || *byte == b' ')
+        {
+            break;
+        }
+        let is_movie = atom_type == b"moov";
+
+        let size = match size {
+            // The last atom may run to the end of the file, which is unknown.
+            0 => break,
+            1 => match window.u64(position + 8)? {
+                Some(size) => size,
+                None => break,
+            },
+            size => size as u64,
+        };
+        let Some(next) = position.checked_add(size) else {
+            break;
+        };
+        if size < 8 || next > window.limit {
+            break;
+        }
+        has_movie |= is_movie;
+        position = next;
+    }
+    Ok((has_movie && position > start).then_some(position))
+}
+
+/// Search for the trailer at the end of a binary property list. Its offset
+/// table position and size determine where the file ends, so a trailer is
+/// only accepted when it agrees with its own position.
+fn binary_plist_end(
+    window: &mut Window<impl Read + Seek>,
+    start: u64,
+) -> Result<Option<u64>, io::Error> {
+    // The trailer starts with five unused bytes and a sort version of zero.
+    let mut position = start + 8;
+    while let Some(at) = window.find(position, &[0; 6])? {
+        position = at + 1;
+        let Some(trailer) = window.bytes(at, 32)? else {
+            return Ok(None);
+        };
+        let offset_size = trailer[6] as u64;
+        let reference_size = trailer[7];
+        let object_count = u64::from_be_bytes(trailer[8..16].try_into().unwrap());
+        let top_object = u64::from_be_bytes(trailer[16..24].try_into().unwrap());
+        let table_offset = u64::from_be_bytes(trailer[24..32].try_into().unwrap());
+        if !(1..=8).contains(&offset_size)
+            || !(1..=8).contains(&reference_size)
+            || object_count == 0
+            || top_object >= object_count
+            || table_offset < 8
+        {
+            continue;
+        }
+        let table_end = object_count
+            .checked_mul(offset_size)
+            .and_then(|size| size.checked_add(table_offset));
+        if table_end == Some(at - start) {
+            return Ok(Some(at + 32));
+        }
+    }
+    Ok(None)
+}
+
+/// The database header records the page size and, when it is up to date,
+/// the number of pages.
+fn sqlite_end(window: &mut Window<impl Read + Seek>, start: u64) -> Result<Option<u64>, io::Error> {
+    let page_size = match window.u16(start + 16)? {
+        Some(1) => 65536,
+        Some(size) if size >= 512 && size.is_power_of_two() => size as u64,
+        _ => return Ok(None),
+    };
+    let (Some(change_counter), Some(page_count), Some(valid_for)) = (
+        window.u32(start + 24)?,
+        window.u32(start + 28)?,
+        window.u32(start + 92)?,
+    ) else {
+        return Ok(None);
+    };
+    // Older writers did not maintain the page count, which is only trusted
+    // when it was written along with the change counter.
+    if page_count == 0 || change_counter != valid_for {
+        return Ok(None);
+    }
+    let end = start + page_size * page_count as u64;
+    Ok((end <= window.limit).then_some(end))
+}
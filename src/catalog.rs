@@ -0,0 +1,293 @@
+//! Catalog File records and navigation. Described in TN1150 > Catalog File.
+
+use crate::fork::assemble_extents;
+use crate::*;
+use deku::bitvec::BitSlice;
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::io::{self, Cursor, Read, Seek};
+
+/// All leaf records of the Catalog B-tree, keyed by the raw catalog key
+/// (parent CNID followed by the length-prefixed UTF-16 node name).
+pub type CatalogMap = BTreeMap<BTreeKey, CatalogLeafRecord>;
+
+/// Maximum number of symbolic links traversed during a single path lookup.
+/// Matches `MAXSYMLINKS` on macOS.
+pub const MAX_SYMLINK_HOPS: usize = 32;
+
+/// Name of the hidden folder in the root that holds the indirect node files
+/// for hard links. Defined in TN1150 > Hard Links.
+pub const METADATA_FOLDER_NAME: &str = "\0\0\0\0HFS+ Private Data";
+
+/// Indirect node files are named with this prefix followed by their number.
+pub const INDIRECT_NODE_PREFIX: &str = "iNode";
+
+/// Parse a single leaf record from the Catalog B-tree, returning the raw key
+/// and the decoded record.
+pub fn parse_catalog_leaf(record: &[u8]) -> Result<(BTreeKey, CatalogLeafRecord), io::Error> {
+    let mut cur = Cursor::new(record);
+
+    // Key Length: u16, as per TN1150 > Keyed Records. Might vary for non-leaves.
+    let mut buf = [0u8; 2];
+    cur.read_exact(&mut buf)?;
+    let key_length = u16::from_be_bytes(buf);
+
+    // Key Data (opaque)
+    let mut key: BTreeKey = vec![0u8; key_length as usize];
+    cur.read_exact(&mut key)?;
+
+    // Alignment
+    if key_length % 2 == 1 {
+        eprintln!("Found odd key length! Consume padding.");
+        cur.read_exact(&mut [0u8; 1])?;
+    }
+
+    let mut data = Vec::<u8>::new();
+    cur.read_to_end(&mut data)?;
+
+    // Peek at record kind. The kind is the first field of each record, so
+    // parse the payload from the start of the data.
+    let bits = BitSlice::from_slice(&data);
+    let (_rest, kind) = CatalogFileDataType::read(bits, ())?;
+
+    // Parse payload
+    let record = match kind {
+        CatalogFileDataType::kHFSPlusFolderRecord => {
+            let (_rest, folder) = CatalogFolder::read(bits, ())?;
+            CatalogLeafRecord::Folder(folder)
+        }
+        CatalogFileDataType::kHFSPlusFileRecord => {
+            let (_rest, file) = CatalogFile::read(bits, ())?;
+            CatalogLeafRecord::File(file)
+        }
+        CatalogFileDataType::kHFSPlusFolderThreadRecord => {
+            let (_rest, folder_thread) = CatalogThread::read(bits, ())?;
+            CatalogLeafRecord::FolderThread(folder_thread)
+        }
+        CatalogFileDataType::kHFSPlusFileThreadRecord => {
+            let (_rest, file_thread) = CatalogThread::read(bits, ())?;
+            CatalogLeafRecord::FileThread(file_thread)
+        }
+    };
+
+    Ok((key, record))
+}
+
+/// Key of the thread record for a given CNID. Thread records are keyed by the
+/// CNID with an empty name.
+pub fn cnid_to_key(cnid: CatalogNodeId) -> BTreeKey {
+    let mut key = Vec::<u8>::with_capacity(6);
+    key.extend_from_slice(cnid.to_be_bytes().as_slice());
+    key.extend(&[0u8; 2]);
+
+    key
+}
+
+/// Key of a file or folder record, given its parent's CNID and its name.
+pub fn child_key(parent: CatalogNodeId, name: &[u16]) -> BTreeKey {
+    let mut key = Vec::<u8>::with_capacity(6 + 2 * name.len());
+    key.extend_from_slice(parent.to_be_bytes().as_slice());
+    key.extend_from_slice((name.len() as u16).to_be_bytes().as_slice());
+    name.iter()
+        .for_each(|c16| key.extend_from_slice(c16.to_be_bytes().as_slice()));
+
+    key
+}
+
+/// Construct a path for a given File Record. Stops early at a missing or
+/// malformed thread record, or if the parent chain loops.
+pub fn path_for_key(map: &CatalogMap, start: BTreeKey) -> Vec<String> {
+    // Record traversal to root
+    let mut path = Vec::<String>::new();
+    let mut visited = HashSet::new();
+
+    // Construct key for initial lookup
+    let mut key = start;
+    while visited.insert(key.clone()) {
+        key = match map.get(&key) {
+            Some(CatalogLeafRecord::FolderThread(t)) | Some(CatalogLeafRecord::FileThread(t)) => {
+                path.push(String::from_utf16_lossy(&t.node_name.unicode));
+                cnid_to_key(t.parent_id)
+            }
+            // A thread key should never lead to a file or folder record.
+            _ => break,
+        };
+    }
+
+    path.reverse();
+    path
+}
+
+/// Find the file or folder record for a CNID by following its thread record.
+pub fn record_for_cnid(map: &CatalogMap, cnid: CatalogNodeId) -> Option<&CatalogLeafRecord> {
+    match map.get(&cnid_to_key(cnid))? {
+        CatalogLeafRecord::FolderThread(t) | CatalogLeafRecord::FileThread(t) => {
+            map.get(&child_key(t.parent_id, &t.node_name.unicode))
+        }
+        _ => None,
+    }
+}
+
+/// Interpret a file's `special` field, using its location in the catalog to
+/// recognise indirect node files.
+pub fn file_special(map: &CatalogMap, file: &CatalogFile) -> BsdSpecial {
+    if let Some(CatalogLeafRecord::FileThread(thread)) = map.get(&cnid_to_key(file.file_id)) {
+        let in_metadata_folder = matches!(
+            map.get(&cnid_to_key(thread.parent_id)),
+            Some(CatalogLeafRecord::FolderThread(parent))
+                if String::from_utf16_lossy(&parent.node_name.unicode) == METADATA_FOLDER_NAME
+        );
+        let name = String::from_utf16_lossy(&thread.node_name.unicode);
+        if in_metadata_folder && name.starts_with(INDIRECT_NODE_PREFIX) {
+            return BsdSpecial::LinkCount(file.permissions.special);
+        }
+    }
+
+    file.special()
+}
+
+/// Find a named child of a folder. Exact matches are preferred, falling back
+/// to a case-insensitive comparison as HFS+ names are case-insensitive.
+pub fn lookup_child<'a>(
+    map: &'a CatalogMap,
+    parent: CatalogNodeId,
+    name: &str,
+) -> Option<&'a CatalogLeafRecord> {
+    let utf16 = name.encode_utf16().collect::<Vec<_>>();
+    if let Some(record) = map.get(&child_key(parent, &utf16)) {
+        return Some(record);
+    }
+
+    let prefix = parent.to_be_bytes();
+    let folded = name.to_lowercase();
+    map.range(prefix.to_vec()..)
+        .take_while(|(key, _)| key.starts_with(&prefix))
+        .find(|(key, _)| {
+            let chars = key
+                .get(6..)
+                .unwrap_or_default()
+                .chunks_exact(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                .collect::<Vec<_>>();
+            String::from_utf16_lossy(&chars).to_lowercase() == folded
+        })
+        .map(|(_, record)| record)
+}
+
+/// The files and folders in a folder, with their names, in key order.
+pub fn children(
+    map: &CatalogMap,
+    parent: CatalogNodeId,
+) -> impl Iterator<Item = (String, &CatalogLeafRecord)> {
+    let prefix = parent.to_be_bytes();
+    map.range(prefix.to_vec()..)
+        .take_while(move |(key, _)| key.starts_with(&prefix))
+        .filter(|(_, record)| {
+            matches!(
+                record,
+                CatalogLeafRecord::File(_) | CatalogLeafRecord::Folder(_)
+            )
+        })
+        .map(|(key, record)| {
+            let chars = key
+                .get(6..)
+                .unwrap_or_default()
+                .chunks_exact(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                .collect::<Vec<_>>();
+            (String::from_utf16_lossy(&chars), record)
+        })
+}
+
+/// The indirect node file holding a hard link's contents, found by the link's
+/// inode number in the metadata folder.
+pub fn hard_link_target<'a>(map: &'a CatalogMap, link: &CatalogFile) -> Option<&'a CatalogFile> {
+    if !link.is_hard_link() {
+        return None;
+    }
+    let root = StandardCnid::kHFSRootFolderID as CatalogNodeId;
+    let Some(CatalogLeafRecord::Folder(metadata_folder)) =
+        lookup_child(map, root, METADATA_FOLDER_NAME)
+    else {
+        return None;
+    };
+    let name = format!("{INDIRECT_NODE_PREFIX}{}", link.permissions.special);
+    match lookup_child(map, metadata_folder.folder_id, &name) {
+        Some(CatalogLeafRecord::File(target)) => Some(target),
+        _ => None,
+    }
+}
+
+/// Read the target of a symbolic link, stored as UTF-8 in the data fork.
+pub fn read_symlink_target(
+    volume: &mut (impl Read + Seek),
+    block_size: usize,
+    file: &CatalogFile,
+) -> Result<String, io::Error> {
+    let data = assemble_extents(volume, &file.data_fork, block_size)?;
+    String::from_utf8(data).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Resolve a slash-separated path, relative to the root folder, to a CNID.
+///
+/// When `follow_symlinks` is set, symbolic links are resolved against the
+/// folder containing them, with absolute targets resolved from the root
+/// folder. Otherwise, symbolic links are only returned as the final component.
+///
+/// Returns `None` when a component does not exist, and an error when more than
+/// [`MAX_SYMLINK_HOPS`] links are followed.
+pub fn lookup_path(
+    map: &CatalogMap,
+    volume: &mut (impl Read + Seek),
+    block_size: usize,
+    path: &str,
+    follow_symlinks: bool,
+) -> Result<Option<CatalogNodeId>, io::Error> {
+    let root = StandardCnid::kHFSRootFolderID as CatalogNodeId;
+    let mut components = path.split('/').map(String::from).collect::<VecDeque<_>>();
+    let mut current = root;
+    let mut hops = 0;
+
+    while let Some(component) = components.pop_front() {
+        match component.as_str() {
+            "" | "." => continue,
+            ".." => {
+                if current != root {
+                    current = match map.get(&cnid_to_key(current)) {
+                        Some(CatalogLeafRecord::FolderThread(t)) => t.parent_id,
+                        _ => return Ok(None),
+                    };
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        current = match lookup_child(map, current, &component) {
+            Some(CatalogLeafRecord::Folder(folder)) => folder.folder_id,
+            Some(CatalogLeafRecord::File(file)) if file.is_symlink() && follow_symlinks => {
+                hops += 1;
+                if hops > MAX_SYMLINK_HOPS {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Too many levels of symbolic links",
+                    ));
+                }
+
+                let target = read_symlink_target(volume, block_size, file)?;
+                if target.starts_with('/') {
+                    current = root;
+                }
+                target
+                    .split('/')
+                    .rev()
+                    .for_each(|c| components.push_front(c.to_string()));
+                continue;
+            }
+            // Files may only appear as the final component.
+            Some(CatalogLeafRecord::File(file)) if components.is_empty() => file.file_id,
+            _ => return Ok(None),
+        };
+    }
+
+    Ok(Some(current))
+}
@@ -0,0 +1,967 @@
+//! Read-only consistency checks, in the spirit of `fsck_hfs -n`.
+//!
+//! Every check runs against whatever could be read, so a damaged volume still
+//! produces a complete report. Findings are graded by severity: errors mean
+//! structures are damaged or data is at risk, warnings are inconsistencies
+//! that a repair would fix without losing data.
+
+use crate::allocation::{
+    AllocationBitmap, BlockOwnership, BlockProblemKind, ForkKind, verify_allocation,
+};
+use crate::btree::{
+    read_btree_header, read_btree_map, read_node_at, salvage_btree_leaves, split_btree_node,
+};
+use crate::catalog::{CatalogMap, child_key, cnid_to_key};
+use crate::extents::{OverflowExtents, fork_overflow_extents, read_overflow_extents};
+use crate::fork::assemble_extents;
+use crate::journal::{read_journal_header, read_journal_info_block};
+use crate::report::json_string;
+use crate::*;
+use itertools::Itertools;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+
+/// Root folder of every volume. Defined in TN1150 > Catalog File.
+const ROOT_FOLDER_ID: CatalogNodeId = StandardCnid::kHFSRootFolderID as CatalogNodeId;
+
+/// Unreachable nodes are summarised rather than listed one by one.
+const MAX_LISTED_NODES: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Info => "info",
+            Self::Warning => "warning",
+            Self::Error => "error",
+        })
+    }
+}
+
+/// A single result of a check.
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub severity: Severity,
+    /// The check that produced the finding, such as `catalog-btree`.
+    pub check: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.severity, self.check, self.message)
+    }
+}
+
+/// All findings for a volume, in the order the checks ran.
+#[derive(Debug, Default)]
+pub struct CheckReport {
+    pub findings: Vec<Finding>,
+}
+
+impl CheckReport {
+    fn push(&mut self, severity: Severity, check: &'static str, message: impl Into<String>) {
+        self.findings.push(Finding {
+            severity,
+            check,
+            message: message.into(),
+        });
+    }
+
+    /// The most severe finding, or None for a clean volume.
+    pub fn worst(&self) -> Option<Severity> {
+        self.findings.iter().map(|finding| finding.severity).max()
+    }
+
+    /// Write one JSON object per finding, with `severity`, `check`, and
+    /// `message` fields.
+    pub fn write_jsonl(&self, output: &mut impl Write) -> Result<(), io::Error> {
+        for finding in &self.findings {
+            writeln!(
+                output,
+                "{{\"severity\":{},\"check\":{},\"message\":{}}}",
+                json_string(&finding.severity.to_string()),
+                json_string(finding.check),
+                json_string(&finding.message)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Run every check against a volume. Only failing to read the volume itself
+/// is an error; damage is reported as findings.
+pub fn check_volume(volume: &mut (impl Read + Seek)) -> Result<CheckReport, io::Error> {
+    let mut report = CheckReport::default();
+    let volume_length = volume.seek(SeekFrom::End(0))?;
+
+    let volume_header = match VolumeHeader::read_from(volume) {
+        Ok(volume_header) => volume_header,
+        Err(err) => {
+            report.push(
+                Severity::Error,
+                "header",
+                format!("Unreadable volume header: {err}"),
+            );
+            return Ok(report);
+        }
+    };
+    if !check_header(volume, &volume_header, volume_length, &mut report)? {
+        return Ok(report);
+    }
+    let block_size = volume_header.block_size as usize;
+
+    // Extents Overflow File.
+    let mut overflow = Vec::new();
+    match assemble_extents(volume, &volume_header.extents_file, block_size) {
+        Ok(extents_file) if !extents_file.is_empty() => {
+            check_btree(
+                &mut Cursor::new(extents_file),
+                KeyOrder::Extents,
+                "extents-btree",
+                &mut report,
+            )?;
+            match read_overflow_extents(volume, &volume_header) {
+                Ok((records, _)) => overflow = records,
+                Err(err) => report.push(
+                    Severity::Error,
+                    "extents-btree",
+                    format!("Unreadable extents overflow file: {err}"),
+                ),
+            }
+        }
+        Ok(_) => report.push(
+            Severity::Error,
+            "extents-btree",
+            "Extents overflow file is empty",
+        ),
+        Err(err) => report.push(
+            Severity::Error,
+            "extents-btree",
+            format!("Unreadable extents overflow file: {err}"),
+        ),
+    }
+
+    // Catalog File.
+    let mut map = None;
+    match assemble_extents(volume, &volume_header.catalog_file, block_size) {
+        Ok(catalog_file) if !catalog_file.is_empty() => {
+            let mut cursor = Cursor::new(catalog_file);
+            check_btree(&mut cursor, KeyOrder::Catalog, "catalog-btree", &mut report)?;
+            match salvage_btree_leaves(&mut cursor, block_size) {
+                Ok((salvaged, _)) => map = Some(salvaged),
+                Err(err) => report.push(
+                    Severity::Error,
+                    "catalog-btree",
+                    format!("Unreadable catalog: {err}"),
+                ),
+            }
+        }
+        Ok(_) => report.push(Severity::Error, "catalog-btree", "Catalog file is empty"),
+        Err(err) => report.push(
+            Severity::Error,
+            "catalog-btree",
+            format!("Unreadable catalog file: {err}"),
+        ),
+    }
+
+    // Without the catalog, every file's blocks would appear to be leaked.
+    match map {
+        Some(map) => {
+            check_catalog_records(&volume_header, &map, &mut report);
+            check_fork_sizes(&volume_header, &map, &overflow, &mut report);
+            check_allocation(volume, &volume_header, &map, &overflow, &mut report);
+        }
+        None => report.push(
+            Severity::Info,
+            "catalog-btree",
+            "Skipped catalog record and allocation checks",
+        ),
+    }
+    check_journal(volume, &volume_header, &mut report);
+
+    Ok(report)
+}
+
+/// Check the fields of the volume header that the other checks rely on.
+/// Returns false if the rest of the volume cannot be checked.
+fn check_header(
+    volume: &mut (impl Read + Seek),
+    volume_header: &VolumeHeader,
+    volume_length: u64,
+    report: &mut CheckReport,
+) -> Result<bool, io::Error> {
+    const CHECK: &str = "header";
+
+    // Version 4 is HFS Plus, and version 5 is HFSX.
+    if !matches!(volume_header.version, 4 | 5) {
+        report.push(
+            Severity::Warning,
+            CHECK,
+            format!("Unexpected volume version {}", volume_header.version),
+        );
+    }
+
+    let block_size = volume_header.block_size;
+    if !block_size.is_power_of_two() || block_size < 512 {
+        report.push(
+            Severity::Error,
+            CHECK,
+            format!("Implausible block size {block_size}"),
+        );
+        return Ok(false);
+    }
+
+    let volume_size = volume_header.total_blocks as u64 * block_size as u64;
+    if volume_size > volume_length {
+        report.push(
+            Severity::Error,
+            CHECK,
+            format!(
+                "Volume claims {volume_size} bytes, but only {volume_length} are present. The image may be truncated."
+            ),
+        );
+    }
+    if volume_header.free_blocks > volume_header.total_blocks {
+        report.push(
+            Severity::Error,
+            CHECK,
+            format!(
+                "Free block count {} exceeds total block count {}",
+                volume_header.free_blocks, volume_header.total_blocks
+            ),
+        );
+    }
+
+    if !volume_header.has_attribute(VolumeAttributeBit::Unmounted) {
+        report.push(Severity::Warning, CHECK, "Volume was not cleanly unmounted");
+    }
+    if volume_header.has_attribute(VolumeAttributeBit::BootVolumeInconsistent) {
+        report.push(
+            Severity::Warning,
+            CHECK,
+            "Volume is marked inconsistent, and may have been in use",
+        );
+    }
+
+    // The alternate volume header sits 1024 bytes before the end of the
+    // volume. Only its signature is checked, as other fields may lag.
+    if volume_size >= VolumeHeader::OFFSET && volume_size <= volume_length {
+        let mut signature = [0u8; 2];
+        volume.seek(SeekFrom::Start(volume_size - VolumeHeader::OFFSET))?;
+        volume.read_exact(&mut signature)?;
+        if &signature != b"H+" && &signature != b"HX" {
+            report.push(
+                Severity::Warning,
+                CHECK,
+                "Alternate volume header is missing",
+            );
+        }
+    }
+
+    Ok(true)
+}
+
+/// How keys are ordered in a B-tree.
+#[derive(Clone, Copy)]
+enum KeyOrder {
+    /// Parent CNID, then name. Names are compared with case folding unless
+    /// the header asks for binary comparison.
+    Catalog,
+    /// File ID, then fork type, then start block.
+    Extents,
+}
+
+impl KeyOrder {
+    /// Compare two keys, excluding their key length fields.
+    fn compare(&self, header: &BTreeHeaderRecord, a: &[u8], b: &[u8]) -> Ordering {
+        match self {
+            Self::Catalog => {
+                let binary = matches!(
+                    header.key_compare_type,
+                    BTreeKeyCompareType::kHFSBinaryCompare
+                );
+                compare_catalog_keys(a, b, binary)
+            }
+            Self::Extents => {
+                let fields = |key: &[u8]| {
+                    key.get(..10).map(|key| {
+                        (
+                            u32::from_be_bytes(key[2..6].try_into().unwrap()),
+                            key[0],
+                            u32::from_be_bytes(key[6..10].try_into().unwrap()),
+                        )
+                    })
+                };
+                fields(a).cmp(&fields(b))
+            }
+        }
+    }
+}
+
+fn compare_catalog_keys(a: &[u8], b: &[u8], binary: bool) -> Ordering {
+    let parent = |key: &[u8]| {
+        key.get(..4)
+            .map(|id| u32::from_be_bytes(id.try_into().unwrap()))
+    };
+    let name = |key: &[u8]| {
+        key.get(6..)
+            .unwrap_or_default()
+            .chunks_exact(2)
+            .map(|c| u16::from_be_bytes([c[0], c[1]]))
+            .filter_map(move |c| if binary { Some(c) } else { fold_case(c) })
+            .collect::<Vec<_>>()
+    };
+    parent(a)
+        .cmp(&parent(b))
+        .then_with(|| name(a).cmp(&name(b)))
+}
+
+/// Approximates the case folding of `FastUnicodeCompare` in TN1150 > Case
+/// Folding: ignorable characters are skipped, and NUL sorts after everything.
+fn fold_case(c: u16) -> Option<u16> {
+    match c {
+        0x0000 => Some(0xFFFF),
+        0x200C..=0x200F | 0x202A..=0x202E | 0x206A..=0x206F | 0xFEFF => None,
+        _ => {
+            let lower = char::from_u32(c as u32).and_then(|upper| {
+                let mut lower = upper.to_lowercase();
+                match (lower.next(), lower.next()) {
+                    (Some(lower), None) => u16::try_from(lower as u32).ok(),
+                    _ => None,
+                }
+            });
+            Some(lower.unwrap_or(c))
+        }
+    }
+}
+
+/// Check a B-tree's structure: node descriptors and heights, key order,
+/// sibling links on every level, and the header's counts against the nodes
+/// that are reachable from the root.
+fn check_btree(
+    stream: &mut Cursor<Vec<u8>>,
+    key_order: KeyOrder,
+    check: &'static str,
+    report: &mut CheckReport,
+) -> Result<(), io::Error> {
+    stream.seek(SeekFrom::Start(0))?;
+    let (header_descriptor, header) = match read_btree_header(stream, 0) {
+        Ok(header) => header,
+        Err(err) => {
+            report.push(
+                Severity::Error,
+                check,
+                format!("Unreadable header node: {err}"),
+            );
+            return Ok(());
+        }
+    };
+    let node_size = header.node_size as usize;
+    let available_nodes = (stream.get_ref().len() / node_size) as u32;
+    if header.total_nodes > available_nodes {
+        report.push(
+            Severity::Error,
+            check,
+            format!(
+                "Header claims {} nodes, but only {available_nodes} are present",
+                header.total_nodes
+            ),
+        );
+    }
+    let total_nodes = header.total_nodes.min(available_nodes);
+
+    // Node allocation map.
+    let node_map = match read_btree_map(stream, node_size) {
+        Ok((node_map, diagnostics)) => {
+            for diagnostic in diagnostics {
+                report.push(Severity::Error, check, diagnostic.to_string());
+            }
+            Some(node_map)
+        }
+        Err(err) => {
+            report.push(
+                Severity::Error,
+                check,
+                format!("Unreadable node allocation map: {err}"),
+            );
+            None
+        }
+    };
+    let is_used = |node: u32| {
+        node_map
+            .as_ref()
+            .and_then(|node_map| node_map.is_node_used(node))
+    };
+    let used_nodes = (0..header.total_nodes)
+        .filter(|&node| is_used(node) == Some(true))
+        .count() as u64;
+    let expected_used =
+        header.total_nodes as u64 - header.free_nodes.min(header.total_nodes) as u64;
+    if node_map.is_some() && used_nodes != expected_used {
+        report.push(
+            Severity::Warning,
+            check,
+            format!(
+                "Header counts {} free of {} nodes, but the map marks {used_nodes} in use",
+                header.free_nodes, header.total_nodes
+            ),
+        );
+    }
+
+    // The header and map nodes are in use without being part of the tree.
+    let mut reached = HashSet::from([0]);
+    let mut next_map_node = header_descriptor.forward_link;
+    while next_map_node != 0 && next_map_node < total_nodes && reached.insert(next_map_node) {
+        next_map_node = match read_node_at(stream, next_map_node, node_size)
+            .and_then(|node| split_btree_node(&node))
+        {
+            Ok((descriptor, _)) => descriptor.forward_link,
+            Err(_) => break,
+        };
+    }
+
+    // Walk the tree from the root, depth first and left to right, so that
+    // keys on each level are visited in order.
+    let mut levels = BTreeMap::<u8, Vec<(u32, BTreeNodeDescriptor)>>::new();
+    let mut last_keys = HashMap::<u8, Vec<u8>>::new();
+    let mut leaf_records = 0u64;
+    let mut stack = Vec::new();
+    if header.tree_depth > 0 {
+        stack.push((header.root_node, header.tree_depth as u8, None::<Vec<u8>>));
+    } else if header.root_node != 0 || header.leaf_records != 0 {
+        report.push(
+            Severity::Error,
+            check,
+            "Tree depth is zero, but the tree has a root or records",
+        );
+    }
+
+    while let Some((node, height, index_key)) = stack.pop() {
+        let offset = node as u64 * node_size as u64;
+        let mut push = |severity, reason: String| {
+            report.push(
+                severity,
+                check,
+                format!("node {node} at offset {offset:#x}: {reason}"),
+            );
+        };
+
+        if node == 0 || node >= total_nodes {
+            push(Severity::Error, "Node number is out of range".to_string());
+            continue;
+        }
+        if !reached.insert(node) {
+            push(
+                Severity::Error,
+                "Node is referenced more than once".to_string(),
+            );
+            continue;
+        }
+        if is_used(node) == Some(false) {
+            push(
+                Severity::Error,
+                "Node is in the tree but marked free".to_string(),
+            );
+        }
+
+        let (descriptor, records) =
+            match read_node_at(stream, node, node_size).and_then(|data| split_btree_node(&data)) {
+                Ok(node) => node,
+                Err(err) => {
+                    push(Severity::Error, err.to_string());
+                    continue;
+                }
+            };
+        let expected_kind = match height {
+            1 => BTreeNodeKind::kBTLeafNode,
+            _ => BTreeNodeKind::kBTIndexNode,
+        };
+        if descriptor.kind != expected_kind || descriptor.height != height {
+            push(
+                Severity::Error,
+                format!(
+                    "Expected {expected_kind:?} at height {height}, found {:?} at height {}",
+                    descriptor.kind, descriptor.height
+                ),
+            );
+            continue;
+        }
+        if records.is_empty() {
+            push(Severity::Error, "Node has no records".to_string());
+        }
+
+        let mut children = Vec::new();
+        for record in records {
+            let data = match record.data {
+                Ok(data) => data,
+                Err(err) => {
+                    push(Severity::Error, format!("record {}: {err}", record.index));
+                    continue;
+                }
+            };
+            let key_length = data
+                .get(..2)
+                .map(|length| u16::from_be_bytes([length[0], length[1]]) as usize);
+            let Some(key) = key_length.and_then(|length| data.get(2..2 + length)) else {
+                push(
+                    Severity::Error,
+                    format!("record {}: Key extends past the record", record.index),
+                );
+                continue;
+            };
+
+            if record.index == 0
+                && let Some(index_key) = &index_key
+                && index_key.as_slice() != key
+            {
+                push(
+                    Severity::Error,
+                    "First key does not match the index record pointing to it".to_string(),
+                );
+            }
+            if let Some(last_key) = last_keys.get(&height)
+                && key_order.compare(&header, last_key, key) != Ordering::Less
+            {
+                push(
+                    Severity::Error,
+                    format!("record {}: Key is out of order", record.index),
+                );
+            }
+            last_keys.insert(height, key.to_vec());
+
+            if height == 1 {
+                leaf_records += 1;
+            } else {
+                // Keys are padded to an even length before the child pointer.
+                let pointer_offset = (2 + key.len()).next_multiple_of(2);
+                match data.get(pointer_offset..pointer_offset + 4) {
+                    Some(pointer) => children.push((
+                        u32::from_be_bytes(pointer.try_into().unwrap()),
+                        height - 1,
+                        Some(key.to_vec()),
+                    )),
+                    None => push(
+                        Severity::Error,
+                        format!("record {}: Missing child pointer", record.index),
+                    ),
+                }
+            }
+        }
+        stack.extend(children.into_iter().rev());
+        levels.entry(height).or_default().push((node, descriptor));
+    }
+
+    // Each level is a doubly linked list, in key order.
+    for (height, nodes) in &levels {
+        for (i, (node, descriptor)) in nodes.iter().enumerate() {
+            let previous = i.checked_sub(1).map_or(0, |i| nodes[i].0);
+            let next = nodes.get(i + 1).map_or(0, |(next, _)| *next);
+            if descriptor.backward_link != previous || descriptor.forward_link != next {
+                report.push(
+                    Severity::Error,
+                    check,
+                    format!(
+                        "node {node} at height {height}: Links to {} and {}, expected {previous} and {next}",
+                        descriptor.backward_link, descriptor.forward_link
+                    ),
+                );
+            }
+        }
+    }
+
+    let leaves = levels.get(&1).map(Vec::as_slice).unwrap_or_default();
+    let first_leaf = leaves.first().map_or(0, |(node, _)| *node);
+    let last_leaf = leaves.last().map_or(0, |(node, _)| *node);
+    if header.tree_depth > 0
+        && (header.first_leaf_node != first_leaf || header.last_leaf_node != last_leaf)
+    {
+        report.push(
+            Severity::Error,
+            check,
+            format!(
+                "Header names leaves {} to {}, but the tree's leaves run from {first_leaf} to {last_leaf}",
+                header.first_leaf_node, header.last_leaf_node
+            ),
+        );
+    }
+    if header.leaf_records as u64 != leaf_records {
+        report.push(
+            Severity::Warning,
+            check,
+            format!(
+                "Header counts {} leaf records, but the tree holds {leaf_records}",
+                header.leaf_records
+            ),
+        );
+    }
+
+    let unreachable = (0..total_nodes)
+        .filter(|node| is_used(*node) == Some(true) && !reached.contains(node))
+        .collect::<Vec<_>>();
+    if !unreachable.is_empty() {
+        let listed = unreachable.iter().take(MAX_LISTED_NODES).join(", ");
+        let more = if unreachable.len() > MAX_LISTED_NODES {
+            ", ..."
+        } else {
+            ""
+        };
+        report.push(
+            Severity::Warning,
+            check,
+            format!(
+                "{} nodes are marked in use but unreachable: {listed}{more}",
+                unreachable.len()
+            ),
+        );
+    }
+
+    Ok(())
+}
+
+/// The name in a catalog key, as UTF-16 code units.
+fn key_name(key: &[u8]) -> Vec<u16> {
+    key.get(6..)
+        .unwrap_or_default()
+        .chunks_exact(2)
+        .map(|c| u16::from_be_bytes([c[0], c[1]]))
+        .collect()
+}
+
+/// Check that records and threads point at each other, folder valences,
+/// the volume's file and folder counts, and CNID allocation.
+fn check_catalog_records(volume_header: &VolumeHeader, map: &CatalogMap, report: &mut CheckReport) {
+    let mut owners = HashMap::<CatalogNodeId, &BTreeKey>::new();
+    let mut children = HashMap::<CatalogNodeId, u32>::new();
+    let (mut file_count, mut folder_count) = (0u32, 0u32);
+
+    for (key, record) in map {
+        // Salvaged records can have keys too short to hold a parent CNID.
+        let Some(parent) = key
+            .get(..4)
+            .map(|id| u32::from_be_bytes(id.try_into().unwrap()))
+        else {
+            report.push(
+                Severity::Error,
+                "catalog-btree",
+                format!(
+                    "Record with a {}-byte key is too short to have a parent",
+                    key.len()
+                ),
+            );
+            continue;
+        };
+        let (cnid, is_folder) = match record {
+            CatalogLeafRecord::Folder(folder) => (folder.folder_id, true),
+            CatalogLeafRecord::File(file) => (file.file_id, false),
+            CatalogLeafRecord::FolderThread(thread) | CatalogLeafRecord::FileThread(thread) => {
+                // Threads are keyed by their item's CNID, and name its parent.
+                let target = child_key(thread.parent_id, &thread.node_name.unicode);
+                let points_back = match map.get(&target) {
+                    Some(CatalogLeafRecord::Folder(folder)) => folder.folder_id == parent,
+                    Some(CatalogLeafRecord::File(file)) => file.file_id == parent,
+                    _ => false,
+                };
+                if !points_back {
+                    report.push(
+                        Severity::Error,
+                        "threads",
+                        format!("Thread for CNID {parent} does not point at its record"),
+                    );
+                }
+                continue;
+            }
+        };
+
+        if let Some(previous) = owners.insert(cnid, key)
+            && previous != key
+        {
+            report.push(
+                Severity::Error,
+                "cnids",
+                format!("CNID {cnid} is used by more than one record"),
+            );
+        }
+        if is_folder {
+            folder_count += 1;
+        } else {
+            file_count += 1;
+        }
+        *children.entry(parent).or_default() += 1;
+
+        // TN1150 makes file threads optional, although Mac OS X always
+        // creates them.
+        let thread = map.get(&cnid_to_key(cnid));
+        let name = key_name(key);
+        let (severity, problem) = match (thread, is_folder) {
+            (None, true) => (Severity::Error, "has no thread record"),
+            (None, false) => (Severity::Warning, "has no thread record"),
+            (Some(CatalogLeafRecord::FolderThread(_)), false)
+            | (Some(CatalogLeafRecord::FileThread(_)), true) => {
+                (Severity::Error, "has the wrong kind of thread record")
+            }
+            (
+                Some(CatalogLeafRecord::FolderThread(thread))
+                | Some(CatalogLeafRecord::FileThread(thread)),
+                _,
+            ) if thread.parent_id != parent || thread.node_name.unicode != name => {
+                (Severity::Error, "has a thread record naming another item")
+            }
+            _ => continue,
+        };
+        report.push(
+            severity,
+            "threads",
+            format!(
+                "CNID {cnid} {:?} {problem}",
+                String::from_utf16_lossy(&name)
+            ),
+        );
+    }
+
+    for record in map.values() {
+        if let CatalogLeafRecord::Folder(folder) = record {
+            let count = children.get(&folder.folder_id).copied().unwrap_or(0);
+            if count != folder.valence {
+                report.push(
+                    Severity::Warning,
+                    "valence",
+                    format!(
+                        "Folder {} has valence {}, but holds {count} items",
+                        folder.folder_id, folder.valence
+                    ),
+                );
+            }
+        }
+    }
+
+    // The root folder is not included in the volume's folder count.
+    let folder_count = folder_count.saturating_sub(owners.contains_key(&ROOT_FOLDER_ID) as u32);
+    if file_count != volume_header.file_count || folder_count != volume_header.folder_count {
+        report.push(
+            Severity::Warning,
+            "counts",
+            format!(
+                "Volume header counts {} files and {} folders, but the catalog holds {file_count} and {folder_count}",
+                volume_header.file_count, volume_header.folder_count
+            ),
+        );
+    }
+
+    if let Some(max_cnid) = owners.keys().max()
+        && *max_cnid >= volume_header.next_catalog_id
+    {
+        let (severity, reason) =
+            if volume_header.has_attribute(VolumeAttributeBit::CatalogNodeIdsReused) {
+                (Severity::Info, "CNIDs are being reused")
+            } else {
+                (Severity::Error, "new items may reuse existing CNIDs")
+            };
+        report.push(
+            severity,
+            "cnids",
+            format!(
+                "Next catalog ID {} is not above the largest CNID {max_cnid}; {reason}",
+                volume_header.next_catalog_id
+            ),
+        );
+    }
+}
+
+/// Check that each fork's block count matches its extents, including those
+/// in overflow records, and covers its logical size.
+fn check_fork_sizes(
+    volume_header: &VolumeHeader,
+    map: &CatalogMap,
+    overflow: &[OverflowExtents],
+    report: &mut CheckReport,
+) {
+    let special_files = [
+        (
+            StandardCnid::kHFSAllocationFileID,
+            &volume_header.allocation_file,
+        ),
+        (StandardCnid::kHFSExtentsFileID, &volume_header.extents_file),
+        (StandardCnid::kHFSCatalogFileID, &volume_header.catalog_file),
+        (
+            StandardCnid::kHFSAttributesFileID,
+            &volume_header.attributes_file,
+        ),
+        (StandardCnid::kHFSStartupFileID, &volume_header.startup_file),
+    ]
+    .map(|(cnid, fork)| (cnid as CatalogNodeId, ForkKind::Data, fork));
+    let files = map
+        .values()
+        .filter_map(|record| match record {
+            CatalogLeafRecord::File(file) => Some(file),
+            _ => None,
+        })
+        .flat_map(|file| {
+            [
+                (file.file_id, ForkKind::Data, &file.data_fork),
+                (file.file_id, ForkKind::Resource, &file.resource_fork),
+            ]
+        });
+
+    let block_size = volume_header.block_size as u64;
+    for (cnid, kind, fork) in special_files.into_iter().chain(files) {
+        let blocks = fork
+            .extents
+            .iter()
+            .chain(&fork_overflow_extents(overflow, cnid, kind))
+            .map(|extent| extent.block_count as u64)
+            .sum::<u64>();
+        if blocks != fork.total_blocks as u64 {
+            report.push(
+                Severity::Error,
+                "extents",
+                format!(
+                    "CNID {cnid} {kind:?} fork claims {} blocks, but its extents hold {blocks}",
+                    fork.total_blocks
+                ),
+            );
+        }
+        if fork.logical_size > fork.total_blocks as u64 * block_size {
+            report.push(
+                Severity::Error,
+                "extents",
+                format!(
+                    "CNID {cnid} {kind:?} fork is {} bytes, but only {} blocks are allocated",
+                    fork.logical_size, fork.total_blocks
+                ),
+            );
+        }
+    }
+}
+
+/// Check the allocation bitmap against every extent, and its free count
+/// against the volume header.
+fn check_allocation(
+    volume: &mut (impl Read + Seek),
+    volume_header: &VolumeHeader,
+    map: &CatalogMap,
+    overflow: &[OverflowExtents],
+    report: &mut CheckReport,
+) {
+    const CHECK: &str = "bitmap";
+
+    let bitmap = match AllocationBitmap::read(volume, volume_header, overflow) {
+        Ok(bitmap) => bitmap,
+        Err(err) => {
+            report.push(
+                Severity::Error,
+                CHECK,
+                format!("Unreadable allocation file: {err}"),
+            );
+            return;
+        }
+    };
+    let mut ownership = BlockOwnership::from_catalog(volume_header, map);
+    ownership.add_overflow_extents(overflow);
+
+    let allocation = verify_allocation(volume_header, &bitmap, &ownership);
+    if allocation.bitmap_free_blocks != allocation.header_free_blocks {
+        report.push(
+            Severity::Warning,
+            CHECK,
+            format!(
+                "Volume header counts {} free blocks, but the bitmap has {}",
+                allocation.header_free_blocks, allocation.bitmap_free_blocks
+            ),
+        );
+    }
+    for problem in allocation.problems {
+        let (severity, check) = match problem.kind {
+            BlockProblemKind::AllocatedUnowned => (Severity::Warning, CHECK),
+            BlockProblemKind::OwnedFree | BlockProblemKind::Uncovered => (Severity::Error, CHECK),
+            BlockProblemKind::MultiplyOwned | BlockProblemKind::OutOfRange => {
+                (Severity::Error, "extents")
+            }
+        };
+        report.push(severity, check, problem.to_string());
+    }
+}
+
+/// Report whether the journal holds transactions that were never replayed,
+/// in which case the rest of the volume may be out of date.
+fn check_journal(
+    volume: &mut (impl Read + Seek),
+    volume_header: &VolumeHeader,
+    report: &mut CheckReport,
+) {
+    const CHECK: &str = "journal";
+
+    if !volume_header.has_attribute(VolumeAttributeBit::Journaled) {
+        return;
+    }
+    let info_block = match read_journal_info_block(volume, volume_header) {
+        Ok(info_block) => info_block,
+        Err(err) => {
+            report.push(
+                Severity::Error,
+                CHECK,
+                format!("Unreadable journal info block: {err}"),
+            );
+            return;
+        }
+    };
+    if info_block.flags.on_other_device {
+        report.push(
+            Severity::Info,
+            CHECK,
+            "Journal is stored on another device, and was not checked",
+        );
+        return;
+    }
+
+    match read_journal_header(volume, &info_block) {
+        Ok(Some(journal)) if journal.needs_replay() => report.push(
+            Severity::Warning,
+            CHECK,
+            format!(
+                "Journal holds transactions from {:#x} to {:#x} that have not been replayed",
+                journal.start, journal.end
+            ),
+        ),
+        Ok(Some(_)) => {}
+        Ok(None) => report.push(Severity::Info, CHECK, "Journal location is unknown"),
+        Err(err) => report.push(
+            Severity::Error,
+            CHECK,
+            format!("Unreadable journal header: {err}"),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::parse_catalog_leaf;
+    use std::io::Cursor;
+
+    #[test]
+    fn short_catalog_keys_are_findings() {
+        let mut volume = vec![0u8; 1536];
+        volume[1024..1026].copy_from_slice(b"H+");
+        let header = VolumeHeader::read_from(&mut Cursor::new(volume)).unwrap();
+        // A folder thread record under a 2-byte key.
+        let mut record = vec![0, 2, 0, 0];
+        record.extend([0, 3, 0, 0, 0, 0, 0, 1, 0, 0]);
+        let (key, leaf) = parse_catalog_leaf(&record).unwrap();
+        let map = CatalogMap::from([(key, leaf)]);
+
+        let mut report = CheckReport::default();
+        check_catalog_records(&header, &map, &mut report);
+        assert!(report.findings.iter().any(|finding| {
+            finding.severity == Severity::Error && finding.message.contains("2-byte key")
+        }));
+    }
+}
@@ -0,0 +1,361 @@
+//! Regions of the volume whose contents cannot be trusted.
+//!
+//! Images taken with GNU ddrescue come with a mapfile recording which regions
+//! were rescued. Everything else holds whatever the imaging tool filled it
+//! with, usually zeroes. Blocks that the file system itself spared are
+//! recorded as extents of the bad block file in the Extents Overflow File,
+//! described in TN1150 > Bad Block File.
+
+use crate::extents::OverflowExtents;
+use crate::*;
+use std::io;
+
+/// Parse a decimal or `0x`-prefixed hexadecimal number.
+fn parse_number(field: &str) -> Result<u64, io::Error> {
+    let parsed = match field
+        .strip_prefix("0x")
+        .or_else(|| field.strip_prefix("0X"))
+    {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => field.parse(),
+    };
+    parsed.map_err(|_| invalid_data(format!("Invalid number {field:?}")))
+}
+
+/// Byte ranges of the volume that are unreadable or known to be bad, sorted
+/// and merged.
+#[derive(Debug, Clone, Default)]
+pub struct DamageMap {
+    ranges: Vec<(u64, u64)>,
+}
+
+impl DamageMap {
+    /// Build a map from `(offset, length)` pairs, in any order.
+    pub fn from_ranges(ranges: impl IntoIterator<Item = (u64, u64)>) -> Self {
+        let mut map = Self::default();
+        map.add_ranges(ranges);
+        map
+    }
+
+    /// Parse a GNU ddrescue mapfile. Every region not marked as finished
+    /// (`+`) is treated as damaged.
+    pub fn parse_ddrescue_mapfile(mapfile: &str) -> Result<Self, io::Error> {
+        let mut lines = mapfile
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+
+        // The first line holds the position and status of the rescue itself,
+        // optionally followed by the pass number.
+        let status_line = lines
+            .next()
+            .ok_or_else(|| invalid_data("Mapfile has no status line"))?;
+        match status_line.split_whitespace().collect::<Vec<_>>()[..] {
+            [position, _status] | [position, _status, _] => parse_number(position)?,
+            _ => {
+                return Err(invalid_data(format!(
+                    "Malformed status line {status_line:?}"
+                )));
+            }
+        };
+
+        let mut ranges = Vec::new();
+        for line in lines {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let [position, size, status] = fields[..] else {
+                return Err(invalid_data(format!("Malformed mapfile line {line:?}")));
+            };
+            if !matches!(status, "?" | "*" | "/" | "-" | "+") {
+                return Err(invalid_data(format!("Unknown block status in {line:?}")));
+            }
+            if status != "+" {
+                ranges.push((parse_number(position)?, parse_number(size)?));
+            }
+        }
+        Ok(Self::from_ranges(ranges))
+    }
+
+    /// Parse a list of bad ranges, one `offset length` pair per line in
+    /// bytes. Blank lines and lines starting with `#` are ignored.
+    pub fn parse_range_list(list: &str) -> Result<Self, io::Error> {
+        let ranges = list
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(
+                |line| match line.split_whitespace().collect::<Vec<_>>()[..] {
+                    [offset, length] => Ok((parse_number(offset)?, parse_number(length)?)),
+                    _ => Err(invalid_data(format!("Malformed range {line:?}"))),
+                },
+            )
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::from_ranges(ranges))
+    }
+
+    /// Add the ranges of another map.
+    pub fn merge(&mut self, other: &DamageMap) {
+        self.add_ranges(
+            other
+                .ranges
+                .iter()
+                .map(|&(start, end)| (start, end - start)),
+        );
+    }
+
+    /// Convert a map of image offsets to offsets within a volume that starts
+    /// `offset` bytes into the image. Ranges before the volume are dropped,
+    /// and those straddling its start are clipped to it.
+    pub fn relative_to(&self, offset: u64) -> Self {
+        Self {
+            ranges: self
+                .ranges
+                .iter()
+                .filter(|(_, end)| *end > offset)
+                .map(|(start, end)| (start.saturating_sub(offset), end - offset))
+                .collect(),
+        }
+    }
+
+    /// Add the blocks spared by the file system, which are owned by the bad
+    /// block file.
+    pub fn add_bad_blocks(&mut self, overflow: &[OverflowExtents], block_size: usize) {
+        let bad_block_file = StandardCnid::kHFSBadBlockFileID as CatalogNodeId;
+        let block_size = block_size as u64;
+        self.add_ranges(
+            overflow
+                .iter()
+                .filter(|record| record.key.file_id == bad_block_file)
+                .flat_map(|record| record.extents)
+                .map(|extent| {
+                    (
+                        extent.start_block as u64 * block_size,
+                        extent.block_count as u64 * block_size,
+                    )
+                }),
+        );
+    }
+
+    fn add_ranges(&mut self, ranges: impl IntoIterator<Item = (u64, u64)>) {
+        self.ranges.extend(
+            ranges
+                .into_iter()
+                .filter(|(_, length)| *length != 0)
+                .map(|(offset, length)| (offset, offset.saturating_add(length))),
+        );
+        self.ranges.sort_unstable();
+
+        let mut merged: Vec<(u64, u64)> = Vec::with_capacity(self.ranges.len());
+        for &(start, end) in &self.ranges {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        self.ranges = merged;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Damaged `(start, end)` byte ranges, sorted and non-overlapping.
+    pub fn ranges(&self) -> &[(u64, u64)] {
+        &self.ranges
+    }
+
+    /// The damaged parts of a byte range, clipped to it.
+    pub fn overlapping(&self, start: u64, end: u64) -> impl Iterator<Item = (u64, u64)> + '_ {
+        let first = self.ranges.partition_point(|range| range.1 <= start);
+        self.ranges[first..]
+            .iter()
+            .take_while(move |range| range.0 < end)
+            .map(move |range| (range.0.max(start), range.1.min(end)))
+    }
+}
+
+/// Damage to one extent of a fork.
+#[derive(Debug, Clone)]
+pub struct ExtentDamage {
+    /// Position of the extent in the fork, counting overflow extents.
+    pub index: usize,
+    pub extent: ExtentDescriptor,
+    /// Damaged bytes within the fork's logical size.
+    pub damaged_bytes: u64,
+}
+
+/// Which parts of a fork touch damaged regions.
+#[derive(Debug, Clone, Default)]
+pub struct ForkDamage {
+    pub extents: Vec<ExtentDamage>,
+}
+
+impl ForkDamage {
+    pub fn damaged_bytes(&self) -> u64 {
+        self.extents.iter().map(|extent| extent.damaged_bytes).sum()
+    }
+
+    /// Whether every byte of the fork can be read.
+    pub fn is_complete(&self) -> bool {
+        self.damaged_bytes() == 0
+    }
+}
+
+/// Find the extents of a fork that touch damaged regions. Only bytes within
+/// the logical size are counted, as the rest of the last block is slack.
+pub fn fork_damage(
+    damage: &DamageMap,
+    block_size: usize,
+    extents: &[ExtentDescriptor],
+    logical_size: u64,
+) -> ForkDamage {
+    let block_size = block_size as u64;
+    let mut fork_offset = 0u64;
+    let mut damaged = Vec::new();
+    for (index, extent) in extents.iter().enumerate() {
+        if fork_offset >= logical_size {
+            break;
+        }
+        let length = (extent.block_count as u64 * block_size).min(logical_size - fork_offset);
+        let start = extent.start_block as u64 * block_size;
+        let damaged_bytes = damage
+            .overlapping(start, start + length)
+            .map(|(start, end)| end - start)
+            .sum::<u64>();
+        if damaged_bytes != 0 {
+            damaged.push(ExtentDamage {
+                index,
+                extent: *extent,
+                damaged_bytes,
+            });
+        }
+        fork_offset += length;
+    }
+    ForkDamage { extents: damaged }
+}
+
+/// Overwrite the damaged parts of a buffer read from the volume. The pattern
+/// repeats from the start of the fork, so the same file offset always gets
+/// the same byte; an empty pattern fills with zeroes.
+pub fn fill_damaged(
+    buf: &mut [u8],
+    volume_offset: u64,
+    fork_offset: u64,
+    damage: &DamageMap,
+    pattern: &[u8],
+) -> u64 {
+    let mut filled = 0;
+    for (start, end) in damage.overlapping(volume_offset, volume_offset + buf.len() as u64) {
+        let range = (start - volume_offset) as usize..(end - volume_offset) as usize;
+        fill_range(buf, range.clone(), fork_offset, pattern);
+        filled += range.len() as u64;
+    }
+    filled
+}
+
+/// Fill part of a buffer with the pattern, aligned to the fork offset of the
+/// buffer's first byte.
+pub(crate) fn fill_range(
+    buf: &mut [u8],
+    range: std::ops::Range<usize>,
+    fork_offset: u64,
+    pattern: &[u8],
+) {
+    for i in range {
+        buf[i] = match pattern.len() {
+            0 => 0,
+            length => pattern[((fork_offset + i as u64) % length as u64) as usize],
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ddrescue_mapfile() -> io::Result<()> {
+        let mapfile = "\
+# Mapfile. Created by GNU ddrescue version 1.27
+# current_pos  current_status  current_pass
+0x00002000     +               1
+#      pos        size  status
+0x00000000  0x00001000  +
+0x00001000  0x00000200  -
+0x00001200  0x00000E00  ?
+0x00002000  0x00001000  +
+0x00003000  0x00000400  /
+";
+        let map = DamageMap::parse_ddrescue_mapfile(mapfile)?;
+        assert_eq!(map.ranges(), &[(0x1000, 0x2000), (0x3000, 0x3400)]);
+        Ok(())
+    }
+
+    #[test]
+    fn malformed_ddrescue_mapfile() {
+        for mapfile in [
+            "",
+            "# only comments\n",
+            "0x0 +\n0x0 0x200\n",
+            "0x0 +\n0x0 0x200 - extra\n",
+            "0x0 +\n0x0 0x200 x\n",
+            "0x0 +\nzero 0x200 -\n",
+            "0x0 +\n0x0 0xfffffffffffffffff -\n",
+            "0x0 +\n-1 0x200 -\n",
+            "+ 0x0\n0x0 0x200 -\n",
+            "0x0\n0x0 0x200 -\n",
+        ] {
+            let error = DamageMap::parse_ddrescue_mapfile(mapfile).err();
+            assert_eq!(
+                error.map(|error| error.kind()),
+                Some(io::ErrorKind::InvalidData),
+                "{mapfile:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn range_list() -> io::Result<()> {
+        let map = DamageMap::parse_range_list("# bad\n4096 512\n\n0x0 0x200\n4608 0\n")?;
+        assert_eq!(map.ranges(), &[(0, 512), (4096, 4608)]);
+
+        for list in ["4096\n", "4096 512 1\n", "4096 -512\n", "0x 512\n"] {
+            let error = DamageMap::parse_range_list(list).err();
+            assert_eq!(
+                error.map(|error| error.kind()),
+                Some(io::ErrorKind::InvalidData),
+                "{list:?}"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn relative_to_partition() {
+        // A partition starting 1 MiB into the image.
+        let offset = 0x10_0000;
+        let map = DamageMap::from_ranges([(0x200, 0x200), (0xF_FE00, 0x400), (0x10_1000, 0x200)]);
+        let relative = map.relative_to(offset);
+        assert_eq!(relative.ranges(), &[(0, 0x200), (0x1000, 0x1200)]);
+        assert_eq!(map.relative_to(0).ranges(), map.ranges());
+        assert!(map.relative_to(u64::MAX).is_empty());
+
+        let mut buf = [1u8; 0x400];
+        assert_eq!(fill_damaged(&mut buf, 0xE00, 0, &relative, &[]), 0x200);
+        assert!(buf[..0x200].iter().all(|&byte| byte == 1));
+        assert!(buf[0x200..].iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn overlapping_ranges_are_merged_and_clipped() {
+        let map = DamageMap::from_ranges([(100, 50), (0, 10), (120, 100), (u64::MAX - 1, 10)]);
+        assert_eq!(
+            map.ranges(),
+            &[(0, 10), (100, 220), (u64::MAX - 1, u64::MAX)]
+        );
+        assert_eq!(
+            map.overlapping(5, 150).collect::<Vec<_>>(),
+            vec![(5, 10), (100, 150)]
+        );
+        assert_eq!(map.overlapping(10, 100).count(), 0);
+    }
+}
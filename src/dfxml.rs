@@ -0,0 +1,393 @@
+//! Reports in Digital Forensics XML, describing the image, the volume, and
+//! each file and folder with where its contents are stored. The elements
+//! follow those written by `fiwalk`, so that DFXML tools can read them.
+
+use crate::allocation::ForkKind;
+use crate::catalog::{CatalogMap, file_special, record_for_cnid};
+use crate::damage::DamageMap;
+use crate::export::catalog_entries;
+use crate::extents::{OverflowExtents, fork_overflow_extents};
+use crate::fork::{ForkReader, HashAlgorithm, hash_reader};
+use crate::query::ItemKind;
+use crate::recovery::orphan_path;
+use crate::timeline::type_letter;
+use crate::volume::Volume;
+use crate::*;
+use std::io::{self, Read, Seek, Write};
+
+const DFXML_NAMESPACE: &str = "http://www.forensicswiki.org/wiki/Category:Digital_Forensics_XML";
+const DFXML_VERSION: &str = "1.2.0";
+
+/// Where the volume came from, and what to include in the report.
+#[derive(Debug, Clone, Default)]
+pub struct DfxmlOptions {
+    /// Path of the image, as given by the user.
+    pub image_filename: String,
+    pub image_size: Option<u64>,
+    /// Offset of the volume from the start of the image, added to offsets
+    /// within the volume to give `img_offset`.
+    pub volume_offset: u64,
+    /// The command that produced the report.
+    pub command_line: Option<String>,
+    /// Also describe records left behind by deleted items, as unallocated.
+    pub deleted: bool,
+    /// Digests of each file's data fork to include.
+    pub hashes: Vec<HashAlgorithm>,
+}
+
+/// Escape text for use in element content or attribute values. Characters
+/// not allowed in XML 1.0, such as the NULs in the metadata folder names, are
+/// replaced with `^` as the Sleuth Kit does.
+fn xml_text(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() || c == '\u{fffe}' || c == '\u{ffff}' => escaped.push('^'),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A contiguous piece of a fork. Offsets are in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRun {
+    pub file_offset: u64,
+    /// Offset from the start of the volume.
+    pub fs_offset: u64,
+    /// Offset from the start of the image.
+    pub img_offset: u64,
+    pub len: u64,
+}
+
+/// Where each part of a fork is stored, in file order. The last run ends at
+/// the fork's logical size rather than the end of its last block.
+pub fn byte_runs(
+    extents: &[ExtentDescriptor],
+    logical_size: u64,
+    block_size: u64,
+    volume_offset: u64,
+) -> Vec<ByteRun> {
+    let mut runs = Vec::new();
+    let mut file_offset = 0;
+    for extent in extents {
+        if file_offset >= logical_size {
+            break;
+        }
+        if extent.block_count == 0 {
+            continue;
+        }
+        let len = (u64::from(extent.block_count) * block_size).min(logical_size - file_offset);
+        let fs_offset = u64::from(extent.start_block) * block_size;
+        runs.push(ByteRun {
+            file_offset,
+            fs_offset,
+            img_offset: volume_offset + fs_offset,
+            len,
+        });
+        file_offset += len;
+    }
+    runs
+}
+
+/// The Sleuth Kit's number for an item's type, as used by `meta_type`.
+fn meta_type(type_letter: char) -> u8 {
+    match type_letter {
+        'r' => 1,
+        'd' => 2,
+        'p' => 3,
+        'c' => 4,
+        'b' => 5,
+        'l' => 6,
+        'h' => 8,
+        'w' => 9,
+        _ => 0,
+    }
+}
+
+/// Write a complete DFXML document describing the volume and every file and
+/// folder in its catalog, other than the root folder.
+pub fn write_dfxml<R: Read + Seek>(
+    output: &mut impl Write,
+    volume: &mut Volume<R>,
+    options: &DfxmlOptions,
+) -> Result<(), io::Error> {
+    let header = &volume.header;
+    let block_size = u64::from(header.block_size);
+    let ftype = match header.signature {
+        [b'H', b'X'] => "hfsx",
+        _ => "hfs+",
+    };
+
+    writeln!(output, "<?xml version='1.0' encoding='UTF-8'?>")?;
+    writeln!(
+        output,
+        "<dfxml xmlns='{DFXML_NAMESPACE}' xmlns:dc='http://purl.org/dc/elements/1.1/' \
+        version='{DFXML_VERSION}'>"
+    )?;
+    writeln!(output, "  <metadata>")?;
+    writeln!(output, "    <dc:type>File System Walk</dc:type>")?;
+    writeln!(output, "  </metadata>")?;
+    writeln!(output, "  <creator version='1.0'>")?;
+    writeln!(output, "    <program>{}</program>", env!("CARGO_PKG_NAME"))?;
+    writeln!(
+        output,
+        "    <version>{}</version>",
+        env!("CARGO_PKG_VERSION")
+    )?;
+    if let Some(command_line) = &options.command_line {
+        writeln!(output, "    <execution_environment>")?;
+        writeln!(
+            output,
+            "      <command_line>{}</command_line>",
+            xml_text(command_line)
+        )?;
+        writeln!(output, "    </execution_environment>")?;
+    }
+    writeln!(output, "  </creator>")?;
+    writeln!(output, "  <source>")?;
+    writeln!(
+        output,
+        "    <image_filename>{}</image_filename>",
+        xml_text(&options.image_filename)
+    )?;
+    if let Some(image_size) = options.image_size {
+        writeln!(output, "    <imagesize>{image_size}</imagesize>")?;
+    }
+    writeln!(output, "  </source>")?;
+
+    writeln!(output, "  <volume offset='{}'>", options.volume_offset)?;
+    writeln!(
+        output,
+        "    <partition_offset>{}</partition_offset>",
+        options.volume_offset
+    )?;
+    writeln!(output, "    <block_size>{block_size}</block_size>")?;
+    writeln!(output, "    <ftype_str>{ftype}</ftype_str>")?;
+    writeln!(
+        output,
+        "    <block_count>{}</block_count>",
+        header.total_blocks
+    )?;
+    writeln!(output, "    <first_block>0</first_block>")?;
+    writeln!(
+        output,
+        "    <last_block>{}</last_block>",
+        u64::from(header.total_blocks).saturating_sub(1)
+    )?;
+    writeln!(
+        output,
+        "    <allocated_only>{}</allocated_only>",
+        u8::from(!options.deleted)
+    )?;
+
+    let root_parent = StandardCnid::kHFSRootParentID as CatalogNodeId;
+    let live = catalog_entries(&volume.catalog)
+        .into_iter()
+        .filter(|entry| entry.parent_id != root_parent)
+        .map(|entry| (entry.path, entry.cnid))
+        .collect::<Vec<_>>();
+    for (path, cnid) in live {
+        let Some(record) = record_for_cnid(&volume.catalog, cnid) else {
+            continue;
+        };
+        let mut fileobject = FileObject {
+            catalog: &volume.catalog,
+            overflow: &volume.overflow,
+            stream: &mut volume.stream,
+            damage: &volume.damage,
+            fill: &volume.fill,
+            block_size,
+            options,
+        };
+        fileobject.write(output, &path, record, false)?;
+    }
+
+    if options.deleted {
+        let (orphans, _) = volume.orphans()?;
+        for orphan in &orphans {
+            let path = orphan_path(&volume.catalog, &orphans, orphan);
+            let path = match path.first() {
+                Some(first) if first.starts_with('#') => path.join("/"),
+                _ => format!("/{}", path.get(1..).unwrap_or_default().join("/")),
+            };
+            let mut fileobject = FileObject {
+                catalog: &volume.catalog,
+                overflow: &volume.overflow,
+                stream: &mut volume.stream,
+                damage: &volume.damage,
+                fill: &volume.fill,
+                block_size,
+                options,
+            };
+            fileobject.write(output, &path, &orphan.leaf, true)?;
+        }
+    }
+
+    writeln!(output, "  </volume>")?;
+    writeln!(output, "</dfxml>")
+}
+
+/// The parts of a volume needed to describe one item, borrowed separately so
+/// that the item's record can stay borrowed from the catalog.
+struct FileObject<'a, R> {
+    catalog: &'a CatalogMap,
+    overflow: &'a [OverflowExtents],
+    stream: &'a mut R,
+    damage: &'a DamageMap,
+    fill: &'a [u8],
+    block_size: u64,
+    options: &'a DfxmlOptions,
+}
+
+impl<R: Read + Seek> FileObject<'_, R> {
+    fn extents(&self, file: &CatalogFile, fork: ForkKind) -> Vec<ExtentDescriptor> {
+        let fork_data = match fork {
+            ForkKind::Data => &file.data_fork,
+            ForkKind::Resource => &file.resource_fork,
+        };
+        fork_data
+            .extents
+            .iter()
+            .copied()
+            .chain(fork_overflow_extents(self.overflow, file.file_id, fork))
+            .collect()
+    }
+
+    fn write(
+        &mut self,
+        output: &mut impl Write,
+        path: &str,
+        record: &CatalogLeafRecord,
+        deleted: bool,
+    ) -> Result<(), io::Error> {
+        let Some(kind) = ItemKind::of(record) else {
+            return Ok(());
+        };
+        let (cnid, permissions, dates, file) = match record {
+            CatalogLeafRecord::File(file) => (
+                file.file_id,
+                &file.permissions,
+                [
+                    file.content_mod_date,
+                    file.attribute_mod_date,
+                    file.access_date,
+                    file.create_date,
+                    file.backup_date,
+                ],
+                Some(file),
+            ),
+            CatalogLeafRecord::Folder(folder) => (
+                folder.folder_id,
+                &folder.permissions,
+                [
+                    folder.content_mod_date,
+                    folder.attribute_mod_date,
+                    folder.access_date,
+                    folder.create_date,
+                    folder.backup_date,
+                ],
+                None,
+            ),
+            _ => return Ok(()),
+        };
+        let type_char = type_letter(permissions, kind);
+        let allocated = u8::from(!deleted);
+
+        writeln!(output, "    <fileobject>")?;
+        writeln!(
+            output,
+            "      <filename>{}</filename>",
+            xml_text(path.strip_prefix('/').unwrap_or(path))
+        )?;
+        writeln!(output, "      <name_type>{type_char}</name_type>")?;
+        writeln!(
+            output,
+            "      <filesize>{}</filesize>",
+            file.map_or(0, |file| file.data_fork.logical_size)
+        )?;
+        writeln!(output, "      <alloc_inode>{allocated}</alloc_inode>")?;
+        writeln!(output, "      <alloc_name>{allocated}</alloc_name>")?;
+        writeln!(output, "      <inode>{cnid}</inode>")?;
+        writeln!(
+            output,
+            "      <meta_type>{}</meta_type>",
+            meta_type(type_char)
+        )?;
+        writeln!(
+            output,
+            "      <mode>{}</mode>",
+            permissions.file_mode & 0o7777
+        )?;
+        let link_count = match file {
+            Some(file) if !deleted => match file_special(self.catalog, file) {
+                BsdSpecial::LinkCount(count) => count,
+                _ => 1,
+            },
+            _ => 1,
+        };
+        writeln!(output, "      <nlink>{link_count}</nlink>")?;
+        writeln!(output, "      <uid>{}</uid>", permissions.owner_id())?;
+        writeln!(output, "      <gid>{}</gid>", permissions.group_id())?;
+        for (name, date) in ["mtime", "ctime", "atime", "crtime", "bkup_time"]
+            .into_iter()
+            .zip(dates)
+        {
+            if date.is_set() {
+                writeln!(output, "      <{name} prec='1'>{date}</{name}>")?;
+            }
+        }
+
+        if let Some(file) = file {
+            for (fork, facet) in [(ForkKind::Data, "data"), (ForkKind::Resource, "resource")] {
+                let logical_size = match fork {
+                    ForkKind::Data => file.data_fork.logical_size,
+                    ForkKind::Resource => file.resource_fork.logical_size,
+                };
+                if logical_size == 0 {
+                    continue;
+                }
+                let extents = self.extents(file, fork);
+                writeln!(output, "      <byte_runs facet='{facet}'>")?;
+                for run in byte_runs(
+                    &extents,
+                    logical_size,
+                    self.block_size,
+                    self.options.volume_offset,
+                ) {
+                    writeln!(
+                        output,
+                        "        <byte_run file_offset='{}' fs_offset='{}' img_offset='{}' len='{}'/>",
+                        run.file_offset, run.fs_offset, run.img_offset, run.len
+                    )?;
+                }
+                writeln!(output, "      </byte_runs>")?;
+            }
+
+            let extents = self.extents(file, ForkKind::Data);
+            for &algorithm in &self.options.hashes {
+                let mut reader = ForkReader::new(
+                    &mut *self.stream,
+                    self.block_size as usize,
+                    extents.iter().copied(),
+                    file.data_fork.logical_size,
+                )
+                .with_damage(self.damage, self.fill);
+                // Forks that cannot be read are described without a digest.
+                if let Ok((_, digest)) = hash_reader(&mut reader, algorithm) {
+                    writeln!(
+                        output,
+                        "      <hashdigest type='{algorithm}'>{digest}</hashdigest>"
+                    )?;
+                }
+            }
+        }
+        writeln!(output, "    </fileobject>")
+    }
+}
@@ -0,0 +1,460 @@
+//! Comparing two volumes, such as images of a disk taken before and after an
+//! incident, or a volume and a directory it was extracted to.
+//!
+//! Items of two volumes are matched by path and CNID together, then by CNID
+//! alone, so that renamed and moved items are recognised, and finally by path
+//! alone, for items that were replaced. Items in a directory are matched by
+//! path. Contents of files that are the same size are compared by hash.
+
+use crate::allocation::ForkKind;
+use crate::catalog::{children, hard_link_target, read_symlink_target};
+use crate::extract::CREATION_DATE_XATTR;
+use crate::filter::Filter;
+use crate::fork::{HashAlgorithm, hash_reader};
+use crate::query::ItemKind;
+use crate::volume::Volume;
+use crate::*;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+/// Where to read the contents of an item.
+#[derive(Debug, Clone)]
+enum Content {
+    None,
+    /// The data fork of a file record, which for hard links is the indirect
+    /// node file.
+    DataFork(CatalogNodeId),
+    File(PathBuf),
+}
+
+/// One file, folder, or symbolic link to compare.
+#[derive(Debug, Clone)]
+pub struct DiffItem {
+    /// Path components below the root folder.
+    pub path: Vec<String>,
+    /// CNID of an item in a volume. Items in a directory have none.
+    pub cnid: Option<CatalogNodeId>,
+    pub kind: ItemKind,
+    /// Size of the data fork of a file, or zero for anything else.
+    pub size: u64,
+    /// Creation date, if known. Extracted items have one if it was recorded
+    /// in [`CREATION_DATE_XATTR`].
+    pub created: Option<Date>,
+    /// Content modification date.
+    pub modified: Date,
+    /// Target of a symbolic link.
+    pub target: Option<String>,
+    content: Content,
+}
+
+/// One side of a comparison.
+pub enum DiffSource<'a, R> {
+    Volume(&'a mut Volume<R>),
+    /// A directory holding the contents of a volume's root folder, as
+    /// written by `extract`.
+    Directory(&'a Path),
+}
+
+impl<R: Read + Seek> DiffSource<'_, R> {
+    /// Every item selected by the filter, sorted by path. Items in a
+    /// directory are only selected by their paths.
+    pub fn items(&mut self, filter: &Filter) -> Result<Vec<DiffItem>, io::Error> {
+        let mut items = match self {
+            Self::Volume(volume) => volume_items(volume, filter)?,
+            Self::Directory(root) => directory_items(root, filter)?,
+        };
+        items.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(items)
+    }
+
+    fn hash(&mut self, item: &DiffItem, algorithm: HashAlgorithm) -> Result<String, io::Error> {
+        match (self, &item.content) {
+            (Self::Volume(volume), Content::DataFork(cnid)) => {
+                let mut reader = volume.fork_reader(*cnid, ForkKind::Data)?;
+                Ok(hash_reader(&mut reader, algorithm)?.1)
+            }
+            (_, Content::File(path)) => Ok(hash_reader(&mut File::open(path)?, algorithm)?.1),
+            _ => Ok(String::new()),
+        }
+    }
+}
+
+/// The files, folders, and symbolic links reachable from the root folder.
+/// Hard links take their size, dates, and contents from their indirect node
+/// file, but keep their own CNID.
+fn volume_items<R: Read + Seek>(
+    volume: &mut Volume<R>,
+    filter: &Filter,
+) -> Result<Vec<DiffItem>, io::Error> {
+    let block_size = volume.block_size();
+    let root = StandardCnid::kHFSRootFolderID as CatalogNodeId;
+    let mut items = Vec::new();
+    let mut pending = vec![(root, Vec::new())];
+    // Damaged catalogs can contain cycles, so each folder is searched once.
+    let mut visited = HashSet::from([root]);
+    while let Some((parent, parent_path)) = pending.pop() {
+        for (name, record) in children(&volume.catalog, parent) {
+            let mut path = parent_path.clone();
+            path.push(name);
+            let item = match record {
+                CatalogLeafRecord::Folder(folder) => {
+                    if visited.insert(folder.folder_id) {
+                        pending.push((folder.folder_id, path.clone()));
+                    }
+                    if !filter.includes_path(&path) {
+                        continue;
+                    }
+                    DiffItem {
+                        path,
+                        cnid: Some(folder.folder_id),
+                        kind: ItemKind::Folder,
+                        size: 0,
+                        created: Some(folder.create_date),
+                        modified: folder.content_mod_date,
+                        target: None,
+                        content: Content::None,
+                    }
+                }
+                CatalogLeafRecord::File(file)
+                    if file.is_symlink() && filter.matches(&path, file) =>
+                {
+                    let target = read_symlink_target(&mut volume.stream, block_size, file)?;
+                    DiffItem {
+                        path,
+                        cnid: Some(file.file_id),
+                        kind: ItemKind::Symlink,
+                        size: 0,
+                        created: Some(file.create_date),
+                        modified: file.content_mod_date,
+                        target: Some(target),
+                        content: Content::None,
+                    }
+                }
+                CatalogLeafRecord::File(file) if filter.matches(&path, file) => {
+                    let contents = hard_link_target(&volume.catalog, file).unwrap_or(file);
+                    DiffItem {
+                        path,
+                        cnid: Some(file.file_id),
+                        kind: ItemKind::File,
+                        size: contents.data_fork.logical_size,
+                        created: Some(contents.create_date),
+                        modified: contents.content_mod_date,
+                        target: None,
+                        content: Content::DataFork(contents.file_id),
+                    }
+                }
+                _ => continue,
+            };
+            items.push(item);
+        }
+    }
+    Ok(items)
+}
+
+/// Everything below a directory, without following symbolic links.
+fn directory_items(root: &Path, filter: &Filter) -> Result<Vec<DiffItem>, io::Error> {
+    let mut items = Vec::new();
+    let mut pending = vec![(root.to_path_buf(), Vec::new())];
+    while let Some((directory, parent_path)) = pending.pop() {
+        for entry in fs::read_dir(&directory)? {
+            let entry = entry?;
+            let mut path = parent_path.clone();
+            path.push(entry.file_name().to_string_lossy().into_owned());
+            let metadata = entry.metadata()?;
+            let file_type = metadata.file_type();
+            if file_type.is_dir() {
+                pending.push((entry.path(), path.clone()));
+            }
+            if !filter.includes_path(&path) {
+                continue;
+            }
+
+            let (kind, size, target, content) = if file_type.is_dir() {
+                (ItemKind::Folder, 0, None, Content::None)
+            } else if file_type.is_symlink() {
+                let target = fs::read_link(entry.path())?;
+                let target = target.to_string_lossy().into_owned();
+                (ItemKind::Symlink, 0, Some(target), Content::None)
+            } else {
+                (
+                    ItemKind::File,
+                    metadata.len(),
+                    None,
+                    Content::File(entry.path()),
+                )
+            };
+            items.push(DiffItem {
+                path,
+                cnid: None,
+                kind,
+                size,
+                created: match kind {
+                    ItemKind::Symlink => None,
+                    _ => recorded_creation_date(&entry.path()),
+                },
+                modified: Date::from_unix(metadata.mtime()).unwrap_or_default(),
+                target,
+                content,
+            });
+        }
+    }
+    Ok(items)
+}
+
+/// The creation date stored by `extract` in an extended attribute.
+fn recorded_creation_date(path: &Path) -> Option<Date> {
+    let mut value = [0u8; 24];
+    let length = rustix::fs::getxattr(path, CREATION_DATE_XATTR, &mut value[..]).ok()?;
+    let seconds = std::str::from_utf8(&value[..length]).ok()?.parse().ok()?;
+    Date::from_unix(seconds)
+}
+
+/// How to compare two sides.
+#[derive(Debug, Clone, Default)]
+pub struct DiffOptions {
+    /// Which items to compare.
+    pub filter: Filter,
+    /// Match items of two volumes by CNID, not only by path. Only useful for
+    /// images of the same volume, as copies get new CNIDs.
+    pub match_cnids: bool,
+    /// How to hash contents, or `None` to compare only sizes and dates.
+    pub hash: Option<HashAlgorithm>,
+}
+
+/// How an item differs between the two sides.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(
+        tag = "change",
+        rename_all = "snake_case",
+        rename_all_fields = "snake_case"
+    )
+)]
+pub enum Change {
+    /// Only on the second side.
+    Added,
+    /// Only on the first side.
+    Removed,
+    /// Given a new name in the same folder.
+    Renamed {
+        from: String,
+    },
+    /// Moved to another folder, and possibly renamed.
+    Moved {
+        from: String,
+    },
+    Resized {
+        from: u64,
+        to: u64,
+    },
+    /// A date changed: `created` or `modified`.
+    Redated {
+        date: &'static str,
+        from: Date,
+        to: Date,
+    },
+    /// The same size, but different contents, or a symbolic link with a new
+    /// target.
+    ContentChanged,
+    /// The contents could not be read to compare them.
+    Unreadable {
+        reason: String,
+    },
+}
+
+/// A change to one item, given by its path on the second side, or on the
+/// first side if it was removed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Difference {
+    pub path: String,
+    pub kind: ItemKind,
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub change: Change,
+}
+
+/// A tab-separated line, such as `moved\t/new\t/old` or
+/// `resized\t/path\t100\t200`.
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.change {
+            Change::Added => write!(f, "added\t{}", self.path),
+            Change::Removed => write!(f, "removed\t{}", self.path),
+            Change::Renamed { from } => write!(f, "renamed\t{}\t{from}", self.path),
+            Change::Moved { from } => write!(f, "moved\t{}\t{from}", self.path),
+            Change::Resized { from, to } => write!(f, "resized\t{}\t{from}\t{to}", self.path),
+            Change::Redated { date, from, to } => {
+                write!(f, "redated\t{}\t{date}\t{from}\t{to}", self.path)
+            }
+            Change::ContentChanged => write!(f, "changed\t{}", self.path),
+            Change::Unreadable { reason } => write!(f, "unreadable\t{}\t{reason}", self.path),
+        }
+    }
+}
+
+fn path_string(path: &[String]) -> String {
+    format!("/{}", path.join("/"))
+}
+
+/// Compare the items of two sides, returning their differences sorted by
+/// path.
+pub fn diff<R1: Read + Seek, R2: Read + Seek>(
+    left: &mut DiffSource<'_, R1>,
+    right: &mut DiffSource<'_, R2>,
+    options: &DiffOptions,
+) -> Result<Vec<Difference>, io::Error> {
+    let left_items = left.items(&options.filter)?;
+    let right_items = right.items(&options.filter)?;
+
+    let pairs = match_items(&left_items, &right_items, options.match_cnids);
+    let mut matched_left = vec![false; left_items.len()];
+    let mut matched_right = vec![false; right_items.len()];
+    let mut differences = Vec::new();
+    for (l, r) in pairs {
+        matched_left[l] = true;
+        matched_right[r] = true;
+        let (old, new) = (&left_items[l], &right_items[r]);
+        let path = path_string(&new.path);
+        let mut push = |change| {
+            differences.push(Difference {
+                path: path.clone(),
+                kind: new.kind,
+                change,
+            })
+        };
+
+        if old.path != new.path {
+            let from = path_string(&old.path);
+            match old.path.split_last().map(|(_, parent)| parent)
+                == new.path.split_last().map(|(_, parent)| parent)
+            {
+                true => push(Change::Renamed { from }),
+                false => push(Change::Moved { from }),
+            }
+        }
+        if old.size != new.size {
+            push(Change::Resized {
+                from: old.size,
+                to: new.size,
+            });
+        }
+        if let (Some(from), Some(to)) = (old.created, new.created)
+            && from != to
+        {
+            push(Change::Redated {
+                date: "created",
+                from,
+                to,
+            });
+        }
+        // A folder's modification date changes with its contents, which are
+        // compared on their own.
+        if new.kind != ItemKind::Folder && old.modified != new.modified {
+            push(Change::Redated {
+                date: "modified",
+                from: old.modified,
+                to: new.modified,
+            });
+        }
+        if old.target != new.target {
+            push(Change::ContentChanged);
+        }
+        if let Some(algorithm) = options.hash
+            && new.kind == ItemKind::File
+            && old.size == new.size
+        {
+            match (left.hash(old, algorithm), right.hash(new, algorithm)) {
+                (Ok(old_hash), Ok(new_hash)) if old_hash != new_hash => {
+                    push(Change::ContentChanged)
+                }
+                (Ok(_), Ok(_)) => {}
+                (Err(err), _) | (_, Err(err)) => push(Change::Unreadable {
+                    reason: err.to_string(),
+                }),
+            }
+        }
+    }
+
+    for (item, _) in left_items
+        .iter()
+        .zip(&matched_left)
+        .filter(|(_, matched)| !**matched)
+    {
+        differences.push(Difference {
+            path: path_string(&item.path),
+            kind: item.kind,
+            change: Change::Removed,
+        });
+    }
+    for (item, _) in right_items
+        .iter()
+        .zip(&matched_right)
+        .filter(|(_, matched)| !**matched)
+    {
+        differences.push(Difference {
+            path: path_string(&item.path),
+            kind: item.kind,
+            change: Change::Added,
+        });
+    }
+    // Stable, so each item's changes stay in the order they were found.
+    differences.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(differences)
+}
+
+/// Pairs of indices of the same item on each side. Items of different kinds
+/// are never paired.
+fn match_items(left: &[DiffItem], right: &[DiffItem], match_cnids: bool) -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    let mut left_matched = vec![false; left.len()];
+    let mut right_matched = vec![false; right.len()];
+    let mut pair = |l: usize, r: usize, pairs: &mut Vec<_>| {
+        if left_matched[l] || right_matched[r] || left[l].kind != right[r].kind {
+            return;
+        }
+        left_matched[l] = true;
+        right_matched[r] = true;
+        pairs.push((l, r));
+    };
+
+    let by_path = right
+        .iter()
+        .enumerate()
+        .map(|(index, item)| (&item.path, index))
+        .collect::<HashMap<_, _>>();
+    let by_cnid = right
+        .iter()
+        .enumerate()
+        .filter_map(|(index, item)| Some((item.cnid?, index)))
+        .collect::<HashMap<_, _>>();
+
+    if match_cnids {
+        // Unchanged paths first, so that a CNID reused for another item
+        // elsewhere does not take them.
+        for (l, item) in left.iter().enumerate() {
+            if let Some(&r) = by_path.get(&item.path)
+                && item.cnid.is_some()
+                && item.cnid == right[r].cnid
+            {
+                pair(l, r, &mut pairs);
+            }
+        }
+        for (l, item) in left.iter().enumerate() {
+            if let Some(&r) = item.cnid.and_then(|cnid| by_cnid.get(&cnid)) {
+                pair(l, r, &mut pairs);
+            }
+        }
+    }
+    for (l, item) in left.iter().enumerate() {
+        if let Some(&r) = by_path.get(&item.path) {
+            pair(l, r, &mut pairs);
+        }
+    }
+    pairs
+}
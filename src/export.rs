@@ -0,0 +1,215 @@
+//! Every file and folder record of the catalog with its path and decoded
+//! fields, for loading into other tools. Records are serialized with serde
+//! when the `serde` feature is enabled, or written as CSV.
+
+use crate::catalog::{CatalogMap, cnid_to_key, file_special, path_for_key};
+use crate::query::ItemKind;
+use crate::report::csv_field;
+use crate::*;
+use std::io::{self, Write};
+
+fn key_parent(key: &[u8]) -> Option<CatalogNodeId> {
+    let parent = key.get(..4)?;
+    Some(u32::from_be_bytes([
+        parent[0], parent[1], parent[2], parent[3],
+    ]))
+}
+
+fn key_name(key: &[u8]) -> Vec<u16> {
+    key.get(6..)
+        .unwrap_or_default()
+        .chunks_exact(2)
+        .map(|c| u16::from_be_bytes([c[0], c[1]]))
+        .collect()
+}
+
+/// A file or folder record, as stored.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(untagged))]
+pub enum CatalogRecord<'a> {
+    Folder(&'a CatalogFolder),
+    File(&'a CatalogFile),
+}
+
+/// One file or folder, with the fields of its record decoded.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CatalogEntry<'a> {
+    /// Slash-separated path from the root folder, such as `/Users/me`.
+    pub path: String,
+    pub name: String,
+    pub cnid: CatalogNodeId,
+    pub parent_id: CatalogNodeId,
+    pub kind: ItemKind,
+    /// The mode formatted as by `ls -l`.
+    pub mode: String,
+    pub bsd_flags: BsdFlags,
+    /// The `special` field, interpreted using the item's location.
+    pub special: BsdSpecial,
+    pub finder_flags: FinderFlags,
+    pub extended_finder_flags: ExtendedFinderFlags,
+    /// `None` for encodings not listed in TN1150.
+    pub text_encoding: Option<TextEncoding>,
+    pub record: CatalogRecord<'a>,
+}
+
+/// Every file and folder record in the catalog, in key order. This includes
+/// items that are not reachable from the root folder, such as those in the
+/// metadata folders.
+pub fn catalog_entries(map: &CatalogMap) -> Vec<CatalogEntry<'_>> {
+    map.iter()
+        .filter_map(|(key, leaf)| {
+            let parent_id = key_parent(key)?;
+            // The parent's path, as the item's own thread record may be
+            // missing. The first component is the volume name.
+            let mut path = path_for_key(map, cnid_to_key(parent_id));
+            path.push(String::from_utf16_lossy(&key_name(key)));
+            catalog_entry(map, &path, parent_id, leaf)
+        })
+        .collect()
+}
+
+/// Decode a single file or folder record, given its path as components
+/// starting with the volume name. The record need not be in `map`, such as
+/// one recovered from a free node, but `map` is used to interpret its BSD
+/// `special` field. Returns `None` for thread records.
+pub fn catalog_entry<'a>(
+    map: &CatalogMap,
+    path: &[String],
+    parent_id: CatalogNodeId,
+    leaf: &'a CatalogLeafRecord,
+) -> Option<CatalogEntry<'a>> {
+    let kind = ItemKind::of(leaf)?;
+    let (cnid, record, permissions, finder_flags, extended_finder_flags, text_encoding) = match leaf
+    {
+        CatalogLeafRecord::File(file) => (
+            file.file_id,
+            CatalogRecord::File(file),
+            &file.permissions,
+            file.user_info.finder_flags(),
+            file.finder_info.extended_finder_flags(),
+            file.text_encoding,
+        ),
+        CatalogLeafRecord::Folder(folder) => (
+            folder.folder_id,
+            CatalogRecord::Folder(folder),
+            &folder.permissions,
+            folder.user_info.finder_flags(),
+            folder.finder_info.extended_finder_flags(),
+            folder.text_encoding,
+        ),
+        _ => return None,
+    };
+    let special = match record {
+        CatalogRecord::File(file) => file_special(map, file),
+        CatalogRecord::Folder(_) => permissions.special(),
+    };
+
+    Some(CatalogEntry {
+        path: format!("/{}", path.get(1..).unwrap_or_default().join("/")),
+        name: path.last().cloned().unwrap_or_default(),
+        cnid,
+        parent_id,
+        kind,
+        mode: permissions.mode_string(),
+        bsd_flags: permissions.flags(),
+        special,
+        finder_flags,
+        extended_finder_flags,
+        text_encoding: TextEncoding::from_u32(text_encoding),
+        record,
+    })
+}
+
+const CSV_HEADER: &str = "path,name,cnid,parent_id,kind,data_size,resource_size,\
+created,modified,changed,accessed,backed_up,owner,group,mode,bsd_flags,special,\
+file_type,creator,finder_flags,extended_finder_flags,text_encoding,valence";
+
+/// Write entries as comma-separated values with a header row. Fields that do
+/// not apply to an item, such as the size of a folder, are left empty.
+pub fn write_csv(output: &mut impl Write, entries: &[CatalogEntry]) -> Result<(), io::Error> {
+    let date = |date: Date| match date.is_set() {
+        true => date.to_string(),
+        false => String::new(),
+    };
+    writeln!(output, "{CSV_HEADER}")?;
+    for entry in entries {
+        let (dates, permissions, text_encoding) = match entry.record {
+            CatalogRecord::File(file) => (
+                [
+                    file.create_date,
+                    file.content_mod_date,
+                    file.attribute_mod_date,
+                    file.access_date,
+                    file.backup_date,
+                ],
+                &file.permissions,
+                file.text_encoding,
+            ),
+            CatalogRecord::Folder(folder) => (
+                [
+                    folder.create_date,
+                    folder.content_mod_date,
+                    folder.attribute_mod_date,
+                    folder.access_date,
+                    folder.backup_date,
+                ],
+                &folder.permissions,
+                folder.text_encoding,
+            ),
+        };
+        let (data_size, resource_size, file_type, creator, valence) = match entry.record {
+            CatalogRecord::File(file) => (
+                file.data_fork.logical_size.to_string(),
+                file.resource_fork.logical_size.to_string(),
+                os_type_string(file.user_info.file_type()),
+                os_type_string(file.user_info.creator()),
+                String::new(),
+            ),
+            CatalogRecord::Folder(folder) => (
+                String::new(),
+                String::new(),
+                String::new(),
+                String::new(),
+                folder.valence.to_string(),
+            ),
+        };
+        let special = match entry.special {
+            BsdSpecial::InodeNumber(inode) => format!("inode {inode}"),
+            BsdSpecial::LinkCount(count) => format!("link count {count}"),
+            BsdSpecial::RawDevice(device) => format!("device {device:#x}"),
+            BsdSpecial::Unused(_) => String::new(),
+        };
+        let text_encoding = match entry.text_encoding {
+            Some(encoding) => format!("{encoding:?}"),
+            None => text_encoding.to_string(),
+        };
+
+        writeln!(
+            output,
+            "{},{},{},{},{},{data_size},{resource_size},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{valence}",
+            csv_field(&entry.path),
+            csv_field(&entry.name),
+            entry.cnid,
+            entry.parent_id,
+            entry.kind.name(),
+            date(dates[0]),
+            date(dates[1]),
+            date(dates[2]),
+            date(dates[3]),
+            date(dates[4]),
+            permissions.owner_id(),
+            permissions.group_id(),
+            entry.mode,
+            csv_field(&entry.bsd_flags.to_string()),
+            special,
+            csv_field(&file_type),
+            csv_field(&creator),
+            csv_field(&entry.finder_flags.to_string()),
+            csv_field(&entry.extended_finder_flags.to_string()),
+            text_encoding,
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,78 @@
+//! Extents Overflow File records. Described in TN1150 > Extents Overflow File.
+
+use crate::allocation::ForkKind;
+use crate::btree::{Diagnostic, salvage_leaf_records};
+use crate::fork::assemble_extents;
+use crate::*;
+use deku::bitvec::BitSlice;
+use std::io::{self, Cursor, Read, Seek};
+
+/// Up to eight further extents of a fork, beyond those in its catalog record
+/// and any earlier overflow records.
+#[derive(Debug)]
+pub struct OverflowExtents {
+    pub key: ExtentKey,
+    pub extents: ExtentRecord,
+}
+
+impl OverflowExtents {
+    pub fn fork(&self) -> ForkKind {
+        match self.key.fork_type {
+            ExtentKeyForkType::Data => ForkKind::Data,
+            ExtentKeyForkType::Resource => ForkKind::Resource,
+        }
+    }
+}
+
+/// Parse a single leaf record from the Extents Overflow B-tree.
+pub fn parse_extent_leaf(record: &[u8]) -> Result<OverflowExtents, io::Error> {
+    let (rest, key) = ExtentKey::read(BitSlice::from_slice(record), ())?;
+    // The key length excludes the length field itself.
+    if key.key_length as usize != ExtentKey::SIZE - 2 {
+        return Err(invalid_data(format!(
+            "Unexpected extent key length {}",
+            key.key_length
+        )));
+    }
+    let (_rest, extents) = ExtentRecord::read(rest, ())?;
+    Ok(OverflowExtents { key, extents })
+}
+
+/// Read every readable record of the Extents Overflow File, in key order.
+/// Damaged nodes and records are skipped and reported as with
+/// [`crate::btree::salvage_btree_leaves`].
+pub fn read_overflow_extents(
+    volume: &mut (impl Read + Seek),
+    volume_header: &VolumeHeader,
+) -> Result<(Vec<OverflowExtents>, Vec<Diagnostic>), io::Error> {
+    let block_size = volume_header.block_size as usize;
+    if volume_header.extents_file.logical_size == 0 {
+        return Ok((Vec::new(), Vec::new()));
+    }
+
+    let extents_file = assemble_extents(volume, &volume_header.extents_file, block_size)?;
+    salvage_leaf_records(
+        &mut Cursor::new(extents_file),
+        block_size,
+        parse_extent_leaf,
+    )
+}
+
+/// A fork's extents from the overflow records, in fork order. These follow
+/// the eight extents held in the fork data itself.
+pub fn fork_overflow_extents(
+    overflow: &[OverflowExtents],
+    file_id: CatalogNodeId,
+    fork: ForkKind,
+) -> Vec<ExtentDescriptor> {
+    let mut records = overflow
+        .iter()
+        .filter(|record| record.key.file_id == file_id && record.fork() == fork)
+        .collect::<Vec<_>>();
+    records.sort_by_key(|record| record.key.start_block);
+    records
+        .into_iter()
+        .flat_map(|record| record.extents)
+        .filter(|extent| extent.block_count != 0)
+        .collect()
+}
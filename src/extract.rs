@@ -0,0 +1,182 @@
+//! Restoring catalog metadata onto extracted files and folders.
+
+use crate::*;
+use rustix::fs::{AtFlags, CWD, Timespec, Timestamps, UTIME_OMIT, XattrFlags};
+use std::fs;
+use std::io;
+use std::os::unix::fs::{PermissionsExt, lchown};
+use std::path::Path;
+
+/// Extended attribute holding the original creation date, as seconds since
+/// the Unix epoch. Linux has no settable creation time.
+pub const CREATION_DATE_XATTR: &str = "user.hfsprust.create_date";
+
+/// Which parts of the catalog metadata to apply to extracted items.
+/// Timestamps and permissions are always applied.
+#[derive(Debug, Clone)]
+pub struct MetadataOptions {
+    /// Apply the owner and group IDs. Usually requires root.
+    pub ownership: bool,
+    /// Apply the BSD flags with Linux equivalents: immutable, append-only,
+    /// and nodump. Usually requires root.
+    pub bsd_flags: bool,
+    /// Store the creation date in [`CREATION_DATE_XATTR`].
+    pub creation_date_xattr: bool,
+}
+
+impl Default for MetadataOptions {
+    fn default() -> Self {
+        Self {
+            ownership: false,
+            bsd_flags: false,
+            creation_date_xattr: true,
+        }
+    }
+}
+
+/// Dates and permissions shared by file and folder records.
+struct ItemMetadata<'a> {
+    create_date: Date,
+    content_mod_date: Date,
+    access_date: Date,
+    permissions: &'a BsdInfo,
+    is_symlink: bool,
+}
+
+/// Apply a file record's timestamps, permissions, and optionally ownership
+/// and flags to an extracted file or symbolic link.
+pub fn apply_file_metadata(
+    path: &Path,
+    file: &CatalogFile,
+    options: &MetadataOptions,
+) -> Result<(), io::Error> {
+    apply_metadata(
+        path,
+        &ItemMetadata {
+            create_date: file.create_date,
+            content_mod_date: file.content_mod_date,
+            access_date: file.access_date,
+            permissions: &file.permissions,
+            is_symlink: file.is_symlink(),
+        },
+        options,
+    )
+}
+
+/// Apply a folder record's timestamps, permissions, and optionally ownership
+/// and flags to an extracted directory. Call this once the directory's
+/// contents have been written, as writing updates the modification time.
+pub fn apply_folder_metadata(
+    path: &Path,
+    folder: &CatalogFolder,
+    options: &MetadataOptions,
+) -> Result<(), io::Error> {
+    apply_metadata(
+        path,
+        &ItemMetadata {
+            create_date: folder.create_date,
+            content_mod_date: folder.content_mod_date,
+            access_date: folder.access_date,
+            permissions: &folder.permissions,
+            is_symlink: false,
+        },
+        options,
+    )
+}
+
+/// Dates that were never set leave the extracted item's time unchanged.
+fn timespec(date: Date) -> Timespec {
+    match date.to_unix() {
+        Some(seconds) => Timespec {
+            tv_sec: seconds,
+            tv_nsec: 0,
+        },
+        None => Timespec {
+            tv_sec: 0,
+            tv_nsec: UTIME_OMIT,
+        },
+    }
+}
+
+fn apply_metadata(
+    path: &Path,
+    item: &ItemMetadata,
+    options: &MetadataOptions,
+) -> Result<(), io::Error> {
+    let permissions = item.permissions;
+
+    // Changing ownership clears the setuid and setgid bits, so it must happen
+    // before the mode is applied.
+    if options.ownership {
+        lchown(
+            path,
+            Some(permissions.owner_id()),
+            Some(permissions.group_id()),
+        )?;
+    }
+
+    // User extended attributes are not permitted on symbolic links. The
+    // creation date is informational, so a file system without user extended
+    // attributes does not stop the rest of the metadata from being applied.
+    if options.creation_date_xattr
+        && !item.is_symlink
+        && let Some(seconds) = item.create_date.to_unix()
+    {
+        let value = seconds.to_string();
+        let _ = rustix::fs::setxattr(
+            path,
+            CREATION_DATE_XATTR,
+            value.as_bytes(),
+            XattrFlags::empty(),
+        );
+    }
+
+    let times = Timestamps {
+        last_access: timespec(item.access_date),
+        last_modification: timespec(item.content_mod_date),
+    };
+    rustix::fs::utimensat(CWD, path, &times, AtFlags::SYMLINK_NOFOLLOW)?;
+
+    // Linux ignores the mode of symbolic links. TN1150 specifies that a file
+    // type of zero means the permissions were never set. A read-only mode
+    // would prevent the changes above, so it is applied after them.
+    if !item.is_symlink && permissions.is_set() {
+        let mode = permissions.permission_bits() as u32;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    }
+
+    // Immutable and append-only files reject further changes, so flags go last.
+    #[cfg(target_os = "linux")]
+    if options.bsd_flags && !item.is_symlink {
+        apply_bsd_flags(path, permissions)?;
+    }
+
+    Ok(())
+}
+
+/// Map the BSD admin and owner flags onto Linux inode flags.
+#[cfg(target_os = "linux")]
+fn apply_bsd_flags(path: &Path, permissions: &BsdInfo) -> Result<(), io::Error> {
+    use rustix::fs::IFlags;
+
+    let flags = permissions.flags();
+    let mut wanted = IFlags::empty();
+    if flags.system_immutable || flags.user_immutable {
+        wanted |= IFlags::IMMUTABLE;
+    }
+    if flags.system_append || flags.user_append {
+        wanted |= IFlags::APPEND;
+    }
+    if flags.no_dump {
+        wanted |= IFlags::NODUMP;
+    }
+    if wanted.is_empty() {
+        return Ok(());
+    }
+
+    let file = fs::File::open(path)?;
+    let current = rustix::fs::ioctl_getflags(&file)?;
+    rustix::fs::ioctl_setflags(&file, current | wanted)?;
+
+    Ok(())
+}
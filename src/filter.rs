@@ -0,0 +1,235 @@
+//! Choosing which files to extract, by path, size, modification date, CNID,
+//! and Finder type and creator.
+//!
+//! Paths are matched as slash-separated strings below the root folder, such
+//! as `/Users/me/notes.txt`, without the volume name. A rule that matches a
+//! folder also applies to everything in it.
+
+use crate::catalog::METADATA_FOLDER_NAME;
+use crate::*;
+use globset::{GlobBuilder, GlobMatcher};
+use regex_automata::meta::Regex;
+use std::io;
+use std::ops::RangeInclusive;
+
+/// Items that the file system and macOS keep at the root of a volume, which
+/// are skipped by [`Filter::system_files`].
+pub const SYSTEM_FILE_NAMES: &[&str] = &[
+    METADATA_FOLDER_NAME,
+    ".DS_Store",
+    ".Spotlight-V100",
+    ".journal",
+    ".journal_info_block",
+    ".fseventsd",
+];
+
+#[derive(Debug, Clone)]
+enum Matcher {
+    Exact(String),
+    /// Matched against the item's name only.
+    Name(GlobMatcher),
+    /// Matched against the path without its leading `/`.
+    Path(GlobMatcher),
+    Regex(Regex),
+}
+
+/// A rule matching paths.
+#[derive(Debug, Clone)]
+pub struct PathPattern(Matcher);
+
+impl PathPattern {
+    /// Match one path exactly, such as `/.Spotlight-V100`.
+    pub fn exact(path: impl Into<String>) -> Self {
+        Self(Matcher::Exact(path.into()))
+    }
+
+    /// A shell-style glob. A pattern without a `/` matches names in any
+    /// folder, so `*.tmp` matches `/a/b.tmp`. Otherwise it matches whole
+    /// paths from the root: `*` stays within one component, and `**` matches
+    /// any number of them.
+    pub fn glob(pattern: &str) -> Result<Self, io::Error> {
+        let build = |pattern: &str| {
+            GlobBuilder::new(pattern)
+                .literal_separator(true)
+                .build()
+                .map(|glob| glob.compile_matcher())
+                .map_err(|err| invalid_input(format!("Invalid glob {pattern:?}: {err}")))
+        };
+        Ok(Self(match pattern.contains('/') {
+            true => Matcher::Path(build(pattern.trim_start_matches('/'))?),
+            false => Matcher::Name(build(pattern)?),
+        }))
+    }
+
+    /// A regular expression, which matches if it is found anywhere in the
+    /// path. Use `^` and `$` to anchor it.
+    pub fn regex(pattern: &str) -> Result<Self, io::Error> {
+        Regex::new(pattern)
+            .map(|regex| Self(Matcher::Regex(regex)))
+            .map_err(|err| {
+                let detail = err
+                    .syntax_error()
+                    .map_or(err.to_string(), ToString::to_string);
+                invalid_input(format!("Invalid regular expression {pattern:?}: {detail}"))
+            })
+    }
+
+    pub fn is_match(&self, path: &str) -> bool {
+        match &self.0 {
+            Matcher::Exact(exact) => path == exact,
+            Matcher::Name(glob) => glob.is_match(path.rsplit('/').next().unwrap_or(path)),
+            Matcher::Path(glob) => glob.is_match(path.trim_start_matches('/')),
+            Matcher::Regex(regex) => regex.is_match(path),
+        }
+    }
+}
+
+/// Which files to extract. Every condition that is set must hold; an empty
+/// filter matches everything.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    /// If any are given, only paths matching one of them, or inside a
+    /// folder matching one of them, are extracted.
+    pub include: Vec<PathPattern>,
+    /// Paths to skip, along with everything inside them. These take
+    /// precedence over `include`.
+    pub exclude: Vec<PathPattern>,
+    /// Smallest data fork to extract, in bytes.
+    pub min_size: Option<u64>,
+    /// Largest data fork to extract, in bytes.
+    pub max_size: Option<u64>,
+    /// Earliest content modification date, inclusive.
+    pub modified_after: Option<Date>,
+    /// Latest content modification date, inclusive.
+    pub modified_before: Option<Date>,
+    /// If any are given, only files whose CNID is in one of the ranges.
+    pub cnids: Vec<RangeInclusive<CatalogNodeId>>,
+    /// If any are given, only files with one of these Finder types.
+    pub file_types: Vec<OSType>,
+    /// If any are given, only files with one of these Finder creators.
+    pub creators: Vec<OSType>,
+}
+
+impl Filter {
+    /// Skip [`SYSTEM_FILE_NAMES`] at the root of the volume, and nothing else.
+    pub fn system_files() -> Self {
+        Self {
+            exclude: SYSTEM_FILE_NAMES
+                .iter()
+                .map(|name| PathPattern::exact(format!("/{name}")))
+                .collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Whether the path rules select `path`, given as components below the
+    /// root folder.
+    pub fn includes_path(&self, path: &[String]) -> bool {
+        // The path of each enclosing folder, then of the item itself.
+        let mut prefixes = Vec::with_capacity(path.len());
+        let mut prefix = String::new();
+        for component in path {
+            prefix.push('/');
+            prefix.push_str(component);
+            prefixes.push(prefix.clone());
+        }
+        let any_match = |patterns: &[PathPattern]| {
+            prefixes
+                .iter()
+                .any(|prefix| patterns.iter().any(|pattern| pattern.is_match(prefix)))
+        };
+        (self.include.is_empty() || any_match(&self.include)) && !any_match(&self.exclude)
+    }
+
+    /// Whether to extract `file`, whose path is given as components below the
+    /// root folder.
+    pub fn matches(&self, path: &[String], file: &CatalogFile) -> bool {
+        let size = file.data_fork.logical_size;
+        let modified = file.content_mod_date;
+        self.min_size.is_none_or(|min| size >= min)
+            && self.max_size.is_none_or(|max| size <= max)
+            && self.modified_after.is_none_or(|after| modified >= after)
+            && self.modified_before.is_none_or(|before| modified <= before)
+            && (self.cnids.is_empty()
+                || self.cnids.iter().any(|range| range.contains(&file.file_id)))
+            && (self.file_types.is_empty() || self.file_types.contains(&file.user_info.file_type))
+            && (self.creators.is_empty() || self.creators.contains(&file.user_info.file_creator))
+            && self.includes_path(path)
+    }
+}
+
+/// Parse a date in UTC: `YYYY-MM-DD`, optionally followed by `THH:MM:SS` and
+/// `Z`.
+pub fn parse_date(text: &str) -> Result<Date, io::Error> {
+    let error = || {
+        invalid_input(format!(
+            "Invalid date {text:?}; expected YYYY-MM-DD[THH:MM:SS]"
+        ))
+    };
+    let trimmed = text.trim_end_matches('Z');
+    let (date, time) = trimmed
+        .split_once(['T', ' '])
+        .unwrap_or((trimmed, "00:00:00"));
+    let fields = |field: &str, separator| {
+        field
+            .split(separator)
+            .map(|n| n.parse::<i64>().map_err(|_| error()))
+            .collect::<Result<Vec<_>, _>>()
+    };
+    let (year, month, day) = match fields(date, '-')?[..] {
+        [year, month, day] if (1..=12).contains(&month) && (1..=31).contains(&day) => {
+            (year, month, day)
+        }
+        _ => return Err(error()),
+    };
+    let seconds = match fields(time, ':')?[..] {
+        [hour, minute, second]
+            if (0..24).contains(&hour)
+                && (0..60).contains(&minute)
+                && (0..60).contains(&second) =>
+        {
+            hour * 3600 + minute * 60 + second
+        }
+        _ => return Err(error()),
+    };
+    Date::from_unix(days_from_civil(year, month, day) * 86_400 + seconds)
+        .ok_or_else(|| invalid_input(format!("{text} is outside the range of HFS+ dates")))
+}
+
+/// Days since the Unix epoch of a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Parse a Finder type or creator code: four characters, such as `TEXT`, or
+/// `0x` followed by eight hexadecimal digits.
+pub fn parse_os_type(text: &str) -> Result<OSType, io::Error> {
+    if let Some(hex) = text.strip_prefix("0x").filter(|hex| hex.len() == 8) {
+        return u32::from_str_radix(hex, 16)
+            .map_err(|_| invalid_input(format!("Invalid code {text:?}")));
+    }
+    match <[u8; 4]>::try_from(text.as_bytes()) {
+        Ok(code) => Ok(u32::from_be_bytes(code)),
+        Err(_) => Err(invalid_input(format!(
+            "Type and creator codes are four characters, not {text:?}"
+        ))),
+    }
+}
+
+/// Parse a CNID, or an inclusive range of them such as `100-200`.
+pub fn parse_cnid_range(text: &str) -> Result<RangeInclusive<CatalogNodeId>, io::Error> {
+    let parse = |n: &str| {
+        n.trim()
+            .parse::<CatalogNodeId>()
+            .map_err(|_| invalid_input(format!("Invalid CNID range {text:?}")))
+    };
+    match text.split_once('-') {
+        Some((start, end)) => Ok(parse(start)?..=parse(end)?),
+        None => parse(text).map(|cnid| cnid..=cnid),
+    }
+}
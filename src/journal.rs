@@ -0,0 +1,115 @@
+//! Locating the journal and reading its state. Described in TN1150 > Journal.
+
+use crate::*;
+use deku::bitvec::BitSlice;
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// Defined as `JOURNAL_HEADER_MAGIC` in TN1150 > Journal Header.
+pub const JOURNAL_HEADER_MAGIC: u32 = 0x4A4E4C78;
+
+/// Defined as `ENDIAN_MAGIC` in TN1150 > Journal Header.
+pub const JOURNAL_ENDIAN_MAGIC: u32 = 0x12345678;
+
+/// Defined as `struct journal_header` in TN1150 > Journal Header. Offsets
+/// are in bytes from the start of the journal.
+#[derive(Debug, Clone)]
+pub struct JournalHeader {
+    pub magic: u32,
+    pub endian: u32,
+    /// Offset of the oldest transaction that has not been written to its
+    /// final location.
+    pub start: u64,
+    /// Offset just past the newest transaction.
+    pub end: u64,
+    pub size: u64,
+    pub block_list_header_size: u32,
+    pub checksum: u32,
+    pub journal_header_size: u32,
+    /// The journal is written in the byte order of the host that created it.
+    pub little_endian: bool,
+}
+
+impl JournalHeader {
+    pub const SIZE: usize = 44;
+
+    /// Parse a journal header in either byte order, as identified by its
+    /// endian field.
+    pub fn parse(bytes: &[u8]) -> Result<Self, io::Error> {
+        let bytes = bytes
+            .get(..Self::SIZE)
+            .ok_or_else(|| invalid_data("Journal header is truncated"))?;
+        let little_endian = match bytes[4..8].try_into().unwrap() {
+            endian if u32::from_be_bytes(endian) == JOURNAL_ENDIAN_MAGIC => false,
+            endian if u32::from_le_bytes(endian) == JOURNAL_ENDIAN_MAGIC => true,
+            _ => return Err(invalid_data("Journal header has no valid endian field")),
+        };
+
+        let u32_at = |offset: usize| {
+            let field = bytes[offset..offset + 4].try_into().unwrap();
+            match little_endian {
+                true => u32::from_le_bytes(field),
+                false => u32::from_be_bytes(field),
+            }
+        };
+        let u64_at = |offset: usize| {
+            let field = bytes[offset..offset + 8].try_into().unwrap();
+            match little_endian {
+                true => u64::from_le_bytes(field),
+                false => u64::from_be_bytes(field),
+            }
+        };
+
+        let header = Self {
+            magic: u32_at(0),
+            endian: u32_at(4),
+            start: u64_at(8),
+            end: u64_at(16),
+            size: u64_at(24),
+            block_list_header_size: u32_at(32),
+            checksum: u32_at(36),
+            journal_header_size: u32_at(40),
+            little_endian,
+        };
+        if header.magic != JOURNAL_HEADER_MAGIC {
+            return Err(invalid_data(format!(
+                "Unexpected journal magic {:#010x}",
+                header.magic
+            )));
+        }
+        Ok(header)
+    }
+
+    /// Whether the journal holds transactions that may not have been written
+    /// to their final locations. The rest of the volume may be out of date
+    /// until they are replayed.
+    pub fn needs_replay(&self) -> bool {
+        self.start != self.end
+    }
+}
+
+/// Read the journal info block named by the volume header.
+pub fn read_journal_info_block(
+    volume: &mut (impl Read + Seek),
+    volume_header: &VolumeHeader,
+) -> Result<JournalInfoBlock, io::Error> {
+    let offset = volume_header.journal_info_block as u64 * volume_header.block_size as u64;
+    let mut buf = [0u8; JournalInfoBlock::PACKED_SIZE];
+    volume.seek(SeekFrom::Start(offset))?;
+    volume.read_exact(&mut buf)?;
+    let (_rest, info_block) = JournalInfoBlock::read(BitSlice::from_slice(&buf), ())?;
+    Ok(info_block)
+}
+
+/// Read the journal header, if the journal is stored on this volume.
+pub fn read_journal_header(
+    volume: &mut (impl Read + Seek),
+    info_block: &JournalInfoBlock,
+) -> Result<Option<JournalHeader>, io::Error> {
+    if !info_block.flags.in_fs {
+        return Ok(None);
+    }
+    let mut buf = [0u8; JournalHeader::SIZE];
+    volume.seek(SeekFrom::Start(info_block.offset))?;
+    volume.read_exact(&mut buf)?;
+    JournalHeader::parse(&buf).map(Some)
+}
@@ -1,7 +1,39 @@
 #![forbid(unsafe_code)]
 #![allow(dead_code)]
-
+// Code generated by deku's derive macros trips this lint.
+#![allow(clippy::manual_div_ceil)]
+
+pub mod alias;
+pub mod allocation;
+pub mod appledouble;
+pub mod attributes;
+pub mod btree;
+pub mod carve;
+pub mod catalog;
+pub mod check;
+pub mod damage;
+pub mod dfxml;
+pub mod diff;
+pub mod export;
+pub mod extents;
+pub mod extract;
+pub mod filter;
+pub mod fork;
+pub mod journal;
+pub mod partition;
+pub mod query;
 pub mod raw;
+pub mod recovery;
+pub mod report;
+pub mod resource;
+pub mod resume;
+#[cfg(feature = "serde")]
+mod serialize;
+pub mod stat;
+pub mod tar;
+pub mod timeline;
+pub mod volume;
+pub mod zip;
 
 #[cfg(feature = "deku")]
 use deku::ctx::Endian;
@@ -10,10 +42,22 @@ use deku::prelude::*;
 use std::io;
 use std::io::{Cursor, Read};
 
+/// An error for malformed on-disk structures.
+pub(crate) fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// An error for arguments or items that cannot be used, such as a bad
+/// pattern or a name that does not fit an archive format.
+pub(crate) fn invalid_input(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message.into())
+}
+
 /// Unicode 2.0 String. Defined in TN1150 > HFS Plus Names.
 /// Strings are stored fully-decomposed in canonical order.
 #[cfg(feature = "deku")]
 #[deku_derive(DekuRead)]
+#[derive(Debug)]
 #[deku(endian = "endian", ctx = "endian: Endian", ctx_default = "Endian::Big")]
 pub struct HFSUniStr255 {
     #[deku(temp)]
@@ -25,14 +69,15 @@ pub struct HFSUniStr255 {
 // Manual reimplementation to handle issues with `#[deku(temp)]` macro.
 // See https://github.com/sharksforarms/deku/issues/343
 #[cfg(not(feature = "deku"))]
+#[derive(Debug)]
 pub struct HFSUniStr255 {
     pub length: u16,
     pub unicode: Vec<u16>,
 }
 
-impl Into<String> for HFSUniStr255 {
-    fn into(self) -> String {
-        String::from_utf16_lossy(self.unicode.as_slice())
+impl From<HFSUniStr255> for String {
+    fn from(value: HFSUniStr255) -> Self {
+        String::from_utf16_lossy(value.unicode.as_slice())
     }
 }
 
@@ -40,9 +85,10 @@ impl Into<String> for HFSUniStr255 {
 /// Defined in TN1150 > Text Encodings.
 #[repr(u32)]
 #[allow(clippy::enum_variant_names)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "deku", derive(DekuRead))]
 #[cfg_attr(feature = "deku", deku(endian = "big", type = "u32"))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum TextEncoding {
     MacRoman = 0,
     MacJapanese = 1,
@@ -87,32 +133,65 @@ pub enum TextEncoding {
     MacUkrainian2 = 152,
 }
 
+impl TextEncoding {
+    /// Look up the encoding recorded in a catalog record, or `None` for
+    /// values not listed in TN1150.
+    pub fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(Self::MacRoman),
+            1 => Some(Self::MacJapanese),
+            2 => Some(Self::MacChineseTriad),
+            3 => Some(Self::MacKorean),
+            4 => Some(Self::MacArabic),
+            5 => Some(Self::MacHebrew),
+            6 => Some(Self::MacGreek),
+            7 => Some(Self::MacCyrillic),
+            8 => Some(Self::MacDevanagari),
+            10 => Some(Self::MacGurmukhi),
+            11 => Some(Self::MacGujarati),
+            12 => Some(Self::MacOriya),
+            13 => Some(Self::MacBengali),
+            14 => Some(Self::MacTamil),
+            15 => Some(Self::MacTelugu),
+            16 => Some(Self::MacKannada),
+            17 => Some(Self::MacMalayalam),
+            18 => Some(Self::MacSinhalese),
+            19 => Some(Self::MacBurmese),
+            20 => Some(Self::MacKhmer),
+            21 => Some(Self::MacThai),
+            22 => Some(Self::MacLaotian),
+            23 => Some(Self::MacGeorgian),
+            24 => Some(Self::MacArmenian),
+            25 => Some(Self::MacChineseSimp),
+            26 => Some(Self::MacTibetan),
+            27 => Some(Self::MacMongolian),
+            28 => Some(Self::MacEthiopic),
+            29 => Some(Self::MacCentralEurRoman),
+            30 => Some(Self::MacVietnamese),
+            31 => Some(Self::MacExtArabic),
+            33 => Some(Self::MacSymbol),
+            34 => Some(Self::MacDingbats),
+            35 => Some(Self::MacTurkish),
+            36 => Some(Self::MacCroatian),
+            37 => Some(Self::MacIcelandic),
+            38 => Some(Self::MacRomanian),
+            49 => Some(Self::MacFarsi),
+            140 => Some(Self::MacFarsi2),
+            48 => Some(Self::MacUkrainian),
+            152 => Some(Self::MacUkrainian2),
+            _ => None,
+        }
+    }
+}
+
 /// Dates are represented as seconds since Jan 1, 1904.
 /// Defined in TN1150 > HFS Plus Dates
-type Date = u32;
-
-/// Type-dependent file information. Defined in `struct HFSPlusBSDInfo.special`
-/// in TN1150 > HFS Plus Permissions.
-///
-/// Unlike the spec, do not use a union to represent the different types of
-/// special info.
-#[derive(Debug)]
-#[cfg_attr(feature = "deku", derive(DekuRead))]
-#[cfg_attr(
-    feature = "deku",
-    deku(endian = "endian", ctx = "endian: Endian", ctx_default = "Endian::Big")
-)]
-pub struct BsdInfoSpecial {
-    /// May represent an inode number, link count, or raw device
-    special: u32,
-    // inode_number: u32,
-    // link_count: u32,
-    // raw_device: u32,
-}
+pub use hfs_types_rs::DateTime as Date;
 
 /// File and Folder permissions. Defined as `struct HFSPlusBSDInfo` in
 /// TN1150 > HFS Plus Permissions.
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 #[cfg_attr(feature = "deku", derive(DekuRead))]
 #[cfg_attr(
     feature = "deku",
@@ -124,48 +203,223 @@ pub struct BsdInfo {
     admin_flags: u8,
     owner_flags: u8,
     file_mode: u16,
-    special: BsdInfoSpecial,
+    /// May represent an inode number, link count, or raw device. See
+    /// [`BsdInfo::special`] and [`CatalogFile::special`].
+    special: u32,
 }
 
-// TODO Populate fileMode enum once it needs to be referenced
-#[repr(u32)]
-#[derive(Debug)]
-#[cfg_attr(feature = "deku", derive(DekuRead))]
-#[cfg_attr(feature = "deku", deku(endian = "big", type = "u32"))]
-enum FileMode {
-    Suid = 0o004000,
-    Sgid = 0o002000,
-    Sticky = 0o001000,
-
-    OwnerRwxMask = 0o000700,
-    OwnerR = 0o000400,
-    OwnerW = 0o000200,
-    OwnerX = 0o000100,
-
-    GroupRwxMask = 0o000070,
-    GroupR = 0o000040,
-    GroupW = 0o000020,
-    GroupX = 0o000010,
-
-    OtherRwxMask = 0o000007,
-    OtherR = 0o000004,
-    OtherW = 0o000002,
-    OtherX = 0o000001,
-
-    FileTypeMask = 0o170000,
-    Fifo = 0o010000,
-    Character = 0o020000,
-    Directory = 0o040000,
-    Block = 0o060000,
-    Regular = 0o100000,
-    SymbolicLink = 0o120000,
-    Socket = 0o140000,
-    Whiteout = 0o160000,
+impl BsdInfo {
+    pub fn owner_id(&self) -> u32 {
+        self.owner_id
+    }
+
+    pub fn group_id(&self) -> u32 {
+        self.group_id
+    }
+
+    /// The raw mode, including the file type and permission bits.
+    pub fn mode(&self) -> u16 {
+        self.file_mode
+    }
+
+    /// The permission bits, including setuid, setgid, and sticky.
+    pub fn permission_bits(&self) -> u16 {
+        self.file_mode & 0o7777
+    }
+
+    /// The file type from the mode, or `None` if the permissions were never
+    /// set. TN1150 specifies that a file type of zero means the owner, group,
+    /// and mode are not meaningful.
+    pub fn file_type(&self) -> Option<FileType> {
+        FileType::from_mode(self.file_mode)
+    }
+
+    /// Whether the owner, group, and mode have been set.
+    pub fn is_set(&self) -> bool {
+        self.file_mode & hfs_types::S_IFMT != 0
+    }
+
+    /// The mode formatted as by `ls -l`, for example `drwxr-xr-x`. Unknown
+    /// file types are shown as `?`.
+    pub fn mode_string(&self) -> String {
+        use hfs_types::*;
+
+        let mode = self.file_mode;
+        let bit = |mask: u16, c: char| if mode & mask != 0 { c } else { '-' };
+        // The execute position doubles as the setuid, setgid, and sticky flags.
+        let exec = |x: u16, special: u16, set: char, unset: char| match (
+            mode & x != 0,
+            mode & special != 0,
+        ) {
+            (true, true) => set,
+            (false, true) => unset,
+            (true, false) => 'x',
+            (false, false) => '-',
+        };
+
+        [
+            self.file_type().map_or('?', FileType::ls_char),
+            bit(S_IRUSR, 'r'),
+            bit(S_IWUSR, 'w'),
+            exec(S_IXUSR, S_ISUID, 's', 'S'),
+            bit(S_IRGRP, 'r'),
+            bit(S_IWGRP, 'w'),
+            exec(S_IXGRP, S_ISGID, 's', 'S'),
+            bit(S_IROTH, 'r'),
+            bit(S_IWOTH, 'w'),
+            exec(S_IXOTH, S_ISTXT, 't', 'T'),
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    /// The decoded admin (superuser) and owner flags.
+    pub fn flags(&self) -> BsdFlags {
+        use hfs_types::*;
+
+        let admin = |mask: u8| self.admin_flags & mask != 0;
+        let owner = |mask: u8| self.owner_flags & mask != 0;
+        BsdFlags {
+            archived: admin(SF_ARCHIVED),
+            system_immutable: admin(SF_IMMUTABLE),
+            system_append: admin(SF_APPEND),
+            no_dump: owner(UF_NODUMP),
+            user_immutable: owner(UF_IMMUTABLE),
+            user_append: owner(UF_APPEND),
+            opaque: owner(UF_OPAQUE),
+            compressed: owner(UF_COMPRESSED),
+        }
+    }
+
+    /// The `special` field, interpreted using only the file type. Hard links
+    /// and indirect nodes need more context; see [`CatalogFile::special`] and
+    /// [`catalog::file_special`].
+    pub fn special(&self) -> BsdSpecial {
+        match self.file_type() {
+            Some(FileType::CharacterDevice | FileType::BlockDevice) => {
+                BsdSpecial::RawDevice(self.special)
+            }
+            _ => BsdSpecial::Unused(self.special),
+        }
+    }
+}
+
+/// File type from the `S_IFMT` bits of the mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    Fifo,
+    CharacterDevice,
+    Directory,
+    BlockDevice,
+    Regular,
+    SymbolicLink,
+    Socket,
+    Whiteout,
+}
+
+impl FileType {
+    /// Decode the file type from a mode, or `None` if unset or unknown.
+    pub fn from_mode(mode: u16) -> Option<Self> {
+        use hfs_types::*;
+
+        match mode & S_IFMT {
+            S_IFIFO => Some(Self::Fifo),
+            S_IFCHR => Some(Self::CharacterDevice),
+            S_IFDIR => Some(Self::Directory),
+            S_IFBLK => Some(Self::BlockDevice),
+            S_IFREG => Some(Self::Regular),
+            S_IFLNK => Some(Self::SymbolicLink),
+            S_IFSOCK => Some(Self::Socket),
+            S_IFWHT => Some(Self::Whiteout),
+            _ => None,
+        }
+    }
+
+    /// The type character shown by `ls -l`.
+    pub fn ls_char(self) -> char {
+        match self {
+            Self::Fifo => 'p',
+            Self::CharacterDevice => 'c',
+            Self::Directory => 'd',
+            Self::BlockDevice => 'b',
+            Self::Regular => '-',
+            Self::SymbolicLink => 'l',
+            Self::Socket => 's',
+            Self::Whiteout => 'w',
+        }
+    }
+}
+
+/// Decoded admin and owner flags. Defined in TN1150 > HFS Plus Permissions,
+/// with `compressed` from macOS `<sys/stat.h>`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct BsdFlags {
+    /// `SF_ARCHIVED`: File has been archived.
+    pub archived: bool,
+    /// `SF_IMMUTABLE`: File may not be changed, even by the superuser.
+    pub system_immutable: bool,
+    /// `SF_APPEND`: Writes may only append, even by the superuser.
+    pub system_append: bool,
+    /// `UF_NODUMP`: Do not dump (back up) the file.
+    pub no_dump: bool,
+    /// `UF_IMMUTABLE`: File may not be changed.
+    pub user_immutable: bool,
+    /// `UF_APPEND`: Writes may only append.
+    pub user_append: bool,
+    /// `UF_OPAQUE`: Directory is opaque when viewed through a union mount.
+    pub opaque: bool,
+    /// `UF_COMPRESSED`: Contents are stored compressed by decmpfs.
+    pub compressed: bool,
+}
+
+/// Formats the set flags as a comma-separated list using the names from
+/// `chflags(1)`, or `-` if none are set.
+impl std::fmt::Display for BsdFlags {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names = [
+            (self.archived, "arch"),
+            (self.system_immutable, "schg"),
+            (self.system_append, "sappnd"),
+            (self.no_dump, "nodump"),
+            (self.user_immutable, "uchg"),
+            (self.user_append, "uappnd"),
+            (self.opaque, "opaque"),
+            (self.compressed, "compressed"),
+        ]
+        .into_iter()
+        .filter_map(|(set, name)| set.then_some(name))
+        .collect::<Vec<_>>();
+
+        if names.is_empty() {
+            write!(f, "-")
+        } else {
+            write!(f, "{}", names.join(","))
+        }
+    }
+}
+
+/// Type-dependent file information. Defined as the `struct HFSPlusBSDInfo.special`
+/// union in TN1150 > HFS Plus Permissions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum BsdSpecial {
+    /// Hard link file: the number of the indirect node file holding the
+    /// contents, found in the metadata directory as `iNode<number>`.
+    InodeNumber(u32),
+    /// Indirect node file: the number of hard links that reference it.
+    LinkCount(u32),
+    /// Block or character device: the device number.
+    RawDevice(u32),
+    /// Not meaningful for this kind of item.
+    Unused(u32),
 }
 
 /// Extent information. Defined as `struct HfsPlusExtentDescriptor` in
 /// TN1150 > Fork Data Structure.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 #[cfg_attr(feature = "deku", derive(DekuRead))]
 #[cfg_attr(
     feature = "deku",
@@ -189,6 +443,7 @@ pub type ExtentRecord = [ExtentDescriptor; 8];
 /// Resource and Data Fork contents. Defined as `struct HFSPlusForkData` in
 /// TN1150 > Fork Data Structure.
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 #[cfg_attr(feature = "deku", derive(DekuRead))]
 #[cfg_attr(
     feature = "deku",
@@ -207,8 +462,9 @@ const VOLUME_SIGNATURE: [u8; 2] = [b'H', b'+'];
 
 /// Known volume attribute bits. Defined as part of `struct HFSPlusVolumeHeader`
 /// in TN1150 > Volume Header. Unknown bits MUST be zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u32)]
-enum VolumeAttributeBit {
+pub enum VolumeAttributeBit {
     // Bits 0-6 are reserved
     // Documentation implies that 7 is reserved as well
     /// Volume is write-protected due to a hardware setting.
@@ -242,44 +498,44 @@ enum VolumeAttributeBit {
 )]
 struct VolumeAttribute {
     // Bits 16-31 are reserved.
-    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "*reserved_31 == false"))]
+    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "!*reserved_31"))]
     reserved_31: bool,
-    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "*reserved_30 == false"))]
+    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "!*reserved_30"))]
     reserved_30: bool,
-    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "*reserved_29 == false"))]
+    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "!*reserved_29"))]
     reserved_29: bool,
-    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "*reserved_28 == false"))]
+    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "!*reserved_28"))]
     reserved_28: bool,
-    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "*reserved_27 == false"))]
+    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "!*reserved_27"))]
     reserved_27: bool,
-    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "*reserved_26 == false"))]
+    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "!*reserved_26"))]
     reserved_26: bool,
-    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "*reserved_25 == false"))]
+    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "!*reserved_25"))]
     reserved_25: bool,
-    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "*reserved_24 == false"))]
+    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "!*reserved_24"))]
     reserved_24: bool,
-    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "*reserved_23 == false"))]
+    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "!*reserved_23"))]
     reserved_23: bool,
-    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "*reserved_22 == false"))]
+    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "!*reserved_22"))]
     reserved_22: bool,
-    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "*reserved_21 == false"))]
+    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "!*reserved_21"))]
     reserved_21: bool,
-    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "*reserved_20 == false"))]
+    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "!*reserved_20"))]
     reserved_20: bool,
-    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "*reserved_19 == false"))]
+    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "!*reserved_19"))]
     reserved_19: bool,
-    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "*reserved_18 == false"))]
+    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "!*reserved_18"))]
     reserved_18: bool,
-    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "*reserved_17 == false"))]
+    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "!*reserved_17"))]
     reserved_17: bool,
-    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "*reserved_16 == false"))]
+    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "!*reserved_16"))]
     reserved_16: bool,
 
     #[cfg_attr(feature = "deku", deku(bits = 1))]
     software_lock: bool,
 
     /// Bit 14 is reserved.
-    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "*reserved_14 == false"))]
+    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "!*reserved_14"))]
     reserved_14: bool,
 
     #[cfg_attr(feature = "deku", deku(bits = 1))]
@@ -298,19 +554,19 @@ struct VolumeAttribute {
     hardware_lock: bool,
 
     // Bits 0-6 are reserved.
-    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "*reserved_6 == false"))]
+    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "!*reserved_6"))]
     reserved_6: bool,
-    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "*reserved_5 == false"))]
+    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "!*reserved_5"))]
     reserved_5: bool,
-    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "*reserved_4 == false"))]
+    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "!*reserved_4"))]
     reserved_4: bool,
-    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "*reserved_3 == false"))]
+    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "!*reserved_3"))]
     reserved_3: bool,
-    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "*reserved_2 == false"))]
+    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "!*reserved_2"))]
     reserved_2: bool,
-    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "*reserved_1 == false"))]
+    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "!*reserved_1"))]
     reserved_1: bool,
-    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "*reserved_0 == false"))]
+    #[cfg_attr(feature = "deku", deku(bits = 1, assert = "!*reserved_0"))]
     reserved_0: bool,
 }
 
@@ -362,6 +618,7 @@ struct VolumeAttribute {
 /// bytes from the end. Defined as `struct HFSPlusVolumeHeader` in
 /// TN1150 > Volume Header.
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 #[cfg_attr(feature = "deku", derive(DekuRead))]
 #[cfg_attr(
     feature = "deku",
@@ -369,12 +626,15 @@ struct VolumeAttribute {
 )]
 pub struct VolumeHeader {
     #[cfg_attr(feature = "deku", deku(assert = "*signature == VOLUME_SIGNATURE"))]
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize::signature"))]
     pub signature: [u8; 2],
     pub version: u16,
     pub attributes: u32,
     pub last_mounted_version: u32,
     pub journal_info_block: u32,
 
+    /// Stored in local time, unlike the other dates.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize::local_date"))]
     pub create_date: Date,
     pub modify_date: Date,
     pub backup_date: Date,
@@ -407,6 +667,24 @@ pub struct VolumeHeader {
 
 impl VolumeHeader {
     pub const PACKED_SIZE: usize = 512;
+
+    /// Offset of the volume header from the start of the volume. The
+    /// alternate volume header is stored 1024 bytes before the end.
+    pub const OFFSET: u64 = 1024;
+
+    /// Read and parse the volume header.
+    #[cfg(feature = "deku")]
+    pub fn read_from(volume: &mut (impl Read + io::Seek)) -> Result<Self, io::Error> {
+        let mut buf = [0u8; Self::PACKED_SIZE];
+        volume.seek(io::SeekFrom::Start(Self::OFFSET))?;
+        volume.read_exact(&mut buf)?;
+        let (_rest, volume_header) = Self::read(deku::bitvec::BitSlice::from_slice(&buf), ())?;
+        Ok(volume_header)
+    }
+
+    pub fn has_attribute(&self, bit: VolumeAttributeBit) -> bool {
+        self.attributes & (1 << bit as u32) != 0
+    }
 }
 
 /// Catalog Node ID or CNID identifies a B-tree file.
@@ -512,22 +790,21 @@ impl BTreeUserDataRecord {
 
 pub type BTreeKey = Vec<u8>;
 
-// Can we specify size for Deku parse?
+/// The B-tree node allocation bitmap, concatenated from the map record of the
+/// header node and any map nodes. Defined in TN1150 > Map Record.
+#[derive(Debug, Default)]
 pub struct BTreeAllocationMapRecord {
-    bitmap: Vec<u8>,
+    pub bitmap: Vec<u8>,
 }
 
 impl BTreeAllocationMapRecord {
+    /// Whether a node is in use, or `None` if the bitmap does not cover it.
     // Algorithm taken from IsAllocationBlockUsed in  TN1150 > Allocation File
-    fn is_block_used(&self, allocation_block: u32) -> bool {
-        // TODO handle overflow?
-        let offset = allocation_block / 8;
-        let this_byte = self.bitmap[offset as usize];
-        let bit_mask = 1 << (7 - (allocation_block & 8));
-
-        let is_set = this_byte & bit_mask != 0;
+    pub fn is_node_used(&self, node: u32) -> Option<bool> {
+        let this_byte = self.bitmap.get(node as usize / 8)?;
+        let bit_mask = 1 << (7 - (node % 8));
 
-        is_set
+        Some(this_byte & bit_mask != 0)
     }
 }
 
@@ -575,11 +852,10 @@ impl TryFrom<Vec<u8>> for CatalogFileKey {
 
         // Key: File Name
         let mut string = vec![0u16; 255];
-        for i in 0..char_count {
+        for c in string.iter_mut().take(char_count) {
             let mut buf = [0u8; 2];
             key_cur.read_exact(&mut buf)?;
-            let char = u16::from_be_bytes(buf);
-            string[i] = char;
+            *c = u16::from_be_bytes(buf);
         }
 
         let name = HFSUniStr255 {
@@ -597,17 +873,18 @@ impl TryFrom<Vec<u8>> for CatalogFileKey {
 }
 
 // TODO Deku should handle serializing CatalogFileKey to bytes.
-impl Into<Vec<u8>> for CatalogFileKey {
-    fn into(self) -> Vec<u8> {
-        let len =  4 // Parent CNID (u32) 
-            + 2 // Name Length (u16) 
-            + 2 * self.name.unicode.len() // Bytes
+impl From<CatalogFileKey> for Vec<u8> {
+    fn from(value: CatalogFileKey) -> Self {
+        let len =  4 // Parent CNID (u32)
+            + 2 // Name Length (u16)
+            + 2 * value.name.unicode.len() // Bytes
             ;
 
-        let mut out = Vec::<u8>::with_capacity(len as usize);
-        out.extend_from_slice(self.parent.to_be_bytes().as_slice());
-        out.extend_from_slice((self.name.unicode.len() as u16).to_be_bytes().as_slice());
-        self.name
+        let mut out = Vec::<u8>::with_capacity(len);
+        out.extend_from_slice(value.parent.to_be_bytes().as_slice());
+        out.extend_from_slice((value.name.unicode.len() as u16).to_be_bytes().as_slice());
+        value
+            .name
             .unicode
             .iter()
             .for_each(|c16| out.extend_from_slice(c16.to_be_bytes().as_slice()));
@@ -621,6 +898,7 @@ impl Into<Vec<u8>> for CatalogFileKey {
 /// TN1150 > Catalog File Data.
 #[allow(non_camel_case_types, clippy::enum_variant_names)]
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 #[cfg_attr(feature = "deku", derive(DekuRead))]
 #[cfg_attr(
     feature = "deku",
@@ -706,6 +984,7 @@ pub enum BTreeKeyCompareType {
 /// BTree leaf node for Folders. Defined as `struct HFSPlusCatalogFolder`
 /// in TN1150 > Catalog Folder Records
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 #[cfg_attr(feature = "deku", derive(DekuRead))]
 #[cfg_attr(
     feature = "deku",
@@ -726,9 +1005,11 @@ pub struct CatalogFolder {
     pub user_info: FolderInfo,
     pub finder_info: ExtendedFolderInfo,
     pub text_encoding: u32, // TextEncoding,
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub reserved: u32,
 }
 
+#[derive(Debug)]
 pub enum CatalogLeafRecord {
     Folder(CatalogFolder),
     File(CatalogFile),
@@ -757,6 +1038,7 @@ enum CatalogFileBitMask {
 /// BTree leaf node for Files. Defined as `struct HFSPlusCatalogFile` in
 /// TN1150 > Catalog File Records
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 #[cfg_attr(feature = "deku", derive(DekuRead))]
 #[cfg_attr(
     feature = "deku",
@@ -765,6 +1047,7 @@ enum CatalogFileBitMask {
 pub struct CatalogFile {
     pub record_type: CatalogFileDataType,
     pub flags: u16,
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub reserved_1: u32,
     pub file_id: CatalogNodeId,
     pub create_date: Date,
@@ -776,14 +1059,51 @@ pub struct CatalogFile {
     pub user_info: FileInfo,
     pub finder_info: ExtendedFileInfo,
     pub text_encoding: u32, // TextEncoding,
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub reserved_2: u32,
 
     pub data_fork: ForkData,
     pub resource_fork: ForkData,
 }
 
+impl CatalogFile {
+    /// Symbolic links are files with a type of `slnk` and a creator of `rhap`,
+    /// with the UTF-8 target path stored in the data fork.
+    /// Defined in TN1150 > Symbolic Links.
+    pub fn is_symlink(&self) -> bool {
+        self.user_info.file_type == hfs_types::kSymLinkFileType
+            && self.user_info.file_creator == hfs_types::kSymLinkCreator
+    }
+
+    /// Finder aliases are flagged with `kIsAlias`, and store their target in
+    /// an `alis` resource or as bookmark data in the data fork.
+    pub fn is_alias(&self) -> bool {
+        self.user_info.finder_flags & FileInfoFinderFlags::kIsAlias as u16 != 0
+    }
+
+    /// Hard links are files with a type of `hlnk` and a creator of `hfs+`,
+    /// whose contents are stored in an indirect node file.
+    /// Defined in TN1150 > Hard Links.
+    pub fn is_hard_link(&self) -> bool {
+        self.user_info.file_type == hfs_types::kHardLinkFileType
+            && self.user_info.file_creator == hfs_types::kHFSPlusCreator
+    }
+
+    /// The `special` field, interpreted from the file's own record. Indirect
+    /// node files can only be recognised by their location; see
+    /// [`catalog::file_special`].
+    pub fn special(&self) -> BsdSpecial {
+        if self.is_hard_link() {
+            BsdSpecial::InodeNumber(self.permissions.special)
+        } else {
+            self.permissions.special()
+        }
+    }
+}
+
 /// BTree link to CNID. Defined as `struct HFSPlusCatalogThread` in
 /// TN1150 > Catalog Thread Records.
+#[derive(Debug)]
 #[cfg_attr(feature = "deku", derive(DekuRead))]
 #[cfg_attr(
     feature = "deku",
@@ -801,6 +1121,7 @@ pub struct CatalogThread {
 /// A location on screen, used to store window placement.
 /// Defined in TN1150 > Finder Info.
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 #[cfg_attr(feature = "deku", derive(DekuRead))]
 #[cfg_attr(
     feature = "deku",
@@ -814,6 +1135,7 @@ struct Point {
 /// Rectangular region used for Directory windows.
 /// Defined in TN1150 > Finder Info.
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 #[cfg_attr(feature = "deku", derive(DekuRead))]
 #[cfg_attr(
     feature = "deku",
@@ -828,11 +1150,23 @@ struct Rect {
 
 /// Four characters representing OS used to write data.
 /// Defined in TN1150 > Finder Info.
-type OSType = u32;
+pub type OSType = u32;
+
+/// Format a type or creator code as its four characters, such as `TEXT`, or as
+/// `0x` and eight hexadecimal digits if any are not printable ASCII.
+pub fn os_type_string(code: OSType) -> String {
+    let bytes = code.to_be_bytes();
+    if bytes.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
+        bytes.iter().map(|&b| b as char).collect()
+    } else {
+        format!("{code:#010x}")
+    }
+}
 
 /// Presentation info for Finder.
 /// Defined in TN1150 > Finder Info.
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 #[cfg_attr(feature = "deku", derive(DekuRead))]
 #[cfg_attr(
     feature = "deku",
@@ -840,31 +1174,92 @@ type OSType = u32;
 )]
 pub struct FileInfo {
     #[deku(endian = "big")]
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize::os_type"))]
     file_type: OSType,
     #[deku(endian = "big")]
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize::os_type"))]
     file_creator: OSType,
     #[deku(endian = "big")]
     finder_flags: u16,
     location: Point,
     #[deku(endian = "big")]
+    #[cfg_attr(feature = "serde", serde(skip))]
     reserved: u16,
 }
 
+impl FileInfo {
+    /// Four-character file type, such as `TEXT`.
+    pub fn file_type(&self) -> OSType {
+        self.file_type
+    }
+
+    /// Four-character code of the application that created the file.
+    pub fn creator(&self) -> OSType {
+        self.file_creator
+    }
+
+    pub fn finder_flags(&self) -> FinderFlags {
+        FinderFlags::from_bits(self.finder_flags)
+    }
+
+    /// Position of the icon within its window, as `(v, h)`.
+    pub fn location(&self) -> (i16, i16) {
+        (self.location.v, self.location.h)
+    }
+
+    /// The record as stored, in big-endian order.
+    pub fn to_bytes(&self) -> [u8; 16] {
+        let mut bytes = [0u8; 16];
+        bytes[0..4].copy_from_slice(&self.file_type.to_be_bytes());
+        bytes[4..8].copy_from_slice(&self.file_creator.to_be_bytes());
+        bytes[8..10].copy_from_slice(&self.finder_flags.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.location.v.to_be_bytes());
+        bytes[12..14].copy_from_slice(&self.location.h.to_be_bytes());
+        bytes[14..16].copy_from_slice(&self.reserved.to_be_bytes());
+        bytes
+    }
+}
+
 /// Additional file information for display in Finder
 /// Defined in TN1150 > Finder Info.
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 #[cfg_attr(feature = "deku", derive(DekuRead))]
 #[cfg_attr(
     feature = "deku",
     deku(endian = "endian", ctx = "endian: Endian", ctx_default = "Endian::Big")
 )]
 pub struct ExtendedFileInfo {
+    #[cfg_attr(feature = "serde", serde(skip))]
     reserved_1: [i16; 4],
     extended_finder_flags: u16,
+    #[cfg_attr(feature = "serde", serde(skip))]
     reserved_2: i16,
     put_away_folder_id: i32,
 }
 
+impl ExtendedFileInfo {
+    pub fn extended_finder_flags(&self) -> ExtendedFinderFlags {
+        ExtendedFinderFlags::from_bits(self.extended_finder_flags)
+    }
+
+    /// Folder the file was in before it was moved to the Trash, or zero.
+    pub fn put_away_folder_id(&self) -> CatalogNodeId {
+        self.put_away_folder_id as CatalogNodeId
+    }
+    /// The record as stored, in big-endian order.
+    pub fn to_bytes(&self) -> [u8; 16] {
+        let mut bytes = [0u8; 16];
+        for (i, reserved) in self.reserved_1.iter().enumerate() {
+            bytes[i * 2..i * 2 + 2].copy_from_slice(&reserved.to_be_bytes());
+        }
+        bytes[8..10].copy_from_slice(&self.extended_finder_flags.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.reserved_2.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.put_away_folder_id.to_be_bytes());
+        bytes
+    }
+}
+
 /// Known flags for Finder
 /// Defined in TN1150 > Finder Info.
 #[allow(non_camel_case_types, clippy::enum_variant_names)]
@@ -899,9 +1294,136 @@ enum FileInfoFinderFlags {
     kIsAlias = 0x8000,
 }
 
+/// Decoded Finder flags, shared by files and folders. Defined in TN1150 >
+/// Finder Info. Some flags only apply to files, or only to applications.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FinderFlags {
+    /// `kIsOnDesk`: Shown on the desktop (System 6).
+    pub is_on_desk: bool,
+    /// `kColor`: Label color, from 0 for none to 7.
+    pub color: u8,
+    /// `kIsShared`: Application can be run by several users at once.
+    pub is_shared: bool,
+    /// `kHasNoINITs`: Extension or control panel has no INIT resource.
+    pub has_no_inits: bool,
+    /// `kHasBeenInited`: Finder has added the file's desktop database
+    /// resources.
+    pub has_been_inited: bool,
+    /// `kHasCustomIcon`: Icon is stored in an `icns` resource or `Icon\r`
+    /// file.
+    pub has_custom_icon: bool,
+    /// `kIsStationery`: Opening the file creates a copy.
+    pub is_stationery: bool,
+    /// `kNameLocked`: Name may not be changed from the Finder.
+    pub name_locked: bool,
+    /// `kHasBundle`: File has a `BNDL` resource.
+    pub has_bundle: bool,
+    /// `kIsInvisible`: Hidden from the Finder.
+    pub is_invisible: bool,
+    /// `kIsAlias`: File is a Finder alias.
+    pub is_alias: bool,
+}
+
+impl FinderFlags {
+    pub fn from_bits(bits: u16) -> Self {
+        use hfs_types::*;
+
+        let set = |mask: u16| bits & mask != 0;
+        Self {
+            is_on_desk: set(kIsOnDesk),
+            color: ((bits & kColor) >> 1) as u8,
+            is_shared: set(kIsShared),
+            has_no_inits: set(kHasNoINITs),
+            has_been_inited: set(kHasBeenInited),
+            has_custom_icon: set(kHasCustomIcon),
+            is_stationery: set(kIsStationery),
+            name_locked: set(kNameLocked),
+            has_bundle: set(kHasBundle),
+            is_invisible: set(kIsInvisible),
+            is_alias: set(kIsAlias),
+        }
+    }
+}
+
+/// Formats the set flags as a comma-separated list, or `-` if none are set.
+impl std::fmt::Display for FinderFlags {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let color = format!("color {}", self.color);
+        let names = [
+            (self.is_on_desk, "on desk"),
+            (self.color != 0, color.as_str()),
+            (self.is_shared, "shared"),
+            (self.has_no_inits, "no INITs"),
+            (self.has_been_inited, "inited"),
+            (self.has_custom_icon, "custom icon"),
+            (self.is_stationery, "stationery"),
+            (self.name_locked, "name locked"),
+            (self.has_bundle, "bundle"),
+            (self.is_invisible, "invisible"),
+            (self.is_alias, "alias"),
+        ]
+        .into_iter()
+        .filter_map(|(set, name)| set.then_some(name))
+        .collect::<Vec<_>>();
+
+        if names.is_empty() {
+            write!(f, "-")
+        } else {
+            write!(f, "{}", names.join(", "))
+        }
+    }
+}
+
+/// Decoded extended Finder flags. Defined in TN1150 > Finder Info.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ExtendedFinderFlags {
+    /// `kExtendedFlagsAreInvalid`: The other extended flags should be
+    /// ignored.
+    pub flags_are_invalid: bool,
+    /// `kExtendedFlagHasCustomBadge`: The item has a badge resource.
+    pub has_custom_badge: bool,
+    /// `kExtendedFlagHasRoutingInfo`: The file has a routing info resource.
+    pub has_routing_info: bool,
+}
+
+impl ExtendedFinderFlags {
+    pub fn from_bits(bits: u16) -> Self {
+        use hfs_types::*;
+
+        Self {
+            flags_are_invalid: bits & kExtendedFlagsAreInvalid != 0,
+            has_custom_badge: bits & kExtendedFlagHasCustomBadge != 0,
+            has_routing_info: bits & kExtendedFlagHasRoutingInfo != 0,
+        }
+    }
+}
+
+/// Formats the set flags as a comma-separated list, or `-` if none are set.
+impl std::fmt::Display for ExtendedFinderFlags {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names = [
+            (self.flags_are_invalid, "invalid"),
+            (self.has_custom_badge, "custom badge"),
+            (self.has_routing_info, "routing info"),
+        ]
+        .into_iter()
+        .filter_map(|(set, name)| set.then_some(name))
+        .collect::<Vec<_>>();
+
+        if names.is_empty() {
+            write!(f, "-")
+        } else {
+            write!(f, "{}", names.join(", "))
+        }
+    }
+}
+
 /// Finder Metadata and display information
 /// Defined in TN1150 > Finder Info.
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 #[cfg_attr(feature = "deku", derive(DekuRead))]
 #[cfg_attr(
     feature = "deku",
@@ -911,12 +1433,46 @@ pub struct FolderInfo {
     window_bounds: Rect,
     finder_flags: u16,
     location: Point,
+    #[cfg_attr(feature = "serde", serde(skip))]
     reserved: u16,
 }
 
+impl FolderInfo {
+    /// Bounds of the folder's window, as `(top, left, bottom, right)`.
+    pub fn window_bounds(&self) -> (i16, i16, i16, i16) {
+        let bounds = &self.window_bounds;
+        (bounds.top, bounds.left, bounds.bottom, bounds.right)
+    }
+
+    pub fn finder_flags(&self) -> FinderFlags {
+        FinderFlags::from_bits(self.finder_flags)
+    }
+
+    /// Position of the icon within its window, as `(v, h)`.
+    pub fn location(&self) -> (i16, i16) {
+        (self.location.v, self.location.h)
+    }
+
+    /// The record as stored, in big-endian order.
+    pub fn to_bytes(&self) -> [u8; 16] {
+        let bounds = &self.window_bounds;
+        let mut bytes = [0u8; 16];
+        bytes[0..2].copy_from_slice(&bounds.top.to_be_bytes());
+        bytes[2..4].copy_from_slice(&bounds.left.to_be_bytes());
+        bytes[4..6].copy_from_slice(&bounds.bottom.to_be_bytes());
+        bytes[6..8].copy_from_slice(&bounds.right.to_be_bytes());
+        bytes[8..10].copy_from_slice(&self.finder_flags.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.location.v.to_be_bytes());
+        bytes[12..14].copy_from_slice(&self.location.h.to_be_bytes());
+        bytes[14..16].copy_from_slice(&self.reserved.to_be_bytes());
+        bytes
+    }
+}
+
 /// Finder Metadata and display information
 /// Defined in TN1150 > Finder Info.
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 #[cfg_attr(feature = "deku", derive(DekuRead))]
 #[cfg_attr(
     feature = "deku",
@@ -924,26 +1480,78 @@ pub struct FolderInfo {
 )]
 pub struct ExtendedFolderInfo {
     scroll_position: Point,
+    #[cfg_attr(feature = "serde", serde(skip))]
     reserved_1: i32,
     extended_finder_flags: u16,
+    #[cfg_attr(feature = "serde", serde(skip))]
     reserved_2: i16,
     put_away_folder_id: i32,
 }
 
+impl ExtendedFolderInfo {
+    /// Scroll position of the folder's window, as `(v, h)`.
+    pub fn scroll_position(&self) -> (i16, i16) {
+        (self.scroll_position.v, self.scroll_position.h)
+    }
+
+    pub fn extended_finder_flags(&self) -> ExtendedFinderFlags {
+        ExtendedFinderFlags::from_bits(self.extended_finder_flags)
+    }
+
+    /// Folder the folder was in before it was moved to the Trash, or zero.
+    pub fn put_away_folder_id(&self) -> CatalogNodeId {
+        self.put_away_folder_id as CatalogNodeId
+    }
+    /// The record as stored, in big-endian order.
+    pub fn to_bytes(&self) -> [u8; 16] {
+        let mut bytes = [0u8; 16];
+        bytes[0..2].copy_from_slice(&self.scroll_position.v.to_be_bytes());
+        bytes[2..4].copy_from_slice(&self.scroll_position.h.to_be_bytes());
+        bytes[4..8].copy_from_slice(&self.reserved_1.to_be_bytes());
+        bytes[8..10].copy_from_slice(&self.extended_finder_flags.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.reserved_2.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.put_away_folder_id.to_be_bytes());
+        bytes
+    }
+}
+
 /// Defined as `struct HFSPlusExtentKey` in TN1150 > Extents Overflow File
 /// Key.
-struct ExtentKey {
-    key_length: u16,
-    fork_type: ExtentKeyForkType,
-    pad: u8,
-    file_id: CatalogNodeId,
-    start_block: u32,
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "deku", derive(DekuRead))]
+#[cfg_attr(
+    feature = "deku",
+    deku(endian = "endian", ctx = "endian: Endian", ctx_default = "Endian::Big")
+)]
+pub struct ExtentKey {
+    pub key_length: u16,
+    pub fork_type: ExtentKeyForkType,
+    pub pad: u8,
+    pub file_id: CatalogNodeId,
+    /// Offset of the first extent in the record, in allocation blocks from
+    /// the start of the fork.
+    pub start_block: u32,
+}
+
+impl ExtentKey {
+    pub const SIZE: usize = 12;
 }
 
 /// Defined in docs for struct HFSPlusExtentKey` in
 /// TN1150 > Extents Overflow File Key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "deku", derive(DekuRead))]
+#[cfg_attr(
+    feature = "deku",
+    deku(
+        endian = "endian",
+        ctx = "endian: Endian",
+        ctx_default = "Endian::Big",
+        type = "u8"
+    )
+)]
 #[repr(u8)]
-enum ExtentKeyForkType {
+pub enum ExtentKeyForkType {
     Data = 0x00,
     Resource = 0xFF,
 }
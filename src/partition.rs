@@ -0,0 +1,345 @@
+//! Finding a volume within a whole-disk image. Disks formatted on a Mac use an
+//! Apple Partition Map or a GUID Partition Table, and other disks may hold an
+//! HFS+ volume in an MBR partition. None of these are described in TN1150; the
+//! layouts follow Inside Macintosh: Devices > SCSI Manager, and the UEFI
+//! specification > GUID Partition Table.
+
+use crate::invalid_data;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// Sector size assumed by MBR, and by GPT unless a header is found at 4096.
+const SECTOR_SIZE: u64 = 512;
+
+/// Type of HFS+ partitions in an Apple Partition Map.
+pub const APM_HFS_TYPE: &str = "Apple_HFS";
+
+/// Type GUID of HFS+ partitions in a GUID Partition Table.
+pub const GPT_HFS_TYPE: &str = "48465300-0000-11AA-AA11-00306543ECAC";
+
+/// Type of HFS+ partitions in an MBR partition table.
+pub const MBR_HFS_TYPE: u8 = 0xAF;
+
+/// Type of the protective MBR partition covering a GUID Partition Table.
+const MBR_PROTECTIVE_TYPE: u8 = 0xEE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionScheme {
+    /// Apple Partition Map, used by PowerPC Macs.
+    Apple,
+    /// GUID Partition Table, used by Intel and Apple silicon Macs.
+    Gpt,
+    /// Master Boot Record partition table.
+    Mbr,
+}
+
+impl fmt::Display for PartitionScheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Apple => "Apple Partition Map",
+            Self::Gpt => "GUID Partition Table",
+            Self::Mbr => "MBR",
+        })
+    }
+}
+
+/// One entry of a partition map.
+#[derive(Debug, Clone)]
+pub struct Partition {
+    /// Position in the partition map, counting from 1. Empty entries are
+    /// skipped but still counted, so numbers match other tools.
+    pub number: usize,
+    /// Offset from the start of the image, in bytes.
+    pub offset: u64,
+    /// Length in bytes.
+    pub length: u64,
+    /// Partition type: a name for Apple Partition Maps, a GUID for GPT, and a
+    /// hexadecimal byte for MBR.
+    pub kind: String,
+    /// Partition name, where the scheme has one.
+    pub name: String,
+    /// The partition type is one used for HFS+ volumes.
+    pub is_hfs: bool,
+}
+
+/// A partition map and its non-empty entries.
+#[derive(Debug, Clone)]
+pub struct PartitionMap {
+    pub scheme: PartitionScheme,
+    pub partitions: Vec<Partition>,
+}
+
+impl PartitionMap {
+    pub fn partition(&self, number: usize) -> Option<&Partition> {
+        self.partitions
+            .iter()
+            .find(|partition| partition.number == number)
+    }
+}
+
+/// Read the partition map at the start of an image, if there is one.
+pub fn read_partition_map(
+    image: &mut (impl Read + Seek),
+) -> Result<Option<PartitionMap>, io::Error> {
+    let mut block = [0u8; SECTOR_SIZE as usize];
+    if !read_at(image, 0, &mut block)? {
+        return Ok(None);
+    }
+
+    // The Driver Descriptor Map starts with `ER`, and gives the block size
+    // used by the partition map.
+    if &block[0..2] == b"ER" {
+        let block_size = match u16::from_be_bytes([block[2], block[3]]) {
+            0 => SECTOR_SIZE,
+            block_size => block_size as u64,
+        };
+        return read_apple_partition_map(image, block_size).map(Some);
+    }
+
+    if block[510..512] != [0x55, 0xAA] {
+        return Ok(None);
+    }
+    let entries = block[446..510]
+        .chunks_exact(16)
+        .map(|entry| {
+            (
+                entry[4],
+                u32::from_le_bytes(entry[8..12].try_into().unwrap()) as u64,
+                u32::from_le_bytes(entry[12..16].try_into().unwrap()) as u64,
+            )
+        })
+        .collect::<Vec<_>>();
+    if entries
+        .iter()
+        .any(|(kind, _, _)| *kind == MBR_PROTECTIVE_TYPE)
+    {
+        for sector_size in [SECTOR_SIZE, 4096] {
+            if let Some(map) = read_gpt(image, sector_size)? {
+                return Ok(Some(map));
+            }
+        }
+    }
+
+    let partitions = entries
+        .into_iter()
+        .enumerate()
+        .filter(|(_, (kind, _, sectors))| *kind != 0 && *sectors != 0)
+        .map(|(index, (kind, start, sectors))| Partition {
+            number: index + 1,
+            offset: start * SECTOR_SIZE,
+            length: sectors * SECTOR_SIZE,
+            kind: format!("{kind:#04x}"),
+            name: String::new(),
+            is_hfs: kind == MBR_HFS_TYPE,
+        })
+        .collect::<Vec<_>>();
+    // A volume boot record also ends with 0x55AA, but has no partitions.
+    if partitions.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(PartitionMap {
+        scheme: PartitionScheme::Mbr,
+        partitions,
+    }))
+}
+
+/// Read as much of `buf` as the image holds at `offset`. Returns false if the
+/// image ends first.
+fn read_at(image: &mut (impl Read + Seek), offset: u64, buf: &mut [u8]) -> Result<bool, io::Error> {
+    image.seek(SeekFrom::Start(offset))?;
+    match image.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+/// Decode a NUL-padded string field.
+fn padded_string(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+/// Entries start in the second block, and each records the number of
+/// entries in the map.
+fn read_apple_partition_map(
+    image: &mut (impl Read + Seek),
+    block_size: u64,
+) -> Result<PartitionMap, io::Error> {
+    let mut partitions = Vec::new();
+    let mut entry = [0u8; SECTOR_SIZE as usize];
+    let mut count = 1;
+    let mut number = 1;
+    while number <= count {
+        if !read_at(image, number as u64 * block_size, &mut entry)? || &entry[0..2] != b"PM" {
+            return Err(invalid_data(format!(
+                "Apple Partition Map entry {number} is missing"
+            )));
+        }
+        if number == 1 {
+            count = u32::from_be_bytes(entry[4..8].try_into().unwrap()) as usize;
+        }
+        let start = u32::from_be_bytes(entry[8..12].try_into().unwrap()) as u64;
+        let blocks = u32::from_be_bytes(entry[12..16].try_into().unwrap()) as u64;
+        let kind = padded_string(&entry[48..80]);
+        partitions.push(Partition {
+            number,
+            offset: start * block_size,
+            length: blocks * block_size,
+            is_hfs: kind == APM_HFS_TYPE,
+            kind,
+            name: padded_string(&entry[16..48]),
+        });
+        number += 1;
+    }
+    Ok(PartitionMap {
+        scheme: PartitionScheme::Apple,
+        partitions,
+    })
+}
+
+/// The header is in the second sector, and points to an array of entries.
+fn read_gpt(
+    image: &mut (impl Read + Seek),
+    sector_size: u64,
+) -> Result<Option<PartitionMap>, io::Error> {
+    let mut header = [0u8; 92];
+    if !read_at(image, sector_size, &mut header)? || &header[0..8] != b"EFI PART" {
+        return Ok(None);
+    }
+    let entries_lba = u64::from_le_bytes(header[72..80].try_into().unwrap());
+    let entry_count = u32::from_le_bytes(header[80..84].try_into().unwrap()) as usize;
+    let entry_size = u32::from_le_bytes(header[84..88].try_into().unwrap()) as usize;
+    if entry_size < 128 || entry_count > 1024 {
+        return Err(invalid_data("GUID Partition Table header is damaged"));
+    }
+
+    let mut entries = vec![0u8; entry_count * entry_size];
+    if !read_at(image, entries_lba * sector_size, &mut entries)? {
+        return Err(invalid_data("GUID Partition Table entries are truncated"));
+    }
+    let partitions = entries
+        .chunks_exact(entry_size)
+        .enumerate()
+        .filter(|(_, entry)| entry[0..16].iter().any(|&b| b != 0))
+        .map(|(index, entry)| {
+            let first = u64::from_le_bytes(entry[32..40].try_into().unwrap());
+            let last = u64::from_le_bytes(entry[40..48].try_into().unwrap());
+            let name = entry[56..128]
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .take_while(|&c| c != 0)
+                .collect::<Vec<_>>();
+            let kind = guid_string(&entry[0..16]);
+            Partition {
+                number: index + 1,
+                offset: first * sector_size,
+                length: (last + 1).saturating_sub(first) * sector_size,
+                is_hfs: kind == GPT_HFS_TYPE,
+                kind,
+                name: String::from_utf16_lossy(&name),
+            }
+        })
+        .collect();
+    Ok(Some(PartitionMap {
+        scheme: PartitionScheme::Gpt,
+        partitions,
+    }))
+}
+
+/// Format a GUID stored with its first three fields in little-endian order.
+fn guid_string(guid: &[u8]) -> String {
+    format!(
+        "{:08X}-{:04X}-{:04X}-{:02X}{:02X}-{}",
+        u32::from_le_bytes(guid[0..4].try_into().unwrap()),
+        u16::from_le_bytes([guid[4], guid[5]]),
+        u16::from_le_bytes([guid[6], guid[7]]),
+        guid[8],
+        guid[9],
+        guid[10..16]
+            .iter()
+            .map(|b| format!("{b:02X}"))
+            .collect::<String>()
+    )
+}
+
+/// A region of a larger stream, such as a volume within a disk image,
+/// presented as a stream of its own.
+#[derive(Debug)]
+pub struct Slice<R> {
+    inner: R,
+    offset: u64,
+    length: u64,
+    position: u64,
+}
+
+impl<R: Seek> Slice<R> {
+    /// Present `length` bytes of `inner` from `offset`. A `length` of `None`
+    /// extends to the end of `inner`.
+    pub fn new(mut inner: R, offset: u64, length: Option<u64>) -> Result<Self, io::Error> {
+        let end = inner.seek(SeekFrom::End(0))?;
+        if offset > end {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Volume offset is past the end of the image",
+            ));
+        }
+        let length = length.unwrap_or(end - offset).min(end - offset);
+        Ok(Self {
+            inner,
+            offset,
+            length,
+            position: 0,
+        })
+    }
+
+    /// Offset of the slice within the underlying stream.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read + Seek> Read for Slice<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.length.saturating_sub(self.position);
+        let wanted = (buf.len() as u64).min(remaining) as usize;
+        if wanted == 0 {
+            return Ok(0);
+        }
+        self.inner
+            .seek(SeekFrom::Start(self.offset + self.position))?;
+        let read = self.inner.read(&mut buf[..wanted])?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: Seek> Seek for Slice<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(position) => Some(position),
+            SeekFrom::End(delta) => self.length.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+        };
+        let position = position.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Seek to a negative or overflowing position",
+            )
+        })?;
+        self.position = position;
+        Ok(position)
+    }
+}
@@ -0,0 +1,326 @@
+//! Searching the catalog by metadata: name, Finder type and creator, size,
+//! dates, ownership, and flags, for `hfsprust find`.
+//!
+//! Paths are given as components below the root folder, as in
+//! [`crate::filter`].
+
+use crate::allocation::ForkKind;
+use crate::catalog::children;
+use crate::extents::{OverflowExtents, fork_overflow_extents};
+use crate::filter::PathPattern;
+use crate::volume::Volume;
+use crate::*;
+use std::collections::HashSet;
+use std::io;
+use std::str::FromStr;
+
+/// What kind of item a record describes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum ItemKind {
+    /// Files, other than symbolic links.
+    File,
+    Folder,
+    Symlink,
+}
+
+impl ItemKind {
+    /// The kind of a file or folder record, or `None` for thread records.
+    pub fn of(record: &CatalogLeafRecord) -> Option<Self> {
+        match record {
+            CatalogLeafRecord::File(file) if file.is_symlink() => Some(Self::Symlink),
+            CatalogLeafRecord::File(_) => Some(Self::File),
+            CatalogLeafRecord::Folder(_) => Some(Self::Folder),
+            _ => None,
+        }
+    }
+
+    /// Lowercase name, such as `symlink`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::File => "file",
+            Self::Folder => "folder",
+            Self::Symlink => "symlink",
+        }
+    }
+}
+
+/// Accepts the letters used by `find -type`: `f`, `d`, and `l`.
+impl FromStr for ItemKind {
+    type Err = io::Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "f" | "file" => Ok(Self::File),
+            "d" | "folder" => Ok(Self::Folder),
+            "l" | "symlink" => Ok(Self::Symlink),
+            _ => Err(invalid_input(format!("Unknown item type {name:?}"))),
+        }
+    }
+}
+
+/// An inclusive range of dates. Either end may be open.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DateRange {
+    pub after: Option<Date>,
+    pub before: Option<Date>,
+}
+
+impl DateRange {
+    pub fn contains(&self, date: Date) -> bool {
+        self.after.is_none_or(|after| date >= after)
+            && self.before.is_none_or(|before| date <= before)
+    }
+}
+
+/// A test of the permission bits, written as for `find -perm`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PermissionMatch {
+    /// `644`: The permission bits are exactly these.
+    Exact(u16),
+    /// `-644`: All of these bits are set.
+    AllOf(u16),
+    /// `/022`: Any of these bits is set.
+    AnyOf(u16),
+}
+
+impl PermissionMatch {
+    pub fn matches(self, permissions: &BsdInfo) -> bool {
+        let bits = permissions.permission_bits();
+        match self {
+            Self::Exact(mode) => bits == mode,
+            Self::AllOf(mode) => bits & mode == mode,
+            Self::AnyOf(mode) => mode == 0 || bits & mode != 0,
+        }
+    }
+}
+
+/// Parses octal modes, optionally prefixed with `-` or `/`.
+impl FromStr for PermissionMatch {
+    type Err = io::Error;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let parse = |octal: &str| {
+            u16::from_str_radix(octal, 8)
+                .ok()
+                .filter(|mode| *mode <= 0o7777)
+                .ok_or_else(|| invalid_input(format!("Invalid octal mode {text:?}")))
+        };
+        if let Some(octal) = text.strip_prefix('-') {
+            Ok(Self::AllOf(parse(octal)?))
+        } else if let Some(octal) = text.strip_prefix('/') {
+            Ok(Self::AnyOf(parse(octal)?))
+        } else {
+            Ok(Self::Exact(parse(text)?))
+        }
+    }
+}
+
+/// Conditions on catalog records. Every condition that is set must hold; an
+/// empty query matches every file and folder. Conditions that only apply to
+/// files, such as sizes and Finder types, never match folders.
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    /// Matched against the path, so a glob without a `/` matches the name.
+    pub name: Option<PathPattern>,
+    pub kind: Option<ItemKind>,
+    /// If any are given, only files with one of these Finder types.
+    pub file_types: Vec<OSType>,
+    /// If any are given, only files with one of these Finder creators.
+    pub creators: Vec<OSType>,
+    /// Smallest data fork, in bytes.
+    pub min_size: Option<u64>,
+    /// Largest data fork, in bytes.
+    pub max_size: Option<u64>,
+    pub created: DateRange,
+    /// Content modification date.
+    pub modified: DateRange,
+    /// Attribute modification date, which is the `ctime` of the item.
+    pub changed: DateRange,
+    pub accessed: DateRange,
+    pub backed_up: DateRange,
+    pub owner: Option<u32>,
+    pub group: Option<u32>,
+    pub permissions: Option<PermissionMatch>,
+    /// Has the Finder's `kIsInvisible` flag.
+    pub invisible: Option<bool>,
+    /// Has `kHFSFileLockedMask` in its record flags, as set by the Finder's
+    /// Locked checkbox.
+    pub locked: Option<bool>,
+    /// Is a Finder alias.
+    pub alias: Option<bool>,
+    /// Has a non-empty resource fork.
+    pub resource_fork: Option<bool>,
+    /// Has the `UF_COMPRESSED` flag, so its contents are in a decmpfs
+    /// attribute or the resource fork.
+    pub compressed: Option<bool>,
+    /// Has a fork stored in more than one extent.
+    pub fragmented: Option<bool>,
+}
+
+/// Whether either fork of a file is stored in more than one extent,
+/// counting those in overflow records.
+pub fn is_fragmented(file: &CatalogFile, overflow: &[OverflowExtents]) -> bool {
+    [
+        (ForkKind::Data, &file.data_fork),
+        (ForkKind::Resource, &file.resource_fork),
+    ]
+    .into_iter()
+    .any(|(kind, fork)| {
+        let extents = fork
+            .extents
+            .iter()
+            .filter(|extent| extent.block_count != 0)
+            .count();
+        extents > 1
+            || (extents == 1 && !fork_overflow_extents(overflow, file.file_id, kind).is_empty())
+    })
+}
+
+impl Query {
+    /// Whether a file or folder record matches, whose path is given as
+    /// components below the root folder. `overflow` is only used to test
+    /// [`Query::fragmented`].
+    pub fn matches(
+        &self,
+        path: &[String],
+        record: &CatalogLeafRecord,
+        overflow: &[OverflowExtents],
+    ) -> bool {
+        let (flags, dates, permissions) = match record {
+            CatalogLeafRecord::File(file) => (
+                file.flags,
+                [
+                    file.create_date,
+                    file.content_mod_date,
+                    file.attribute_mod_date,
+                    file.access_date,
+                    file.backup_date,
+                ],
+                &file.permissions,
+            ),
+            CatalogLeafRecord::Folder(folder) => (
+                folder.flags,
+                [
+                    folder.create_date,
+                    folder.content_mod_date,
+                    folder.attribute_mod_date,
+                    folder.access_date,
+                    folder.backup_date,
+                ],
+                &folder.permissions,
+            ),
+            _ => return false,
+        };
+        let file = match record {
+            CatalogLeafRecord::File(file) => Some(file),
+            _ => None,
+        };
+        let invisible = match record {
+            CatalogLeafRecord::File(file) => file.user_info.finder_flags().is_invisible,
+            CatalogLeafRecord::Folder(folder) => folder.user_info.finder_flags().is_invisible,
+            _ => false,
+        };
+        // A condition on files, which folders never satisfy.
+        let file_test = |test: &dyn Fn(&CatalogFile) -> bool| file.is_some_and(test);
+        let flag = |want: Option<bool>, have: bool| want.is_none_or(|want| want == have);
+        let date_ranges = [
+            self.created,
+            self.modified,
+            self.changed,
+            self.accessed,
+            self.backed_up,
+        ];
+
+        self.name.as_ref().is_none_or(|pattern| {
+            let mut joined = String::new();
+            for component in path {
+                joined.push('/');
+                joined.push_str(component);
+            }
+            pattern.is_match(&joined)
+        }) && self
+            .kind
+            .is_none_or(|kind| ItemKind::of(record) == Some(kind))
+            && (self.file_types.is_empty()
+                || file_test(&|file| self.file_types.contains(&file.user_info.file_type())))
+            && (self.creators.is_empty()
+                || file_test(&|file| self.creators.contains(&file.user_info.creator())))
+            && self
+                .min_size
+                .is_none_or(|min| file_test(&|file| file.data_fork.logical_size >= min))
+            && self
+                .max_size
+                .is_none_or(|max| file_test(&|file| file.data_fork.logical_size <= max))
+            && date_ranges
+                .iter()
+                .zip(dates)
+                .all(|(range, date)| range.contains(date))
+            && self
+                .owner
+                .is_none_or(|owner| permissions.owner_id() == owner)
+            && self
+                .group
+                .is_none_or(|group| permissions.group_id() == group)
+            && self
+                .permissions
+                .is_none_or(|test| test.matches(permissions))
+            && flag(self.invisible, invisible)
+            && flag(self.locked, flags & hfs_types::kHFSFileLockedMask != 0)
+            && flag(self.alias, file.is_some_and(CatalogFile::is_alias))
+            && flag(
+                self.resource_fork,
+                file.is_some_and(|file| file.resource_fork.logical_size != 0),
+            )
+            && flag(self.compressed, permissions.flags().compressed)
+            && self
+                .fragmented
+                .is_none_or(|want| file.is_some_and(|file| is_fragmented(file, overflow) == want))
+    }
+}
+
+/// A record found by [`find_records`].
+#[derive(Debug)]
+pub struct QueryMatch<'a> {
+    /// Path starting with the volume name.
+    pub path: Vec<String>,
+    pub parent_id: CatalogNodeId,
+    pub record: &'a CatalogLeafRecord,
+}
+
+/// Search the folder `start` and everything below it, in catalog order.
+pub fn find_records<'a, R>(
+    volume: &'a Volume<R>,
+    start: CatalogNodeId,
+    query: &Query,
+) -> Vec<QueryMatch<'a>>
+where
+    R: io::Read + io::Seek,
+{
+    let mut matches = Vec::new();
+    let mut pending = vec![start];
+    // Damaged catalogs can contain cycles, so each folder is searched once.
+    let mut visited = HashSet::from([start]);
+    while let Some(folder) = pending.pop() {
+        for (_, record) in children(&volume.catalog, folder) {
+            let cnid = match record {
+                CatalogLeafRecord::File(file) => file.file_id,
+                CatalogLeafRecord::Folder(folder) => folder.folder_id,
+                _ => continue,
+            };
+            let path = volume.path(cnid);
+            if query.matches(path.get(1..).unwrap_or_default(), record, &volume.overflow) {
+                matches.push(QueryMatch {
+                    path,
+                    parent_id: folder,
+                    record,
+                });
+            }
+            if matches!(record, CatalogLeafRecord::Folder(_)) && visited.insert(cnid) {
+                pending.push(cnid);
+            }
+        }
+    }
+    matches
+}
@@ -501,10 +501,8 @@ pub struct HFSPlusExtentKey {
 }
 
 fn IsAllocationBlockUsed(thisAllocationBlock: u32, allocationFileContents: &[u8]) -> bool {
-    let thisByte: u8;
-
-    thisByte = allocationFileContents[(thisAllocationBlock / 8) as usize];
-    return (thisByte & (1 << (7 - (thisAllocationBlock % 8)))) != 0;
+    let thisByte = allocationFileContents[(thisAllocationBlock / 8) as usize];
+    (thisByte & (1 << (7 - (thisAllocationBlock % 8)))) != 0
 }
 
 #[repr(u32)]
@@ -608,7 +606,7 @@ fn calc_checksum(ptr: &[u8]) -> i32 {
         cksum = (cksum << 8) ^ (cksum.overflowing_add(*b as i32).0);
     }
 
-    return !cksum;
+    !cksum
 }
 
 pub const HFC_MAGIC: u32 = 0xFF28FF26;
@@ -0,0 +1,490 @@
+//! Recovering catalog records that are no longer part of the live B-tree.
+//!
+//! Deleting a file removes its records from the catalog's leaf nodes, but the
+//! bytes often survive in nodes that were freed or unlinked as the tree was
+//! rebalanced. These records are reported as possibly deleted.
+//!
+//! When the B-tree's header node is destroyed, the catalog can still be
+//! rebuilt by scanning for anything that looks like a leaf node, either in
+//! the Catalog file or, when its extents are lost too, across the volume.
+
+use crate::allocation::{BlockOwnership, OwnedExtent};
+use crate::btree::{
+    Diagnostic, is_plausible_node, read_btree_header, read_btree_map, read_leaf_chain,
+    read_node_at, split_btree_node,
+};
+use crate::catalog::{CatalogMap, child_key, cnid_to_key, parse_catalog_leaf, path_for_key};
+use crate::*;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// Why a node's records are not part of the live catalog.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeState {
+    /// Marked free in the node allocation map.
+    Free,
+    /// Marked in use, but not reachable from the first leaf node.
+    Unlinked,
+}
+
+/// A catalog record found outside the live B-tree, which may belong to a
+/// deleted file or folder.
+#[derive(Debug)]
+pub struct OrphanRecord {
+    pub node: u32,
+    pub record: usize,
+    /// Offset of the record from the start of the Catalog file.
+    pub offset: u64,
+    pub state: NodeState,
+    pub key: BTreeKey,
+    pub leaf: CatalogLeafRecord,
+}
+
+impl OrphanRecord {
+    /// CNID of the parent folder, from the record's key.
+    pub fn parent_id(&self) -> Option<CatalogNodeId> {
+        key_parent(&self.key)
+    }
+
+    /// Name of the file or folder, from the record's key. Empty for threads.
+    pub fn name(&self) -> String {
+        String::from_utf16_lossy(&key_name(&self.key))
+    }
+
+    /// CNID of the file or folder, or `None` for thread records.
+    pub fn cnid(&self) -> Option<CatalogNodeId> {
+        match &self.leaf {
+            CatalogLeafRecord::File(file) => Some(file.file_id),
+            CatalogLeafRecord::Folder(folder) => Some(folder.folder_id),
+            _ => None,
+        }
+    }
+}
+
+fn key_parent(key: &[u8]) -> Option<CatalogNodeId> {
+    let parent = key.get(..4)?;
+    Some(u32::from_be_bytes([
+        parent[0], parent[1], parent[2], parent[3],
+    ]))
+}
+
+fn key_name(key: &[u8]) -> Vec<u16> {
+    key.get(6..)
+        .unwrap_or_default()
+        .chunks_exact(2)
+        .map(|c| u16::from_be_bytes([c[0], c[1]]))
+        .collect()
+}
+
+/// Whether a record found outside the tree is just a stale copy of a live one.
+/// Files and folders that were renamed or moved are still live under their
+/// CNID, so only records whose CNID has disappeared are of interest.
+fn is_live(live: &CatalogMap, orphan: &OrphanRecord) -> bool {
+    match orphan.cnid() {
+        Some(cnid) => live.contains_key(&cnid_to_key(cnid)),
+        None => live.contains_key(&orphan.key),
+    }
+}
+
+/// Scan the Catalog file for leaf records in free or unlinked nodes, and
+/// return those that are not part of the live catalog.
+///
+/// Free nodes may hold anything, so unreadable nodes and records are skipped
+/// silently. Diagnostics only report problems with the allocation map and
+/// leaf chain. If the leaf chain is broken, only free nodes are scanned, as
+/// every leaf past the break would look unlinked.
+pub fn find_orphan_records(
+    mut stream: &mut (impl Read + Seek),
+    block_size: usize,
+    live: &CatalogMap,
+) -> Result<(Vec<OrphanRecord>, Vec<Diagnostic>), io::Error> {
+    stream.seek(SeekFrom::Start(0))?;
+    let (_node_descriptor, header) = read_btree_header(&mut stream, block_size)?;
+    let node_size = header.node_size as usize;
+
+    let (node_map, mut diagnostics) = read_btree_map(stream, node_size)?;
+    let (leaf_chain, chain_diagnostics) = read_leaf_chain(stream, &header);
+    let chain_complete = chain_diagnostics.is_empty();
+    diagnostics.extend(chain_diagnostics);
+
+    let available_nodes = (stream.seek(SeekFrom::End(0))? / node_size as u64) as u32;
+    let mut seen = HashSet::new();
+    let mut orphans = Vec::new();
+    for n in 1..header.total_nodes.min(available_nodes) {
+        let state = match node_map.is_node_used(n) {
+            Some(false) => NodeState::Free,
+            _ if chain_complete && !leaf_chain.contains(&n) => NodeState::Unlinked,
+            _ => continue,
+        };
+
+        let Ok((descriptor, records)) =
+            read_node_at(stream, n, node_size).and_then(|node| split_btree_node(&node))
+        else {
+            continue;
+        };
+        if descriptor.kind != BTreeNodeKind::kBTLeafNode || descriptor.height != 1 {
+            continue;
+        }
+
+        for record in records {
+            let Ok((key, leaf)) = record.data.and_then(|data| parse_catalog_leaf(&data)) else {
+                continue;
+            };
+            let orphan = OrphanRecord {
+                node: n,
+                record: record.index,
+                offset: n as u64 * node_size as u64 + record.offset as u64,
+                state,
+                key,
+                leaf,
+            };
+
+            // Freed nodes often hold several stale copies of the same record.
+            if !is_live(live, &orphan) && seen.insert((orphan.key.clone(), orphan.cnid())) {
+                orphans.push(orphan);
+            }
+        }
+    }
+
+    Ok((orphans, diagnostics))
+}
+
+/// Path of an orphaned record, resolving parents through the live catalog
+/// and through orphaned thread records. A parent that cannot be resolved is
+/// shown as its CNID, such as `#123`.
+pub fn orphan_path(
+    live: &CatalogMap,
+    orphans: &[OrphanRecord],
+    orphan: &OrphanRecord,
+) -> Vec<String> {
+    let mut path = vec![orphan.name()];
+    let mut visited = HashSet::new();
+    let mut parent = orphan.parent_id();
+
+    while let Some(cnid) = parent {
+        if live.contains_key(&cnid_to_key(cnid)) {
+            let mut live_path = path_for_key(live, cnid_to_key(cnid));
+            path.reverse();
+            live_path.extend(path);
+            return live_path;
+        }
+
+        let thread = orphans.iter().find(|o| {
+            o.key == cnid_to_key(cnid) && matches!(o.leaf, CatalogLeafRecord::FolderThread(_))
+        });
+        parent = match thread.map(|o| &o.leaf) {
+            Some(CatalogLeafRecord::FolderThread(t)) if visited.insert(cnid) => {
+                path.push(String::from_utf16_lossy(&t.node_name.unicode));
+                Some(t.parent_id)
+            }
+            _ => {
+                path.push(format!("#{cnid}"));
+                None
+            }
+        };
+    }
+
+    path.reverse();
+    path
+}
+
+/// Extents of a file's forks that are claimed by live items. The contents of
+/// a deleted file can only be trusted when there are none.
+pub fn conflicting_extents<'a>(
+    file: &CatalogFile,
+    ownership: &'a BlockOwnership,
+) -> Vec<&'a OwnedExtent> {
+    file.data_fork
+        .extents
+        .iter()
+        .chain(file.resource_fork.extents.iter())
+        .filter(|extent| extent.block_count != 0)
+        .flat_map(|extent| ownership.overlapping(extent.start_block, extent.block_count))
+        .filter(|owned| owned.owner != file.file_id)
+        .collect()
+}
+
+/// Node sizes permitted by TN1150 > B-Trees.
+const NODE_SIZES: [usize; 7] = [512, 1024, 2048, 4096, 8192, 16384, 32768];
+
+/// Amount of data examined when inferring the node size.
+const NODE_SIZE_SAMPLE: u64 = 16 * 1024 * 1024;
+
+/// Infer a B-tree's node size without its header node. Each permitted size
+/// is tried against the start of the file, and the size that splits it into
+/// the most plausible nodes wins. Returns `None` if no node looks plausible.
+pub fn infer_node_size(stream: &mut (impl Read + Seek)) -> Result<Option<usize>, io::Error> {
+    let sample_length = stream.seek(SeekFrom::End(0))?.min(NODE_SIZE_SAMPLE);
+    let mut sample = vec![0u8; sample_length as usize];
+    stream.seek(SeekFrom::Start(0))?;
+    stream.read_exact(&mut sample)?;
+
+    let best = NODE_SIZES
+        .iter()
+        .map(|&size| {
+            let plausible = sample
+                .chunks_exact(size)
+                .filter(|node| is_plausible_node(node))
+                .count();
+            (plausible, size)
+        })
+        .filter(|&(plausible, _)| plausible > 0)
+        .max();
+
+    Ok(best.map(|(_, size)| size))
+}
+
+/// A leaf record found by scanning for nodes, rather than by walking a tree.
+pub struct ScannedRecord {
+    /// Offset of the record from the start of the scanned data.
+    pub offset: u64,
+    pub key: BTreeKey,
+    pub leaf: CatalogLeafRecord,
+}
+
+/// Scan for plausible leaf nodes every `step` bytes, and parse their records.
+/// Records that fail to parse are skipped.
+pub fn scan_leaf_records(
+    stream: &mut (impl Read + Seek),
+    node_size: usize,
+    step: usize,
+) -> Result<Vec<ScannedRecord>, io::Error> {
+    let length = stream.seek(SeekFrom::End(0))?;
+    let mut node = vec![0u8; node_size];
+    let mut found = Vec::new();
+
+    let mut offset = 0;
+    while offset + node_size as u64 <= length {
+        stream.seek(SeekFrom::Start(offset))?;
+        stream.read_exact(&mut node)?;
+
+        if is_plausible_node(&node)
+            && let Ok((descriptor, records)) = split_btree_node(&node)
+            && descriptor.kind == BTreeNodeKind::kBTLeafNode
+        {
+            for record in records {
+                if let Ok((key, leaf)) = record.data.and_then(|data| parse_catalog_leaf(&data)) {
+                    found.push(ScannedRecord {
+                        offset: offset + record.offset as u64,
+                        key,
+                        leaf,
+                    });
+                }
+            }
+        }
+
+        offset += step as u64;
+    }
+
+    Ok(found)
+}
+
+/// Amount of the volume read at a time while carving.
+const CARVE_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+
+/// Whether a record's key is shaped like a catalog key: the key length covers
+/// exactly the parent CNID and a name of at most 255 UTF-16 characters.
+fn has_catalog_key(record: &[u8]) -> bool {
+    let (Some(key_length), Some(name_length)) = (record.get(0..2), record.get(6..8)) else {
+        return false;
+    };
+    let key_length = u16::from_be_bytes([key_length[0], key_length[1]]) as usize;
+    let name_length = u16::from_be_bytes([name_length[0], name_length[1]]) as usize;
+
+    name_length <= 255 && key_length == 6 + 2 * name_length
+}
+
+/// Parse a carved node as a catalog leaf. Every record must have a catalog
+/// key and parse as a catalog record, which rules out leaf nodes from the
+/// other B-trees.
+fn carve_leaf(node: &[u8]) -> Option<Vec<(usize, BTreeKey, CatalogLeafRecord)>> {
+    if !is_plausible_node(node) {
+        return None;
+    }
+    let (descriptor, records) = split_btree_node(node).ok()?;
+    if descriptor.kind != BTreeNodeKind::kBTLeafNode {
+        return None;
+    }
+
+    records
+        .into_iter()
+        .map(|record| {
+            let data = record.data.ok()?;
+            if !has_catalog_key(&data) {
+                return None;
+            }
+            let (key, leaf) = parse_catalog_leaf(&data).ok()?;
+            Some((record.offset, key, leaf))
+        })
+        .collect()
+}
+
+/// Scan every allocation block of a volume for catalog leaf nodes, for when
+/// the Catalog file's extents are lost. Found records can be passed to
+/// [`rebuild_catalog`]. Offsets are relative to the start of the volume.
+///
+/// When the node size is unknown, each permitted size is tried at every block.
+/// Copies of catalog nodes elsewhere on the volume, such as in the journal,
+/// will be found too.
+pub fn carve_catalog_records(
+    volume: &mut (impl Read + Seek),
+    block_size: usize,
+    node_size: Option<usize>,
+) -> Result<Vec<ScannedRecord>, io::Error> {
+    let sizes = node_size.map_or(NODE_SIZES.to_vec(), |size| vec![size]);
+    let largest = sizes.iter().copied().max().unwrap_or_default();
+    let step = node_size
+        .map_or(block_size, |size| size.min(block_size))
+        .max(NODE_SIZES[0]);
+
+    let length = volume.seek(SeekFrom::End(0))?;
+    let chunk_size = (CARVE_CHUNK_SIZE / step).max(1) * step;
+
+    // Each chunk is read with enough overlap to hold a node that starts at
+    // its last step.
+    let mut found = Vec::new();
+    let mut buf = Vec::new();
+    let mut chunk_start = 0u64;
+    while chunk_start < length {
+        let read_length = (chunk_size + largest).min((length - chunk_start) as usize);
+        buf.resize(read_length, 0);
+        volume.seek(SeekFrom::Start(chunk_start))?;
+        volume.read_exact(&mut buf)?;
+
+        for position in (0..chunk_size.min(read_length)).step_by(step) {
+            let candidate = &buf[position..];
+
+            // Cheap check for a leaf node descriptor before trying node sizes.
+            if candidate.get(8..10) != Some(&[0xFF, 1]) {
+                continue;
+            }
+
+            let leaf = sizes
+                .iter()
+                .find_map(|&size| candidate.get(..size).and_then(carve_leaf));
+            for (offset, key, leaf) in leaf.into_iter().flatten() {
+                found.push(ScannedRecord {
+                    offset: chunk_start + (position + offset) as u64,
+                    key,
+                    leaf,
+                });
+            }
+        }
+
+        chunk_start += chunk_size as u64;
+    }
+
+    Ok(found)
+}
+
+/// Modification dates used to pick the newest of several copies of a record.
+fn modified(leaf: &CatalogLeafRecord) -> (Date, Date) {
+    match leaf {
+        CatalogLeafRecord::File(file) => (file.attribute_mod_date, file.content_mod_date),
+        CatalogLeafRecord::Folder(folder) => (folder.attribute_mod_date, folder.content_mod_date),
+        _ => Default::default(),
+    }
+}
+
+fn item_cnid(leaf: &CatalogLeafRecord) -> Option<CatalogNodeId> {
+    match leaf {
+        CatalogLeafRecord::File(file) => Some(file.file_id),
+        CatalogLeafRecord::Folder(folder) => Some(folder.folder_id),
+        _ => None,
+    }
+}
+
+/// Rebuild a catalog from scanned leaf records alone.
+///
+/// Stale copies of records survive in freed nodes, so duplicates are expected.
+/// For each CNID, and then for each key, the file or folder record with the
+/// newest attribute and content modification dates wins. A thread record is
+/// preferred when it points back at the winning record for its CNID.
+///
+/// Thread records are synthesised from the keys of items whose threads were
+/// lost, so that paths can still be built. Without the node allocation map,
+/// deleted items cannot be told apart from live ones, and both appear in the
+/// rebuilt catalog.
+pub fn rebuild_catalog(records: Vec<ScannedRecord>) -> CatalogMap {
+    let mut items = HashMap::<CatalogNodeId, (BTreeKey, CatalogLeafRecord)>::new();
+    let mut threads = HashMap::<BTreeKey, Vec<CatalogLeafRecord>>::new();
+
+    for ScannedRecord { key, leaf, .. } in records {
+        match item_cnid(&leaf) {
+            Some(cnid) => {
+                let newer = items
+                    .get(&cnid)
+                    .is_none_or(|(_, existing)| modified(&leaf) > modified(existing));
+                if newer {
+                    items.insert(cnid, (key, leaf));
+                }
+            }
+            None => threads.entry(key).or_default().push(leaf),
+        }
+    }
+
+    // Different CNIDs can claim the same name after a file is replaced.
+    let mut map = CatalogMap::new();
+    for (key, leaf) in items.into_values() {
+        let newer = map
+            .get(&key)
+            .is_none_or(|existing| modified(&leaf) > modified(existing));
+        if newer {
+            map.insert(key, leaf);
+        }
+    }
+
+    for (key, candidates) in threads {
+        let points_to_item = |leaf: &CatalogLeafRecord| match leaf {
+            CatalogLeafRecord::FolderThread(t) | CatalogLeafRecord::FileThread(t) => map
+                .get(&child_key(t.parent_id, &t.node_name.unicode))
+                .and_then(item_cnid)
+                .is_some_and(|cnid| cnid_to_key(cnid) == key),
+            _ => false,
+        };
+
+        let preferred = candidates.iter().position(points_to_item).unwrap_or(0);
+        if let Some(thread) = candidates.into_iter().nth(preferred) {
+            map.insert(key, thread);
+        }
+    }
+
+    let missing_threads = map
+        .iter()
+        .filter_map(|(key, leaf)| {
+            let thread_key = cnid_to_key(item_cnid(leaf)?);
+            if map.contains_key(&thread_key) {
+                return None;
+            }
+            Some((thread_key, synthesize_thread(key, leaf)?))
+        })
+        .collect::<Vec<_>>();
+    map.extend(missing_threads);
+
+    map
+}
+
+/// Build the thread record that would point at an item with the given key.
+fn synthesize_thread(key: &[u8], leaf: &CatalogLeafRecord) -> Option<CatalogLeafRecord> {
+    let unicode = key_name(key);
+    let thread = |record_type| CatalogThread {
+        record_type,
+        reserved: 0,
+        parent_id: key_parent(key).unwrap_or_default(),
+        node_name: HFSUniStr255 {
+            #[cfg(not(feature = "deku"))]
+            length: unicode.len() as u16,
+            unicode: unicode.clone(),
+        },
+    };
+
+    match leaf {
+        CatalogLeafRecord::File(_) => Some(CatalogLeafRecord::FileThread(thread(
+            CatalogFileDataType::kHFSPlusFileThreadRecord,
+        ))),
+        CatalogLeafRecord::Folder(_) => Some(CatalogLeafRecord::FolderThread(thread(
+            CatalogFileDataType::kHFSPlusFolderThreadRecord,
+        ))),
+        _ => None,
+    }
+}
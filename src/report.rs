@@ -0,0 +1,217 @@
+//! Records of extracted files, kept alongside the output as a chain of
+//! custody: where each file came from, what was written, and how much of it
+//! can be trusted.
+
+use crate::fork::{CopiedFork, HashAlgorithm};
+use crate::*;
+use itertools::Itertools;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Quote a string as a JSON string literal.
+pub(crate) fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Quote a CSV field as described in RFC 4180, if it needs it.
+pub(crate) fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Report formats for [`ExtractionReport`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// One JSON object per line.
+    #[default]
+    Jsonl,
+    /// Comma-separated values with a header row.
+    Csv,
+}
+
+impl FromStr for ReportFormat {
+    type Err = io::Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "jsonl" => Ok(Self::Jsonl),
+            "csv" => Ok(Self::Csv),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unknown report format {name:?}"),
+            )),
+        }
+    }
+}
+
+/// What happened to one file during extraction.
+#[derive(Debug, Clone)]
+pub struct ExtractedFile {
+    /// Path on the volume, starting with the volume name.
+    pub path: Vec<String>,
+    pub cnid: CatalogNodeId,
+    pub output_path: PathBuf,
+    /// Recovered from a record that is no longer in the catalog.
+    pub deleted: bool,
+    pub logical_size: u64,
+    pub bytes_written: u64,
+    pub hash_algorithm: HashAlgorithm,
+    /// Digest of the bytes written, if the data fork was copied.
+    pub hash: Option<String>,
+    /// Non-empty extents, including those from overflow records.
+    pub extent_count: usize,
+    /// The fork has more blocks than its eight inline extents describe.
+    pub overflow: bool,
+    /// Contents are compressed by decmpfs, so the data fork is not the
+    /// file's contents.
+    pub compressed: bool,
+    /// Bytes replaced with the fill pattern.
+    pub damaged_bytes: u64,
+    pub errors: Vec<String>,
+}
+
+impl ExtractedFile {
+    /// Describe a file before it is copied.
+    pub fn new(
+        path: Vec<String>,
+        file_record: &CatalogFile,
+        overflow_extents: &[ExtentDescriptor],
+        output_path: PathBuf,
+        hash_algorithm: HashAlgorithm,
+    ) -> Self {
+        let fork = &file_record.data_fork;
+        let inline_blocks = fork
+            .extents
+            .iter()
+            .map(|extent| u64::from(extent.block_count))
+            .sum::<u64>();
+        Self {
+            path,
+            cnid: file_record.file_id,
+            output_path,
+            deleted: false,
+            logical_size: fork.logical_size,
+            bytes_written: 0,
+            hash_algorithm,
+            hash: None,
+            extent_count: fork
+                .extents
+                .iter()
+                .chain(overflow_extents)
+                .filter(|extent| extent.block_count != 0)
+                .count(),
+            overflow: u64::from(fork.total_blocks) > inline_blocks,
+            compressed: file_record.permissions.flags().compressed,
+            damaged_bytes: 0,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Record the result of copying the data fork.
+    pub fn copied(&mut self, copied: &CopiedFork) {
+        self.bytes_written = copied.bytes_written;
+        self.hash = Some(copied.hash.clone());
+        self.damaged_bytes = copied.damaged_bytes;
+    }
+
+    /// Whether every byte was copied from the volume without damage or
+    /// errors.
+    pub fn is_complete(&self) -> bool {
+        self.hash.is_some()
+            && self.errors.is_empty()
+            && self.damaged_bytes == 0
+            && self.bytes_written == self.logical_size
+    }
+
+    fn path_string(&self) -> String {
+        self.path.iter().join("/")
+    }
+}
+
+const CSV_HEADER: &str = "path,cnid,output_path,deleted,logical_size,bytes_written,\
+hash_algorithm,hash,extent_count,overflow,compressed,damaged_bytes,complete,errors";
+
+/// Writes one record per extracted file as it is extracted, so the report
+/// survives an interrupted extraction.
+pub struct ExtractionReport<W: Write> {
+    output: W,
+    format: ReportFormat,
+}
+
+impl<W: Write> ExtractionReport<W> {
+    /// Start a report, writing the CSV header if needed.
+    pub fn new(mut output: W, format: ReportFormat) -> Result<Self, io::Error> {
+        if format == ReportFormat::Csv {
+            writeln!(output, "{CSV_HEADER}")?;
+        }
+        Ok(Self { output, format })
+    }
+
+    pub fn write(&mut self, file: &ExtractedFile) -> Result<(), io::Error> {
+        let output_path = file.output_path.to_string_lossy();
+        match self.format {
+            ReportFormat::Jsonl => writeln!(
+                self.output,
+                "{{\"path\":{},\"cnid\":{},\"output_path\":{},\"deleted\":{},\
+                \"logical_size\":{},\"bytes_written\":{},\"hash_algorithm\":{},\"hash\":{},\
+                \"extent_count\":{},\"overflow\":{},\"compressed\":{},\"damaged_bytes\":{},\
+                \"complete\":{},\"errors\":[{}]}}",
+                json_string(&file.path_string()),
+                file.cnid,
+                json_string(&output_path),
+                file.deleted,
+                file.logical_size,
+                file.bytes_written,
+                json_string(&file.hash_algorithm.to_string()),
+                file.hash
+                    .as_deref()
+                    .map_or_else(|| "null".to_string(), json_string),
+                file.extent_count,
+                file.overflow,
+                file.compressed,
+                file.damaged_bytes,
+                file.is_complete(),
+                file.errors.iter().map(|error| json_string(error)).join(","),
+            )?,
+            ReportFormat::Csv => writeln!(
+                self.output,
+                "{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                csv_field(&file.path_string()),
+                file.cnid,
+                csv_field(&output_path),
+                file.deleted,
+                file.logical_size,
+                file.bytes_written,
+                file.hash_algorithm,
+                file.hash.as_deref().unwrap_or_default(),
+                file.extent_count,
+                file.overflow,
+                file.compressed,
+                file.damaged_bytes,
+                file.is_complete(),
+                csv_field(&file.errors.join("; ")),
+            )?,
+        }
+        // Flush each record, so that a crash loses at most the file being
+        // extracted.
+        self.output.flush()
+    }
+}
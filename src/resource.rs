@@ -0,0 +1,100 @@
+//! Resource fork parsing. Described in Inside Macintosh: More Macintosh Toolbox >
+//! Resource Manager > Resource File Format.
+
+use crate::*;
+use std::io;
+
+/// A single resource from a resource fork.
+#[derive(Debug)]
+pub struct Resource {
+    pub resource_type: OSType,
+    pub id: i16,
+    pub name: Option<Vec<u8>>,
+    pub data: Vec<u8>,
+}
+
+fn be_u16(bytes: &[u8], offset: usize) -> Result<u16, io::Error> {
+    bytes
+        .get(offset..offset + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+        .ok_or_else(|| invalid_data("Resource fork truncated"))
+}
+
+fn be_u32(bytes: &[u8], offset: usize) -> Result<u32, io::Error> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| invalid_data("Resource fork truncated"))
+}
+
+/// Parse every resource from the contents of a resource fork.
+///
+/// The fork starts with a 16-byte header giving the offsets and lengths of
+/// the resource data and resource map. The map holds a type list, where each
+/// type points to a list of references into the data.
+pub fn parse_resource_fork(fork: &[u8]) -> Result<Vec<Resource>, io::Error> {
+    let data_offset = be_u32(fork, 0)? as usize;
+    let map_offset = be_u32(fork, 4)? as usize;
+
+    // Offsets within the map are relative to the start of the map.
+    let type_list = map_offset + be_u16(fork, map_offset + 24)? as usize;
+    let name_list = map_offset + be_u16(fork, map_offset + 26)? as usize;
+
+    // Counts are stored as one less than the number of entries.
+    let type_count = be_u16(fork, type_list)?.wrapping_add(1) as usize;
+
+    let mut resources = Vec::new();
+    for t in 0..type_count {
+        let type_entry = type_list + 2 + 8 * t;
+        let resource_type = be_u32(fork, type_entry)?;
+        let reference_count = be_u16(fork, type_entry + 4)? as usize + 1;
+        let reference_list = type_list + be_u16(fork, type_entry + 6)? as usize;
+
+        for r in 0..reference_count {
+            let reference = reference_list + 12 * r;
+            let id = be_u16(fork, reference)? as i16;
+            let name_offset = be_u16(fork, reference + 2)?;
+            // Attributes occupy the high byte, followed by a 24-bit offset.
+            let offset = (be_u32(fork, reference + 4)? & 0x00FF_FFFF) as usize;
+
+            let start = data_offset + offset;
+            let length = be_u32(fork, start)? as usize;
+            let data = fork
+                .get(start + 4..start + 4 + length)
+                .ok_or_else(|| invalid_data("Resource data out of bounds"))?
+                .to_vec();
+
+            let name = if name_offset == 0xFFFF {
+                None
+            } else {
+                let name_start = name_list + name_offset as usize;
+                let length = *fork
+                    .get(name_start)
+                    .ok_or_else(|| invalid_data("Resource name out of bounds"))?
+                    as usize;
+                fork.get(name_start + 1..name_start + 1 + length)
+                    .map(|n| n.to_vec())
+            };
+
+            resources.push(Resource {
+                resource_type,
+                id,
+                name,
+                data,
+            });
+        }
+    }
+
+    Ok(resources)
+}
+
+/// Find the first resource of the given type, optionally matching an ID.
+pub fn find_resource(
+    fork: &[u8],
+    resource_type: OSType,
+    id: Option<i16>,
+) -> Result<Option<Resource>, io::Error> {
+    Ok(parse_resource_fork(fork)?
+        .into_iter()
+        .find(|r| r.resource_type == resource_type && id.is_none_or(|id| r.id == id)))
+}
@@ -0,0 +1,223 @@
+//! Remembering which files an extraction has already written, so that an
+//! interrupted or partly failed run can be resumed into the same directory.
+//!
+//! The state journal is a text file with one line per completed file:
+//! `size<TAB>algorithm<TAB>hash<TAB>output path`. Lines are appended as files
+//! complete, and later lines replace earlier ones for the same path. Before a
+//! file is first created, a `created<TAB>output path` line is appended, so
+//! that later runs only replace files that an extraction wrote. Lines that
+//! cannot be parsed are ignored, so a line cut short by a crash only causes
+//! that file to be extracted again.
+//!
+//! Output paths are relative to the output directory, so that it can be
+//! named differently by a later run, and backslashes, tabs, and line breaks
+//! in them are escaped with a backslash.
+
+use crate::fork::{HashAlgorithm, hash_reader};
+use crate::report::ExtractedFile;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// A file recorded as completely extracted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Completed {
+    size: u64,
+    algorithm: HashAlgorithm,
+    hash: String,
+}
+
+/// A line of the state journal.
+enum Line {
+    Created(PathBuf),
+    Completed(PathBuf, Completed),
+}
+
+/// Completed files from earlier runs, and the journal to record new ones.
+/// Files are keyed by their path relative to the output directory.
+#[derive(Debug)]
+pub struct ExtractionState {
+    output_root: PathBuf,
+    completed: HashMap<PathBuf, Completed>,
+    /// Outputs created by this or an earlier run, which may be replaced.
+    created: HashSet<PathBuf>,
+    journal: File,
+}
+
+impl ExtractionState {
+    /// Open or create the state journal at `path`, for an extraction into
+    /// `output_root`.
+    pub fn open(path: &Path, output_root: &Path) -> Result<Self, io::Error> {
+        let mut completed = HashMap::new();
+        let mut created = HashSet::new();
+        if path.exists() {
+            for line in BufReader::new(File::open(path)?).lines() {
+                match parse_line(&line?) {
+                    Some(Line::Created(output_path)) => {
+                        created.insert(output_path);
+                    }
+                    Some(Line::Completed(output_path, entry)) => {
+                        created.insert(output_path.clone());
+                        completed.insert(output_path, entry);
+                    }
+                    None => {}
+                }
+            }
+        }
+        let journal = File::options().create(true).append(true).open(path)?;
+        Ok(Self {
+            output_root: output_root.to_path_buf(),
+            completed,
+            created,
+            journal,
+        })
+    }
+
+    /// Files recorded as completed, whether or not they are still intact.
+    pub fn len(&self) -> usize {
+        self.completed.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.completed.is_empty()
+    }
+
+    /// If the output was completed by an earlier run and still has the
+    /// recorded size and hash, return them. The output is hashed again with
+    /// the recorded algorithm, which must match the one in use.
+    pub fn verified(
+        &self,
+        output_path: &Path,
+        logical_size: u64,
+        algorithm: HashAlgorithm,
+    ) -> Result<Option<(u64, String)>, io::Error> {
+        let Some(entry) = self.completed.get(self.key(output_path)) else {
+            return Ok(None);
+        };
+        if entry.size != logical_size || entry.algorithm != algorithm {
+            return Ok(None);
+        }
+        // Symbolic links are not followed, as a link in the output could
+        // otherwise stand in for the file.
+        match output_path.symlink_metadata() {
+            Ok(metadata) if metadata.is_file() && metadata.len() == entry.size => {}
+            Ok(_) => return Ok(None),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        }
+        let (size, hash) = hash_reader(&mut File::open(output_path)?, algorithm)?;
+        Ok((size == entry.size && hash == entry.hash).then_some((size, hash)))
+    }
+
+    /// Whether the output was created by this or an earlier run, and so may
+    /// be replaced.
+    pub fn is_created(&self, output_path: &Path) -> bool {
+        self.created.contains(self.key(output_path))
+    }
+
+    /// Record that an output is about to be created. This is journaled
+    /// before the file exists, so a crash cannot leave an output that later
+    /// runs refuse to replace.
+    pub fn create(&mut self, output_path: &Path) -> Result<(), io::Error> {
+        let key = self.key(output_path).to_path_buf();
+        if self.created.contains(&key) {
+            return Ok(());
+        }
+        writeln!(self.journal, "created\t{}", escape(&key))?;
+        self.journal.flush()?;
+        self.created.insert(key);
+        Ok(())
+    }
+
+    /// Record a file if it was extracted completely. Anything else will be
+    /// retried by the next run.
+    pub fn record(&mut self, file: &ExtractedFile) -> Result<(), io::Error> {
+        let Some(hash) = file.hash.as_ref().filter(|_| file.is_complete()) else {
+            return Ok(());
+        };
+        let entry = Completed {
+            size: file.bytes_written,
+            algorithm: file.hash_algorithm,
+            hash: hash.clone(),
+        };
+        let key = self.key(&file.output_path).to_path_buf();
+        if self.completed.get(&key) == Some(&entry) {
+            return Ok(());
+        }
+        writeln!(
+            self.journal,
+            "{}\t{}\t{}\t{}",
+            entry.size,
+            entry.algorithm,
+            entry.hash,
+            escape(&key)
+        )?;
+        self.journal.flush()?;
+        self.created.insert(key.clone());
+        self.completed.insert(key, entry);
+        Ok(())
+    }
+
+    /// An output path relative to the output directory. Paths outside of it
+    /// are kept whole.
+    fn key<'p>(&self, output_path: &'p Path) -> &'p Path {
+        output_path
+            .strip_prefix(&self.output_root)
+            .unwrap_or(output_path)
+    }
+}
+
+/// Escape a path for the journal, where fields are separated by tabs and
+/// lines by line breaks.
+fn escape(path: &Path) -> String {
+    let mut escaped = String::new();
+    for c in path.to_string_lossy().chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Undo [`escape`], or `None` for an empty path or an unknown escape.
+fn unescape(field: &str) -> Option<PathBuf> {
+    let mut path = String::new();
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        path.push(match c {
+            '\\' => match chars.next()? {
+                '\\' => '\\',
+                't' => '\t',
+                'n' => '\n',
+                'r' => '\r',
+                _ => return None,
+            },
+            c => c,
+        });
+    }
+    (!path.is_empty()).then(|| PathBuf::from(path))
+}
+
+fn parse_line(line: &str) -> Option<Line> {
+    if let Some(output_path) = line.strip_prefix("created\t") {
+        return unescape(output_path).map(Line::Created);
+    }
+    let mut fields = line.splitn(4, '\t');
+    let size = fields.next()?.parse().ok()?;
+    let algorithm = fields.next()?.parse().ok()?;
+    let hash = fields.next()?.to_string();
+    let output_path = unescape(fields.next()?)?;
+    Some(Line::Completed(
+        output_path,
+        Completed {
+            size,
+            algorithm,
+            hash,
+        },
+    ))
+}
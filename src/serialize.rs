@@ -0,0 +1,27 @@
+//! Helpers for fields whose raw values are not useful when serialized.
+
+use crate::*;
+use serde::Serializer;
+
+/// Type and creator codes as their four characters. See [`os_type_string`].
+pub(crate) fn os_type<S: Serializer>(code: &OSType, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&os_type_string(*code))
+}
+
+/// The volume signature as text, such as `H+`.
+pub(crate) fn signature<S: Serializer>(
+    signature: &[u8; 2],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&String::from_utf8_lossy(signature))
+}
+
+/// A date stored in local time, formatted without an offset as the volume
+/// does not record the time zone.
+pub(crate) fn local_date<S: Serializer>(date: &Date, serializer: S) -> Result<S::Ok, S::Error> {
+    if !date.is_set() {
+        return serializer.serialize_none();
+    }
+    let formatted = date.display_local(0).to_string();
+    serializer.serialize_str(formatted.trim_end_matches("+00:00"))
+}
@@ -0,0 +1,431 @@
+//! Everything recorded about a single file or folder, gathered from its
+//! catalog records, extents overflow records, and extended attributes.
+//! Described in TN1150 > Catalog File, for display by `hfsprust stat`.
+
+use crate::alias::{read_alias, resolve_alias};
+use crate::allocation::ForkKind;
+use crate::attributes::{ExtendedAttribute, item_attributes};
+use crate::catalog::{
+    CatalogMap, cnid_to_key, file_special, hard_link_target, path_for_key, read_symlink_target,
+    record_for_cnid,
+};
+use crate::extents::fork_overflow_extents;
+use crate::volume::Volume;
+use crate::*;
+use std::fmt;
+use std::io::{self, Read, Seek};
+
+/// One fork of a file, with every extent that belongs to it.
+#[derive(Debug)]
+pub struct ForkDetails<'a> {
+    pub kind: ForkKind,
+    pub fork: &'a ForkData,
+    /// Extents from the catalog record, without unused descriptors.
+    pub extents: Vec<ExtentDescriptor>,
+    /// Further extents from the Extents Overflow File.
+    pub overflow_extents: Vec<ExtentDescriptor>,
+}
+
+/// Where a link points.
+#[derive(Debug)]
+pub enum LinkTarget {
+    /// Target path of a symbolic link, or why it could not be read.
+    Symlink(Result<String, io::Error>),
+    /// Indirect node file holding a hard link's contents, or `None` if it is
+    /// missing from the metadata folder.
+    HardLink {
+        inode: u32,
+        target: Option<CatalogNodeId>,
+    },
+    /// Target recorded by a Finder alias. Fields are `None` when the alias
+    /// does not record them.
+    Alias {
+        volume: Option<String>,
+        path: Option<String>,
+        cnid: Option<CatalogNodeId>,
+        /// Path of the target on this volume, starting with the volume
+        /// name, or `None` if it is on another volume or no longer exists.
+        resolved: Option<Vec<String>>,
+    },
+    /// Why the record of a Finder alias could not be read.
+    UnreadableAlias(io::Error),
+}
+
+/// Where a file points, if it is a symbolic link, hard link, or Finder
+/// alias. Aliases that hold no recognisable record have no target.
+pub fn link_target(
+    catalog: &CatalogMap,
+    volume: &mut (impl Read + Seek),
+    block_size: usize,
+    file: &CatalogFile,
+) -> Option<LinkTarget> {
+    if file.is_symlink() {
+        return Some(LinkTarget::Symlink(read_symlink_target(
+            volume, block_size, file,
+        )));
+    }
+    if file.is_hard_link() {
+        return Some(LinkTarget::HardLink {
+            inode: file.permissions.special,
+            target: hard_link_target(catalog, file).map(|target| target.file_id),
+        });
+    }
+    match read_alias(volume, block_size, file) {
+        Ok(Some(target)) => {
+            let resolved = resolve_alias(catalog, volume, block_size, &target)
+                .ok()
+                .flatten()
+                .map(|cnid| path_for_key(catalog, cnid_to_key(cnid)));
+            Some(LinkTarget::Alias {
+                volume: target.volume_name,
+                path: target.path,
+                cnid: target.cnid,
+                resolved,
+            })
+        }
+        Ok(None) => None,
+        Err(err) => Some(LinkTarget::UnreadableAlias(err)),
+    }
+}
+
+/// The target alone, as shown after an item's name by `ls -l`.
+impl fmt::Display for LinkTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Symlink(Ok(target)) => write!(f, "{target}"),
+            Self::Symlink(Err(err)) | Self::UnreadableAlias(err) => {
+                write!(f, "(unreadable: {err})")
+            }
+            Self::HardLink { inode, target } => {
+                write!(f, "{}{inode}", catalog::INDIRECT_NODE_PREFIX)?;
+                match target {
+                    Some(target) => write!(f, " (CNID {target})"),
+                    None => write!(f, " (missing)"),
+                }
+            }
+            Self::Alias {
+                volume,
+                path,
+                cnid,
+                resolved,
+            } => {
+                write!(f, "{}", path.as_deref().unwrap_or("(no path)"))?;
+                if let Some(volume) = volume {
+                    write!(f, " on {volume}")?;
+                }
+                if let Some(cnid) = cnid {
+                    write!(f, " (CNID {cnid})")?;
+                }
+                match resolved {
+                    Some(resolved) => write!(
+                        f,
+                        ", resolved to /{}",
+                        resolved.get(1..).unwrap_or_default().join("/")
+                    ),
+                    None => write!(f, ", not found on this volume"),
+                }
+            }
+        }
+    }
+}
+
+/// A file or folder record, along with what else the volume records about it.
+#[derive(Debug)]
+pub struct RecordDetails<'a> {
+    /// Always a [`CatalogLeafRecord::File`] or [`CatalogLeafRecord::Folder`].
+    pub record: &'a CatalogLeafRecord,
+    /// From the thread record, if there is one.
+    pub parent_id: Option<CatalogNodeId>,
+    /// Raw UTF-16 name from the thread record, as stored.
+    pub name: Option<&'a [u16]>,
+    /// Path starting with the volume name.
+    pub path: Vec<String>,
+    /// The `special` field of a file, interpreted using its location. `None`
+    /// for folders.
+    pub special: Option<BsdSpecial>,
+    /// Data fork then resource fork, or empty for folders.
+    pub forks: Vec<ForkDetails<'a>>,
+    pub attributes: Vec<&'a ExtendedAttribute>,
+    pub link: Option<LinkTarget>,
+}
+
+/// Gather the details of a file or folder, or `None` if there is no record
+/// for `cnid`.
+pub fn record_details<R: Read + Seek>(
+    volume: &mut Volume<R>,
+    cnid: CatalogNodeId,
+) -> Option<RecordDetails<'_>> {
+    let block_size = volume.block_size();
+    let path = volume.path(cnid);
+    let Volume {
+        stream,
+        catalog,
+        overflow,
+        attributes,
+        ..
+    } = volume;
+    let catalog: &CatalogMap = catalog;
+
+    let record = record_for_cnid(catalog, cnid)?;
+    let thread = match catalog.get(&cnid_to_key(cnid)) {
+        Some(CatalogLeafRecord::FileThread(thread) | CatalogLeafRecord::FolderThread(thread)) => {
+            Some(thread)
+        }
+        _ => None,
+    };
+
+    let (special, forks, link) = match record {
+        CatalogLeafRecord::File(file) => {
+            let forks = [
+                (ForkKind::Data, &file.data_fork),
+                (ForkKind::Resource, &file.resource_fork),
+            ]
+            .into_iter()
+            .map(|(kind, fork)| ForkDetails {
+                kind,
+                fork,
+                extents: fork
+                    .extents
+                    .iter()
+                    .copied()
+                    .filter(|extent| extent.block_count != 0)
+                    .collect(),
+                overflow_extents: fork_overflow_extents(overflow, file.file_id, kind),
+            })
+            .collect();
+            let link = link_target(catalog, stream, block_size, file);
+            (Some(file_special(catalog, file)), forks, link)
+        }
+        _ => (None, Vec::new(), None),
+    };
+
+    Some(RecordDetails {
+        record,
+        parent_id: thread.map(|thread| thread.parent_id),
+        name: thread.map(|thread| thread.node_name.unicode.as_slice()),
+        path,
+        special,
+        forks,
+        attributes: item_attributes(attributes, cnid).collect(),
+        link,
+    })
+}
+
+impl fmt::Display for ForkDetails<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self.kind {
+            ForkKind::Data => "Data",
+            ForkKind::Resource => "Resource",
+        };
+        writeln!(
+            f,
+            "{name} fork: {} bytes in {} blocks, clump size {}",
+            self.fork.logical_size, self.fork.total_blocks, self.fork.clump_size
+        )?;
+        let overflow = self.overflow_extents.iter().map(|extent| (extent, true));
+        for (extent, from_overflow) in self
+            .extents
+            .iter()
+            .map(|extent| (extent, false))
+            .chain(overflow)
+        {
+            write!(
+                f,
+                "  blocks {}..{} ({} blocks)",
+                extent.start_block,
+                extent.start_block as u64 + extent.block_count as u64,
+                extent.block_count
+            )?;
+            match from_overflow {
+                true => writeln!(f, " from overflow")?,
+                false => writeln!(f)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Formats one `Label: value` line per field, in the order of the record.
+impl fmt::Display for RecordDetails<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (cnid, flags, dates, permissions, text_encoding) = match self.record {
+            CatalogLeafRecord::File(file) => (
+                file.file_id,
+                file.flags,
+                [
+                    file.create_date,
+                    file.content_mod_date,
+                    file.attribute_mod_date,
+                    file.access_date,
+                    file.backup_date,
+                ],
+                &file.permissions,
+                file.text_encoding,
+            ),
+            CatalogLeafRecord::Folder(folder) => (
+                folder.folder_id,
+                folder.flags,
+                [
+                    folder.create_date,
+                    folder.content_mod_date,
+                    folder.attribute_mod_date,
+                    folder.access_date,
+                    folder.backup_date,
+                ],
+                &folder.permissions,
+                folder.text_encoding,
+            ),
+            _ => return Ok(()),
+        };
+
+        writeln!(
+            f,
+            "Path: /{}",
+            self.path.get(1..).unwrap_or_default().join("/")
+        )?;
+        match self.name {
+            Some(name) => {
+                let units = name
+                    .iter()
+                    .map(|unit| format!("{unit:04x}"))
+                    .collect::<Vec<_>>();
+                writeln!(
+                    f,
+                    "Name: {:?} (UTF-16: {})",
+                    String::from_utf16_lossy(name),
+                    units.join(" ")
+                )?;
+            }
+            None => writeln!(f, "Name: (no thread record)")?,
+        }
+        writeln!(f, "CNID: {cnid}")?;
+        match self.parent_id {
+            Some(parent_id) => writeln!(f, "Parent: {parent_id}")?,
+            None => writeln!(f, "Parent: (no thread record)")?,
+        }
+        let mut record_flags = Vec::new();
+        if flags & hfs_types::kHFSFileLockedMask != 0 {
+            record_flags.push("locked");
+        }
+        if flags & hfs_types::kHFSThreadExistsMask != 0 {
+            record_flags.push("thread exists");
+        }
+        match record_flags.is_empty() {
+            true => writeln!(f, "Record flags: {flags:#06x}")?,
+            false => writeln!(
+                f,
+                "Record flags: {flags:#06x} ({})",
+                record_flags.join(", ")
+            )?,
+        }
+
+        for (name, date) in [
+            "Created",
+            "Content modified",
+            "Attributes modified",
+            "Accessed",
+            "Backed up",
+        ]
+        .into_iter()
+        .zip(dates)
+        {
+            match date.is_set() {
+                true => writeln!(f, "{name}: {date}")?,
+                false => writeln!(f, "{name}: never")?,
+            }
+        }
+
+        writeln!(
+            f,
+            "Mode: {:06o} ({})",
+            permissions.mode(),
+            permissions.mode_string()
+        )?;
+        writeln!(
+            f,
+            "Owner: {} Group: {}",
+            permissions.owner_id(),
+            permissions.group_id()
+        )?;
+        writeln!(f, "BSD flags: {}", permissions.flags())?;
+        match self.special {
+            Some(BsdSpecial::InodeNumber(inode)) => writeln!(f, "Inode number: {inode}")?,
+            Some(BsdSpecial::LinkCount(count)) => writeln!(f, "Link count: {count}")?,
+            Some(BsdSpecial::RawDevice(device)) => writeln!(f, "Device: {device:#x}")?,
+            Some(BsdSpecial::Unused(_)) | None => {}
+        }
+
+        match self.record {
+            CatalogLeafRecord::File(file) => {
+                writeln!(
+                    f,
+                    "Type: {} Creator: {}",
+                    os_type_string(file.user_info.file_type()),
+                    os_type_string(file.user_info.creator())
+                )?;
+                writeln!(f, "Finder flags: {}", file.user_info.finder_flags())?;
+                writeln!(
+                    f,
+                    "Extended Finder flags: {}",
+                    file.finder_info.extended_finder_flags()
+                )?;
+                let (v, h) = file.user_info.location();
+                writeln!(f, "Icon location: ({v}, {h})")?;
+                writeln!(
+                    f,
+                    "Put away folder: {}",
+                    file.finder_info.put_away_folder_id()
+                )?;
+            }
+            CatalogLeafRecord::Folder(folder) => {
+                writeln!(f, "Items: {}", folder.valence)?;
+                writeln!(f, "Finder flags: {}", folder.user_info.finder_flags())?;
+                writeln!(
+                    f,
+                    "Extended Finder flags: {}",
+                    folder.finder_info.extended_finder_flags()
+                )?;
+                let (v, h) = folder.user_info.location();
+                writeln!(f, "Icon location: ({v}, {h})")?;
+                let (top, left, bottom, right) = folder.user_info.window_bounds();
+                writeln!(f, "Window bounds: ({top}, {left}) to ({bottom}, {right})")?;
+                let (v, h) = folder.finder_info.scroll_position();
+                writeln!(f, "Scroll position: ({v}, {h})")?;
+                writeln!(
+                    f,
+                    "Put away folder: {}",
+                    folder.finder_info.put_away_folder_id()
+                )?;
+            }
+            _ => {}
+        }
+        match TextEncoding::from_u32(text_encoding) {
+            Some(encoding) => writeln!(f, "Text encoding: {text_encoding} ({encoding:?})")?,
+            None => writeln!(f, "Text encoding: {text_encoding}")?,
+        }
+
+        for fork in &self.forks {
+            write!(f, "{fork}")?;
+        }
+
+        let names = self
+            .attributes
+            .iter()
+            .map(|attribute| attribute.name())
+            .collect::<Vec<_>>();
+        match names.is_empty() {
+            true => writeln!(f, "Extended attributes: -")?,
+            false => writeln!(f, "Extended attributes: {}", names.join(", "))?,
+        }
+
+        match &self.link {
+            Some(link @ LinkTarget::Symlink(_)) => writeln!(f, "Symbolic link to: {link}")?,
+            Some(link @ LinkTarget::HardLink { .. }) => writeln!(f, "Hard link to: {link}")?,
+            Some(link @ (LinkTarget::Alias { .. } | LinkTarget::UnreadableAlias(_))) => {
+                writeln!(f, "Alias to: {link}")?
+            }
+            None => {}
+        }
+        Ok(())
+    }
+}
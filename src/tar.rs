@@ -0,0 +1,448 @@
+//! Writing POSIX pax archives, as described in POSIX.1-2001 > pax > pax
+//! Interchange Format. Each entry is a ustar header, preceded by an extended
+//! header for anything ustar cannot hold: long names, large sizes and IDs,
+//! access times, and extended attributes.
+
+use crate::invalid_input;
+use std::io::{self, Read, Write};
+
+const BLOCK_SIZE: usize = 512;
+
+/// The kind of an archive entry, with what it needs beyond the header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TarEntryKind {
+    File,
+    Directory,
+    /// A symbolic link to this target.
+    Symlink(String),
+    /// A hard link to an earlier entry, by its path in the archive.
+    HardLink(String),
+}
+
+impl TarEntryKind {
+    fn type_flag(&self) -> u8 {
+        match self {
+            Self::File => b'0',
+            Self::HardLink(_) => b'1',
+            Self::Symlink(_) => b'2',
+            Self::Directory => b'5',
+        }
+    }
+
+    fn link_name(&self) -> &str {
+        match self {
+            Self::Symlink(target) | Self::HardLink(target) => target,
+            Self::File | Self::Directory => "",
+        }
+    }
+}
+
+/// Metadata of one archive entry.
+#[derive(Debug, Clone)]
+pub struct TarEntry {
+    /// Slash-separated path within the archive. Directories do not need a
+    /// trailing slash.
+    pub path: String,
+    pub kind: TarEntryKind,
+    /// Permission bits, including setuid, setgid, and sticky.
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    /// Bytes of data following the header. Zero for everything but files.
+    pub size: u64,
+    /// Seconds since the Unix epoch.
+    pub modified: i64,
+    pub accessed: Option<i64>,
+    /// Extended attributes, stored as `SCHILY.xattr` records.
+    pub xattrs: Vec<(String, Vec<u8>)>,
+}
+
+impl TarEntry {
+    pub fn new(path: impl Into<String>, kind: TarEntryKind) -> Self {
+        Self {
+            path: path.into(),
+            kind,
+            mode: 0o644,
+            uid: 0,
+            gid: 0,
+            size: 0,
+            modified: 0,
+            accessed: None,
+            xattrs: Vec::new(),
+        }
+    }
+}
+
+/// Format a number as NUL-terminated octal filling a header field, or `None`
+/// if it does not fit.
+fn octal_field(field: &mut [u8], value: u64) -> Option<()> {
+    let digits = format!("{value:0width$o}", width = field.len() - 1);
+    if digits.len() >= field.len() {
+        return None;
+    }
+    field[..digits.len()].copy_from_slice(digits.as_bytes());
+    field[digits.len()] = 0;
+    Some(())
+}
+
+/// A pax extended header record: `<length> <key>=<value>\n`, where the
+/// length counts the whole record including itself.
+fn pax_record(key: &str, value: &[u8]) -> Vec<u8> {
+    let body_length = key.len() + value.len() + 3;
+    let mut length = body_length + 1;
+    while length.to_string().len() + body_length != length {
+        length = length.to_string().len() + body_length;
+    }
+    let mut record = format!("{length} {key}=").into_bytes();
+    record.extend_from_slice(value);
+    record.push(b'\n');
+    record
+}
+
+fn pad_to_block(output: &mut impl Write, length: u64) -> Result<(), io::Error> {
+    let padding = (BLOCK_SIZE - (length % BLOCK_SIZE as u64) as usize) % BLOCK_SIZE;
+    output.write_all(&[0u8; BLOCK_SIZE][..padding])
+}
+
+/// Streams entries into a pax archive.
+pub struct TarWriter<W: Write> {
+    output: W,
+    /// Copy buffer shared by every entry, grown to at most 1 MiB as larger
+    /// entries need it.
+    buf: Vec<u8>,
+}
+
+impl<W: Write> TarWriter<W> {
+    pub fn new(output: W) -> Self {
+        Self {
+            output,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Write an entry, followed by `entry.size` bytes of data from `data`.
+    /// If `data` ends early or fails, the rest is filled with zeroes so that
+    /// the archive stays readable. Returns the number of bytes taken from
+    /// `data`, and the error that stopped it, if any.
+    pub fn append(
+        &mut self,
+        entry: &TarEntry,
+        data: &mut impl Read,
+    ) -> Result<(u64, Option<io::Error>), io::Error> {
+        self.write_header(entry)?;
+
+        let buf_len = entry.size.min(1 << 20) as usize;
+        if self.buf.len() < buf_len {
+            self.buf.resize(buf_len, 0);
+        }
+        let mut copied = 0u64;
+        let mut read_error = None;
+        while copied < entry.size {
+            let wanted = (entry.size - copied).min(buf_len as u64) as usize;
+            match data.read(&mut self.buf[..wanted]) {
+                Ok(0) => break,
+                Ok(read) => {
+                    self.output.write_all(&self.buf[..read])?;
+                    copied += read as u64;
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    read_error = Some(err);
+                    break;
+                }
+            }
+        }
+        let mut remaining = entry.size - copied;
+        while remaining > 0 {
+            let length = remaining.min(BLOCK_SIZE as u64) as usize;
+            self.output.write_all(&[0u8; BLOCK_SIZE][..length])?;
+            remaining -= length as u64;
+        }
+        pad_to_block(&mut self.output, entry.size)?;
+        Ok((copied, read_error))
+    }
+
+    fn write_header(&mut self, entry: &TarEntry) -> Result<(), io::Error> {
+        let mut path = entry.path.clone();
+        if entry.kind == TarEntryKind::Directory && !path.ends_with('/') {
+            path.push('/');
+        }
+        let link_name = entry.kind.link_name();
+
+        let mut header = [0u8; BLOCK_SIZE];
+        let mut records = Vec::new();
+        // Names that do not fit, or are not plain ASCII, go in the extended
+        // header. The ustar fields get a truncated copy for older readers.
+        let fits = |value: &str, length: usize| value.len() <= length && value.is_ascii();
+        if !fits(&path, 100) {
+            records.push(pax_record("path", path.as_bytes()));
+        }
+        copy_truncated(&mut header[0..100], &path);
+        if !fits(link_name, 100) {
+            records.push(pax_record("linkpath", link_name.as_bytes()));
+        }
+        copy_truncated(&mut header[157..257], link_name);
+
+        octal_field(&mut header[100..108], u64::from(entry.mode & 0o7777))
+            .ok_or_else(|| invalid_input("Mode does not fit"))?;
+        if octal_field(&mut header[108..116], u64::from(entry.uid)).is_none() {
+            records.push(pax_record("uid", entry.uid.to_string().as_bytes()));
+        }
+        if octal_field(&mut header[116..124], u64::from(entry.gid)).is_none() {
+            records.push(pax_record("gid", entry.gid.to_string().as_bytes()));
+        }
+        if octal_field(&mut header[124..136], entry.size).is_none() {
+            records.push(pax_record("size", entry.size.to_string().as_bytes()));
+        }
+        match u64::try_from(entry.modified)
+            .ok()
+            .and_then(|modified| octal_field(&mut header[136..148], modified))
+        {
+            Some(()) => {}
+            None => records.push(pax_record("mtime", entry.modified.to_string().as_bytes())),
+        }
+        if let Some(accessed) = entry.accessed {
+            records.push(pax_record("atime", accessed.to_string().as_bytes()));
+        }
+        for (name, value) in &entry.xattrs {
+            records.push(pax_record(&format!("SCHILY.xattr.{name}"), value));
+        }
+
+        header[156] = entry.kind.type_flag();
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+
+        if !records.is_empty() {
+            let records = records.concat();
+            let mut extended = [0u8; BLOCK_SIZE];
+            // Named as by GNU tar, with the entry's name for readers that
+            // treat the extended header as a file.
+            let name = format!(
+                "PaxHeaders/{}",
+                path.trim_end_matches('/')
+                    .rsplit('/')
+                    .next()
+                    .unwrap_or_default()
+            );
+            copy_truncated(&mut extended[0..100], &name);
+            octal_field(&mut extended[100..108], 0o644);
+            octal_field(&mut extended[108..116], 0);
+            octal_field(&mut extended[116..124], 0);
+            octal_field(&mut extended[124..136], records.len() as u64)
+                .ok_or_else(|| invalid_input("Extended header is too large"))?;
+            extended[136..148].copy_from_slice(&header[136..148]);
+            extended[156] = b'x';
+            extended[257..263].copy_from_slice(b"ustar\0");
+            extended[263..265].copy_from_slice(b"00");
+            set_checksum(&mut extended);
+            self.output.write_all(&extended)?;
+            self.output.write_all(&records)?;
+            pad_to_block(&mut self.output, records.len() as u64)?;
+        }
+
+        set_checksum(&mut header);
+        self.output.write_all(&header)
+    }
+
+    /// End the archive with two empty blocks, and return the output.
+    pub fn finish(mut self) -> Result<W, io::Error> {
+        self.output.write_all(&[0u8; 2 * BLOCK_SIZE])?;
+        self.output.flush()?;
+        Ok(self.output)
+    }
+}
+
+/// Copy as much of a string as fits, without splitting a character.
+fn copy_truncated(field: &mut [u8], value: &str) {
+    let mut end = value.len().min(field.len());
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    field[..end].copy_from_slice(&value.as_bytes()[..end]);
+}
+
+/// The checksum is the sum of the header's bytes, with the checksum field
+/// itself counted as spaces.
+fn set_checksum(header: &mut [u8; BLOCK_SIZE]) {
+    header[148..156].fill(b' ');
+    let sum = header.iter().map(|&b| u32::from(b)).sum::<u32>();
+    let digits = format!("{sum:06o}\0 ");
+    header[148..156].copy_from_slice(digits.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(entries: &[(TarEntry, &[u8])]) -> Vec<u8> {
+        let mut tar = TarWriter::new(Vec::new());
+        for (entry, data) in entries {
+            tar.append(entry, &mut &data[..]).unwrap();
+        }
+        tar.finish().unwrap()
+    }
+
+    /// An entry as read back by the `tar` crate.
+    struct ReadEntry {
+        path: String,
+        size: u64,
+        data: Vec<u8>,
+        records: Vec<(String, Vec<u8>)>,
+    }
+
+    fn read(archive: &[u8]) -> Vec<ReadEntry> {
+        let mut archive = ::tar::Archive::new(archive);
+        archive
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let mut entry = entry.unwrap();
+                let path = entry.path().unwrap().to_string_lossy().into_owned();
+                let size = entry.size();
+                let records = entry
+                    .pax_extensions()
+                    .unwrap()
+                    .into_iter()
+                    .flatten()
+                    .map(|record| {
+                        let record = record.unwrap();
+                        (
+                            record.key().unwrap().to_string(),
+                            record.value_bytes().to_vec(),
+                        )
+                    })
+                    .collect();
+                let mut data = Vec::new();
+                entry.read_to_end(&mut data).unwrap();
+                ReadEntry {
+                    path,
+                    size,
+                    data,
+                    records,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut folder = TarEntry::new("Volume", TarEntryKind::Directory);
+        folder.mode = 0o755;
+        folder.modified = 1_700_000_000;
+        let mut file = TarEntry::new("Volume/hello.txt", TarEntryKind::File);
+        file.size = 12;
+        file.uid = 501;
+        file.gid = 20;
+        file.modified = 1_700_000_123;
+        let link = TarEntry::new("Volume/link", TarEntryKind::Symlink("hello.txt".into()));
+        let hard_link = TarEntry::new(
+            "Volume/hard",
+            TarEntryKind::HardLink("Volume/hello.txt".into()),
+        );
+        let archive = write(&[
+            (folder, b""),
+            (file, b"Hello world\n"),
+            (link, b""),
+            (hard_link, b""),
+        ]);
+        assert_eq!(archive.len() % BLOCK_SIZE, 0);
+
+        let mut tar = ::tar::Archive::new(archive.as_slice());
+        let headers = tar
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().header().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(headers.len(), 4);
+        assert_eq!(headers[0].path().unwrap().to_str(), Some("Volume/"));
+        assert_eq!(headers[0].entry_type(), ::tar::EntryType::Directory);
+        assert_eq!(headers[0].mode().unwrap(), 0o755);
+        assert_eq!(headers[0].mtime().unwrap(), 1_700_000_000);
+        assert_eq!(
+            headers[1].path().unwrap().to_str(),
+            Some("Volume/hello.txt")
+        );
+        assert_eq!(headers[1].size().unwrap(), 12);
+        assert_eq!(headers[1].uid().unwrap(), 501);
+        assert_eq!(headers[1].gid().unwrap(), 20);
+        assert_eq!(headers[1].mtime().unwrap(), 1_700_000_123);
+        assert_eq!(headers[2].entry_type(), ::tar::EntryType::Symlink);
+        assert_eq!(
+            headers[2].link_name().unwrap().unwrap().to_str(),
+            Some("hello.txt")
+        );
+        assert_eq!(headers[3].entry_type(), ::tar::EntryType::Link);
+        assert_eq!(
+            headers[3].link_name().unwrap().unwrap().to_str(),
+            Some("Volume/hello.txt")
+        );
+
+        let entries = read(&archive);
+        assert_eq!(entries[1].data, b"Hello world\n");
+    }
+
+    #[test]
+    fn extended_header() {
+        let long_name = format!("Volume/{}/Résumé.txt", "folder".repeat(20));
+        let mut entry = TarEntry::new(long_name.as_str(), TarEntryKind::File);
+        entry.size = 3;
+        entry.uid = u32::MAX;
+        // Before 1970, which ustar cannot hold.
+        entry.modified = -86_400;
+        entry.accessed = Some(1_700_000_000);
+        entry.xattrs = vec![("com.apple.FinderInfo".into(), vec![0, 1, 2, 255])];
+        let entries = read(&write(&[(entry, b"abc")]));
+
+        assert_eq!(entries.len(), 1);
+        let entry = &entries[0];
+        assert_eq!(entry.path, long_name);
+        assert_eq!(entry.size, 3);
+        assert_eq!(entry.data, b"abc");
+        let record = |key: &str| {
+            entry
+                .records
+                .iter()
+                .find(|(record_key, _)| record_key == key)
+                .map(|(_, value)| value.as_slice())
+        };
+        assert_eq!(record("path"), Some(long_name.as_bytes()));
+        assert_eq!(record("uid"), Some(b"4294967295".as_slice()));
+        assert_eq!(record("mtime"), Some(b"-86400".as_slice()));
+        assert_eq!(record("atime"), Some(b"1700000000".as_slice()));
+        assert_eq!(
+            record("SCHILY.xattr.com.apple.FinderInfo"),
+            Some([0, 1, 2, 255].as_slice())
+        );
+    }
+
+    #[test]
+    fn short_data_is_padded() {
+        let mut short = TarEntry::new("short", TarEntryKind::File);
+        short.size = 1000;
+        let mut next = TarEntry::new("next", TarEntryKind::File);
+        next.size = 4;
+
+        let mut tar = TarWriter::new(Vec::new());
+        let (copied, error) = tar.append(&short, &mut b"data".as_slice()).unwrap();
+        assert_eq!(copied, 4);
+        assert!(error.is_none());
+        tar.append(&next, &mut b"next".as_slice()).unwrap();
+        let entries = read(&tar.finish().unwrap());
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].size, 1000);
+        assert_eq!(&entries[0].data[..4], b"data");
+        assert!(entries[0].data[4..].iter().all(|&b| b == 0));
+        assert_eq!(entries[1].path, "next");
+        assert_eq!(entries[1].data, b"next");
+    }
+
+    #[test]
+    fn pax_record_length_counts_itself() {
+        assert_eq!(pax_record("path", b"a"), b"9 path=a\n");
+        // Two digits would make the record 100 bytes long, so it needs three.
+        let record = pax_record("path", &[b'a'; 91]);
+        assert!(record.starts_with(b"101 path="));
+        assert_eq!(record.len(), 101);
+    }
+}
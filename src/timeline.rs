@@ -0,0 +1,290 @@
+//! Timelines of file system activity, as Sleuth Kit bodyfile lines or sorted
+//! like the output of `mactime`.
+//!
+//! The bodyfile's times are mapped from the catalog's dates: `atime` from
+//! `access_date`, `mtime` from `content_mod_date`, `ctime` from
+//! `attribute_mod_date`, and `crtime` from `create_date`. Described in TN1150
+//! > HFS Plus Dates.
+
+use crate::allocation::ForkKind;
+use crate::catalog::record_for_cnid;
+use crate::export::catalog_entries;
+use crate::fork::{ForkReader, HashAlgorithm, hash_reader};
+use crate::query::{DateRange, ItemKind};
+use crate::recovery::orphan_path;
+use crate::volume::Volume;
+use crate::*;
+use std::io::{self, Read, Seek, Write};
+
+/// Which records to include in a timeline, and what to compute for them.
+#[derive(Debug, Clone, Default)]
+pub struct TimelineOptions {
+    /// Also include records left behind by deleted items.
+    pub deleted: bool,
+    /// Hash each file's data fork with MD5. Otherwise the bodyfile's MD5
+    /// field is `0`.
+    pub md5: bool,
+}
+
+/// One file or folder, as a line of a bodyfile.
+#[derive(Debug, Clone)]
+pub struct TimelineEntry {
+    /// MD5 of the data fork, if it was hashed.
+    pub md5: Option<String>,
+    /// Slash-separated path from the root folder. Deleted items whose
+    /// parent is unknown start with `#` and the parent's CNID instead.
+    pub path: String,
+    pub cnid: CatalogNodeId,
+    /// Mode in the Sleuth Kit's style, such as `r/rrw-r--r--`.
+    pub mode: String,
+    pub uid: u32,
+    pub gid: u32,
+    /// Size of the data fork, or zero for folders.
+    pub size: u64,
+    pub accessed: Date,
+    pub modified: Date,
+    pub changed: Date,
+    pub created: Date,
+    /// Found in a record that is no longer part of the catalog.
+    pub deleted: bool,
+}
+
+/// The Sleuth Kit's letter for an item's type, taken from the mode if it was
+/// set.
+pub(crate) fn type_letter(permissions: &BsdInfo, kind: ItemKind) -> char {
+    match permissions.file_type() {
+        Some(FileType::Regular) => 'r',
+        Some(FileType::Directory) => 'd',
+        Some(FileType::SymbolicLink) => 'l',
+        Some(FileType::Fifo) => 'p',
+        Some(FileType::CharacterDevice) => 'c',
+        Some(FileType::BlockDevice) => 'b',
+        Some(FileType::Socket) => 'h',
+        Some(FileType::Whiteout) => 'w',
+        None => match kind {
+            ItemKind::File => 'r',
+            ItemKind::Folder => 'd',
+            ItemKind::Symlink => 'l',
+        },
+    }
+}
+
+impl TimelineEntry {
+    /// Describe a file or folder record, or `None` for thread records.
+    pub fn new(path: String, record: &CatalogLeafRecord, deleted: bool) -> Option<Self> {
+        let kind = ItemKind::of(record)?;
+        let (cnid, permissions, size, dates) = match record {
+            CatalogLeafRecord::File(file) => (
+                file.file_id,
+                &file.permissions,
+                file.data_fork.logical_size,
+                [
+                    file.access_date,
+                    file.content_mod_date,
+                    file.attribute_mod_date,
+                    file.create_date,
+                ],
+            ),
+            CatalogLeafRecord::Folder(folder) => (
+                folder.folder_id,
+                &folder.permissions,
+                0,
+                [
+                    folder.access_date,
+                    folder.content_mod_date,
+                    folder.attribute_mod_date,
+                    folder.create_date,
+                ],
+            ),
+            _ => return None,
+        };
+
+        let type_char = type_letter(permissions, kind);
+        let mode_string = permissions.mode_string();
+        let [accessed, modified, changed, created] = dates;
+        Some(Self {
+            md5: None,
+            path,
+            cnid,
+            mode: format!(
+                "{type_char}/{type_char}{}",
+                mode_string.get(1..).unwrap_or_default()
+            ),
+            uid: permissions.owner_id(),
+            gid: permissions.group_id(),
+            size,
+            accessed,
+            modified,
+            changed,
+            created,
+            deleted,
+        })
+    }
+
+    /// The name as written to a bodyfile. Control characters, such as the
+    /// NULs in the metadata folder names, are replaced with `^` as the Sleuth
+    /// Kit does, and deleted items are marked with ` (deleted)`.
+    pub fn name(&self) -> String {
+        let mut name = self
+            .path
+            .chars()
+            .map(|c| if c.is_control() { '^' } else { c })
+            .collect::<String>();
+        if self.deleted {
+            name.push_str(" (deleted)");
+        }
+        name
+    }
+
+    /// A line of the Sleuth Kit 3 bodyfile format, without a line ending:
+    /// `MD5|name|inode|mode_as_string|UID|GID|size|atime|mtime|ctime|crtime`,
+    /// with times in seconds since the Unix epoch, or zero if never set.
+    pub fn bodyfile_line(&self) -> String {
+        let time = |date: Date| date.to_unix().unwrap_or(0);
+        format!(
+            "{}|{}|{}|{}|{}|{}|{}|{}|{}|{}|{}",
+            self.md5.as_deref().unwrap_or("0"),
+            self.name(),
+            self.cnid,
+            self.mode,
+            self.uid,
+            self.gid,
+            self.size,
+            time(self.accessed),
+            time(self.modified),
+            time(self.changed),
+            time(self.created),
+        )
+    }
+}
+
+/// Every file and folder in the catalog, in key order, followed by records of
+/// deleted items if requested.
+pub fn timeline_entries<R: Read + Seek>(
+    volume: &mut Volume<R>,
+    options: &TimelineOptions,
+) -> Result<Vec<TimelineEntry>, io::Error> {
+    let mut entries = catalog_entries(&volume.catalog)
+        .into_iter()
+        .filter_map(|entry| {
+            let record = record_for_entry(&volume.catalog, entry.cnid, entry.parent_id)?;
+            TimelineEntry::new(entry.path, record, false)
+        })
+        .collect::<Vec<_>>();
+
+    let orphans = match options.deleted {
+        true => volume.orphans()?.0,
+        false => Vec::new(),
+    };
+    for orphan in &orphans {
+        let path = orphan_path(&volume.catalog, &orphans, orphan);
+        let path = match path.first() {
+            Some(first) if first.starts_with('#') => path.join("/"),
+            _ => format!("/{}", path.get(1..).unwrap_or_default().join("/")),
+        };
+        entries.extend(TimelineEntry::new(path, &orphan.leaf, true));
+    }
+
+    if options.md5 {
+        let block_size = volume.block_size();
+        for entry in &mut entries {
+            let file = match entry.deleted {
+                false => record_for_cnid(&volume.catalog, entry.cnid),
+                true => orphans
+                    .iter()
+                    .find(|orphan| orphan.cnid() == Some(entry.cnid))
+                    .map(|orphan| &orphan.leaf),
+            };
+            let Some(CatalogLeafRecord::File(file)) = file else {
+                continue;
+            };
+            let extents = volume.fork_extents(file, ForkKind::Data);
+            let logical_size = file.data_fork.logical_size;
+            let mut reader = ForkReader::new(&mut volume.stream, block_size, extents, logical_size)
+                .with_damage(&volume.damage, &volume.fill);
+            // Unreadable forks are left unhashed, as `0`.
+            if let Ok((_, md5)) = hash_reader(&mut reader, HashAlgorithm::Md5) {
+                entry.md5 = Some(md5);
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// The file or folder record stored under a parent, found by CNID.
+fn record_for_entry(
+    map: &catalog::CatalogMap,
+    cnid: CatalogNodeId,
+    parent: CatalogNodeId,
+) -> Option<&CatalogLeafRecord> {
+    catalog::children(map, parent)
+        .map(|(_, record)| record)
+        .find(|record| match record {
+            CatalogLeafRecord::File(file) => file.file_id == cnid,
+            CatalogLeafRecord::Folder(folder) => folder.folder_id == cnid,
+            _ => false,
+        })
+}
+
+/// One point in a timeline: an item and which of its times fall on a date.
+#[derive(Debug, Clone, Copy)]
+pub struct TimelineEvent<'a> {
+    pub date: Date,
+    /// `m`, `a`, `c`, and `b` for the times that match the date, with `.`
+    /// for the others, as in `mactime`.
+    pub macb: [char; 4],
+    pub entry: &'a TimelineEntry,
+}
+
+/// Every distinct time of every entry within `range`, ordered by date and
+/// then path. Times that were never set are left out.
+pub fn timeline_events(entries: &[TimelineEntry], range: DateRange) -> Vec<TimelineEvent<'_>> {
+    let mut events = Vec::new();
+    for entry in entries {
+        let times = [entry.modified, entry.accessed, entry.changed, entry.created];
+        let mut dates = times
+            .iter()
+            .copied()
+            .filter(|date| date.is_set() && range.contains(*date))
+            .collect::<Vec<_>>();
+        dates.sort();
+        dates.dedup();
+        for date in dates {
+            let mut macb = ['.'; 4];
+            for ((letter, time), slot) in ['m', 'a', 'c', 'b'].into_iter().zip(times).zip(&mut macb)
+            {
+                if time == date {
+                    *slot = letter;
+                }
+            }
+            events.push(TimelineEvent { date, macb, entry });
+        }
+    }
+    events.sort_by(|a, b| (a.date, &a.entry.path).cmp(&(b.date, &b.entry.path)));
+    events
+}
+
+/// Write events in the layout of `mactime`, with each date shown only on the
+/// first of its lines.
+pub fn write_mactime(output: &mut impl Write, events: &[TimelineEvent]) -> Result<(), io::Error> {
+    let mut previous = None;
+    for event in events {
+        let date = match previous == Some(event.date) {
+            true => String::new(),
+            false => event.date.to_string(),
+        };
+        previous = Some(event.date);
+        writeln!(
+            output,
+            "{date:<20} {:>10} {} {} {:<8} {:<8} {:<8} {}",
+            event.entry.size,
+            event.macb.iter().collect::<String>(),
+            event.entry.mode,
+            event.entry.uid,
+            event.entry.gid,
+            event.entry.cnid,
+            event.entry.name(),
+        )?;
+    }
+    Ok(())
+}
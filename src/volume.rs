@@ -0,0 +1,263 @@
+//! An HFS+ volume opened for reading, with the catalog and extents overflow
+//! records that most operations need. Damaged structures are worked around
+//! where possible, and noted in [`Volume::warnings`].
+
+use crate::allocation::ForkKind;
+use crate::attributes::{ExtendedAttribute, read_attributes};
+use crate::btree::{Diagnostic, salvage_btree_leaves};
+use crate::catalog::{CatalogMap, cnid_to_key, lookup_path, path_for_key, record_for_cnid};
+use crate::damage::DamageMap;
+use crate::extents::{OverflowExtents, fork_overflow_extents, read_overflow_extents};
+use crate::fork::{ForkReader, assemble_fork};
+use crate::recovery::{
+    OrphanRecord, carve_catalog_records, find_orphan_records, infer_node_size, rebuild_catalog,
+    scan_leaf_records,
+};
+use crate::*;
+use std::io::{self, Cursor, Read, Seek};
+
+/// Where the catalog records came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CatalogSource {
+    /// The catalog B-tree, starting from its header node.
+    BTree,
+    /// Leaf nodes found by scanning the catalog file.
+    Rebuilt { node_size: usize },
+    /// Leaf nodes carved from the whole volume.
+    Carved,
+}
+
+/// How to load the catalog. By default the B-tree is read, falling back to
+/// rebuilding it from leaf nodes, and then to carving leaf nodes from the
+/// volume.
+#[derive(Debug, Clone, Default)]
+pub struct OpenOptions {
+    /// Rebuild the catalog from leaf nodes even if its header node is intact.
+    pub rebuild_catalog: bool,
+    /// Carve catalog leaf nodes from the whole volume, ignoring the catalog
+    /// file's extents.
+    pub carve_catalog: bool,
+}
+
+#[derive(Debug)]
+pub struct Volume<R> {
+    /// The volume, starting with its boot blocks.
+    pub stream: R,
+    pub header: VolumeHeader,
+    pub catalog: CatalogMap,
+    pub catalog_source: CatalogSource,
+    /// Contents of the catalog file, for finding deleted records. Empty if it
+    /// could not be read.
+    pub catalog_file: Vec<u8>,
+    pub overflow: Vec<OverflowExtents>,
+    pub attributes: Vec<ExtendedAttribute>,
+    /// Regions of the volume to treat as unreadable: the blocks spared by the
+    /// file system, and any added with [`Volume::add_damage`].
+    pub damage: DamageMap,
+    /// Replaces damaged bytes read through [`Volume::fork_reader`]; see
+    /// [`crate::damage::fill_damaged`].
+    pub fill: Vec<u8>,
+    /// Damage that was skipped or worked around while opening the volume.
+    pub warnings: Vec<String>,
+}
+
+impl<R: Read + Seek> Volume<R> {
+    /// Read the volume header, catalog, extents overflow records, and
+    /// extended attributes. Only a missing or unreadable volume header is an
+    /// error.
+    pub fn open(mut stream: R, options: &OpenOptions) -> Result<Self, io::Error> {
+        let header = VolumeHeader::read_from(&mut stream)?;
+        let block_size = header.block_size as usize;
+        let mut warnings = Vec::new();
+
+        let overflow = match read_overflow_extents(&mut stream, &header) {
+            Ok((overflow, diagnostics)) => {
+                warnings.extend(
+                    diagnostics
+                        .iter()
+                        .map(|diagnostic| format!("Extents overflow {diagnostic}")),
+                );
+                overflow
+            }
+            Err(err) => {
+                warnings.push(format!("Extents overflow file could not be read: {err}"));
+                Vec::new()
+            }
+        };
+
+        let attributes = match read_attributes(&mut stream, &header) {
+            Ok((attributes, diagnostics)) => {
+                warnings.extend(
+                    diagnostics
+                        .iter()
+                        .map(|diagnostic| format!("Attributes {diagnostic}")),
+                );
+                attributes
+            }
+            Err(err) => {
+                warnings.push(format!("Attributes file could not be read: {err}"));
+                Vec::new()
+            }
+        };
+
+        // The catalog's own extents may be damaged too, in which case its
+        // nodes are carved from the volume instead.
+        let catalog_file = if options.carve_catalog {
+            Vec::new()
+        } else {
+            let overflow_extents = fork_overflow_extents(
+                &overflow,
+                StandardCnid::kHFSCatalogFileID as CatalogNodeId,
+                ForkKind::Data,
+            );
+            assemble_fork(
+                &mut stream,
+                &header.catalog_file,
+                &overflow_extents,
+                block_size,
+            )
+            .unwrap_or_else(|err| {
+                warnings.push(format!("Catalog file could not be read: {err}"));
+                Vec::new()
+            })
+        };
+        let mut cursor = Cursor::new(catalog_file);
+
+        let salvaged = if options.rebuild_catalog || options.carve_catalog {
+            None
+        } else {
+            salvage_btree_leaves(&mut cursor, block_size)
+                .inspect_err(|err| warnings.push(format!("Catalog header is unreadable: {err}")))
+                .ok()
+        };
+        let (catalog, catalog_source) = match salvaged {
+            Some((catalog, diagnostics)) => {
+                warnings.extend(
+                    diagnostics
+                        .iter()
+                        .map(|diagnostic| format!("Catalog {diagnostic}")),
+                );
+                (catalog, CatalogSource::BTree)
+            }
+            // Without a usable header node, rebuild the catalog from its leaf
+            // nodes.
+            None => match infer_node_size(&mut cursor)? {
+                Some(node_size) => (
+                    rebuild_catalog(scan_leaf_records(&mut cursor, node_size, node_size)?),
+                    CatalogSource::Rebuilt { node_size },
+                ),
+                None => (
+                    rebuild_catalog(carve_catalog_records(&mut stream, block_size, None)?),
+                    CatalogSource::Carved,
+                ),
+            },
+        };
+
+        let mut damage = DamageMap::default();
+        damage.add_bad_blocks(&overflow, block_size);
+
+        Ok(Self {
+            stream,
+            header,
+            catalog,
+            catalog_source,
+            catalog_file: cursor.into_inner(),
+            overflow,
+            attributes,
+            damage,
+            fill: Vec::new(),
+            warnings,
+        })
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.header.block_size as usize
+    }
+
+    /// Treat more regions of the volume as unreadable, such as those from a
+    /// ddrescue mapfile. Offsets are relative to the start of the volume; see
+    /// [`DamageMap::relative_to`].
+    pub fn add_damage(&mut self, damage: &DamageMap) {
+        self.damage.merge(damage);
+    }
+
+    /// Path of a file or folder, starting with the volume name.
+    pub fn path(&self, cnid: CatalogNodeId) -> Vec<String> {
+        path_for_key(&self.catalog, cnid_to_key(cnid))
+    }
+
+    /// Find the CNID of a slash-separated path relative to the root folder.
+    /// See [`lookup_path`].
+    pub fn lookup(
+        &mut self,
+        path: &str,
+        follow_symlinks: bool,
+    ) -> Result<Option<CatalogNodeId>, io::Error> {
+        let block_size = self.block_size();
+        lookup_path(
+            &self.catalog,
+            &mut self.stream,
+            block_size,
+            path,
+            follow_symlinks,
+        )
+    }
+
+    /// The file or folder record for a CNID.
+    pub fn record(&self, cnid: CatalogNodeId) -> Option<&CatalogLeafRecord> {
+        record_for_cnid(&self.catalog, cnid)
+    }
+
+    /// All extents of a fork, including those from overflow records.
+    pub fn fork_extents(&self, file: &CatalogFile, fork: ForkKind) -> Vec<ExtentDescriptor> {
+        let fork_data = match fork {
+            ForkKind::Data => &file.data_fork,
+            ForkKind::Resource => &file.resource_fork,
+        };
+        fork_data
+            .extents
+            .iter()
+            .copied()
+            .chain(fork_overflow_extents(&self.overflow, file.file_id, fork))
+            .collect()
+    }
+
+    /// Stream the contents of a file's fork, with damaged regions replaced by
+    /// [`Volume::fill`].
+    pub fn fork_reader(
+        &mut self,
+        cnid: CatalogNodeId,
+        fork: ForkKind,
+    ) -> Result<ForkReader<'_, R>, io::Error> {
+        let Some(CatalogLeafRecord::File(file)) = self.record(cnid) else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No file with CNID {cnid}"),
+            ));
+        };
+        let extents = self.fork_extents(file, fork);
+        let logical_size = match fork {
+            ForkKind::Data => file.data_fork.logical_size,
+            ForkKind::Resource => file.resource_fork.logical_size,
+        };
+        let block_size = self.block_size();
+        Ok(
+            ForkReader::new(&mut self.stream, block_size, extents, logical_size)
+                .with_damage(&self.damage, &self.fill),
+        )
+    }
+
+    /// Records left behind in free or unlinked catalog nodes. A rebuilt or
+    /// carved catalog already includes every record that could be found, so
+    /// there are none.
+    pub fn orphans(&self) -> Result<(Vec<OrphanRecord>, Vec<Diagnostic>), io::Error> {
+        if self.catalog_source != CatalogSource::BTree {
+            return Ok((Vec::new(), Vec::new()));
+        }
+        find_orphan_records(
+            &mut Cursor::new(&self.catalog_file),
+            self.block_size(),
+            &self.catalog,
+        )
+    }
+}
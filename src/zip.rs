@@ -0,0 +1,698 @@
+//! Writing ZIP archives, as described in PKWARE's APPNOTE.TXT. Entries are
+//! stored without compression, with names flagged as UTF-8, and with ZIP64
+//! extensions for sizes and offsets that do not fit in 32 bits. Unix modes
+//! and owners are kept as Info-ZIP does, and times in extended timestamps.
+
+use crate::invalid_input;
+use hfs_types_rs::civil_from_days;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const END_SIGNATURE: u32 = 0x0605_4b50;
+const ZIP64_END_SIGNATURE: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x0706_4b50;
+
+/// Names and comments are UTF-8.
+const FLAG_UTF8: u16 = 1 << 11;
+/// Made on Unix, by version 3.0 of the specification.
+const VERSION_MADE_BY: u16 = 3 << 8 | 30;
+const VERSION_NEEDED: u16 = 20;
+const VERSION_NEEDED_ZIP64: u16 = 45;
+
+const ZIP64_EXTRA: u16 = 0x0001;
+const EXTENDED_TIMESTAMP_EXTRA: u16 = 0x5455;
+const UNIX_OWNER_EXTRA: u16 = 0x7875;
+
+/// The largest value of a 32-bit field. Larger values are stored in the
+/// ZIP64 extra field, with this as a placeholder.
+const ZIP32_LIMIT: u64 = 0xffff_ffff;
+
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+const S_IFLNK: u32 = 0o120000;
+const MSDOS_DIRECTORY: u32 = 0x10;
+
+/// The kind of an archive entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ZipEntryKind {
+    File,
+    Directory,
+    /// A symbolic link to this target, which is stored as its contents.
+    Symlink(String),
+}
+
+/// Metadata of one archive entry.
+#[derive(Debug, Clone)]
+pub struct ZipEntry {
+    /// Slash-separated path within the archive. Directories do not need a
+    /// trailing slash.
+    pub path: String,
+    pub kind: ZipEntryKind,
+    /// Permission bits, including setuid, setgid, and sticky.
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    /// Bytes of data following the header. Only used for files.
+    pub size: u64,
+    /// Seconds since the Unix epoch.
+    pub modified: i64,
+    pub accessed: Option<i64>,
+}
+
+impl ZipEntry {
+    pub fn new(path: impl Into<String>, kind: ZipEntryKind) -> Self {
+        Self {
+            path: path.into(),
+            kind,
+            mode: 0o644,
+            uid: 0,
+            gid: 0,
+            size: 0,
+            modified: 0,
+            accessed: None,
+        }
+    }
+
+    fn name(&self) -> String {
+        match self.kind {
+            ZipEntryKind::Directory if !self.path.ends_with('/') => format!("{}/", self.path),
+            _ => self.path.clone(),
+        }
+    }
+
+    /// The Unix file type and permissions, as stored in the upper half of
+    /// the external attributes.
+    fn unix_mode(&self) -> u32 {
+        let file_type = match self.kind {
+            ZipEntryKind::File => S_IFREG,
+            ZipEntryKind::Directory => S_IFDIR,
+            ZipEntryKind::Symlink(_) => S_IFLNK,
+        };
+        file_type | (self.mode & 0o7777)
+    }
+
+    /// Extended timestamp and Unix owner extra fields. The central directory
+    /// only has the modification time.
+    fn extra_fields(&self, central: bool) -> Vec<u8> {
+        let mut times = Vec::new();
+        let mut flags = 0u8;
+        if let Ok(modified) = i32::try_from(self.modified) {
+            flags |= 1;
+            times.extend_from_slice(&modified.to_le_bytes());
+        }
+        if let Some(accessed) = self
+            .accessed
+            .and_then(|accessed| i32::try_from(accessed).ok())
+        {
+            flags |= 2;
+            if !central {
+                times.extend_from_slice(&accessed.to_le_bytes());
+            }
+        }
+
+        let mut extra = Vec::new();
+        if flags != 0 {
+            extra.extend_from_slice(&EXTENDED_TIMESTAMP_EXTRA.to_le_bytes());
+            extra.extend_from_slice(&(1 + times.len() as u16).to_le_bytes());
+            extra.push(flags);
+            extra.extend_from_slice(&times);
+        }
+        extra.extend_from_slice(&UNIX_OWNER_EXTRA.to_le_bytes());
+        extra.extend_from_slice(&11u16.to_le_bytes());
+        extra.push(1);
+        extra.push(4);
+        extra.extend_from_slice(&self.uid.to_le_bytes());
+        extra.push(4);
+        extra.extend_from_slice(&self.gid.to_le_bytes());
+        extra
+    }
+}
+
+/// What the central directory needs to know about an entry once it has been
+/// written.
+struct CentralRecord {
+    name: String,
+    extra: Vec<u8>,
+    external_attributes: u32,
+    dos_time: u16,
+    dos_date: u16,
+    crc: u32,
+    size: u64,
+    offset: u64,
+}
+
+/// Writes entries into a ZIP archive. The CRC of each entry is filled in
+/// after its data, so the output must be seekable.
+pub struct ZipWriter<W: Write + Seek> {
+    output: W,
+    /// Where the next entry starts.
+    offset: u64,
+    central: Vec<CentralRecord>,
+}
+
+impl<W: Write + Seek> ZipWriter<W> {
+    pub fn new(mut output: W) -> Result<Self, io::Error> {
+        let offset = output.stream_position()?;
+        Ok(Self {
+            output,
+            offset,
+            central: Vec::new(),
+        })
+    }
+
+    /// Write an entry, followed by `entry.size` bytes of data from `data` for
+    /// files, or the target of a symbolic link. If `data` ends early or
+    /// fails, the rest is filled with zeroes so that the archive stays
+    /// readable. Returns the number of bytes taken from `data`, and the error
+    /// that stopped it, if any.
+    pub fn append(
+        &mut self,
+        entry: &ZipEntry,
+        data: &mut impl Read,
+    ) -> Result<(u64, Option<io::Error>), io::Error> {
+        let name = entry.name();
+        let size = match &entry.kind {
+            ZipEntryKind::File => entry.size,
+            ZipEntryKind::Directory => 0,
+            ZipEntryKind::Symlink(target) => target.len() as u64,
+        };
+        let name_length =
+            u16::try_from(name.len()).map_err(|_| invalid_input(format!("{name} is too long")))?;
+        let zip64 = size >= ZIP32_LIMIT;
+        let (dos_time, dos_date) = dos_date_time(entry.modified);
+
+        let mut extra = Vec::new();
+        if zip64 {
+            extra.extend_from_slice(&ZIP64_EXTRA.to_le_bytes());
+            extra.extend_from_slice(&16u16.to_le_bytes());
+            extra.extend_from_slice(&size.to_le_bytes());
+            extra.extend_from_slice(&size.to_le_bytes());
+        }
+        extra.extend_from_slice(&entry.extra_fields(false));
+
+        let mut header = Vec::with_capacity(30 + name.len() + extra.len());
+        header.extend_from_slice(&LOCAL_HEADER_SIGNATURE.to_le_bytes());
+        header.extend_from_slice(&version_needed(zip64).to_le_bytes());
+        header.extend_from_slice(&FLAG_UTF8.to_le_bytes());
+        // Stored, without compression.
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(&dos_time.to_le_bytes());
+        header.extend_from_slice(&dos_date.to_le_bytes());
+        // The CRC, filled in after the data.
+        header.extend_from_slice(&0u32.to_le_bytes());
+        let size_field = size.min(ZIP32_LIMIT) as u32;
+        header.extend_from_slice(&size_field.to_le_bytes());
+        header.extend_from_slice(&size_field.to_le_bytes());
+        header.extend_from_slice(&name_length.to_le_bytes());
+        header.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+        header.extend_from_slice(name.as_bytes());
+        header.extend_from_slice(&extra);
+        self.output.write_all(&header)?;
+
+        let mut crc = Crc32::new();
+        let (copied, read_error) = match &entry.kind {
+            ZipEntryKind::File => self.copy_data(data, size, &mut crc)?,
+            ZipEntryKind::Directory => (0, None),
+            ZipEntryKind::Symlink(target) => {
+                self.output.write_all(target.as_bytes())?;
+                crc.update(target.as_bytes());
+                (size, None)
+            }
+        };
+        let crc = crc.finish();
+
+        let end = self.offset + header.len() as u64 + size;
+        self.output.seek(SeekFrom::Start(self.offset + 14))?;
+        self.output.write_all(&crc.to_le_bytes())?;
+        self.output.seek(SeekFrom::Start(end))?;
+
+        self.central.push(CentralRecord {
+            name,
+            extra: entry.extra_fields(true),
+            external_attributes: entry.unix_mode() << 16
+                | match entry.kind {
+                    ZipEntryKind::Directory => MSDOS_DIRECTORY,
+                    _ => 0,
+                },
+            dos_time,
+            dos_date,
+            crc,
+            size,
+            offset: self.offset,
+        });
+        self.offset = end;
+        Ok((copied, read_error))
+    }
+
+    fn copy_data(
+        &mut self,
+        data: &mut impl Read,
+        size: u64,
+        crc: &mut Crc32,
+    ) -> Result<(u64, Option<io::Error>), io::Error> {
+        // Most files are small, so the buffer is only as large as needed.
+        let mut buf = vec![0u8; size.min(1 << 20) as usize];
+        let mut copied = 0u64;
+        let mut read_error = None;
+        while copied < size {
+            let wanted = (size - copied).min(buf.len() as u64) as usize;
+            match data.read(&mut buf[..wanted]) {
+                Ok(0) => break,
+                Ok(read) => {
+                    self.output.write_all(&buf[..read])?;
+                    crc.update(&buf[..read]);
+                    copied += read as u64;
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    read_error = Some(err);
+                    break;
+                }
+            }
+        }
+        let mut remaining = size - copied;
+        if remaining > 0 {
+            buf.fill(0);
+        }
+        while remaining > 0 {
+            let length = remaining.min(buf.len() as u64) as usize;
+            self.output.write_all(&buf[..length])?;
+            crc.update(&buf[..length]);
+            remaining -= length as u64;
+        }
+        Ok((copied, read_error))
+    }
+
+    /// Write the central directory, and return the output.
+    pub fn finish(mut self) -> Result<W, io::Error> {
+        let directory_offset = self.offset;
+        let mut directory = Vec::new();
+        for record in &self.central {
+            let mut zip64_extra = Vec::new();
+            if record.size >= ZIP32_LIMIT {
+                zip64_extra.extend_from_slice(&record.size.to_le_bytes());
+                zip64_extra.extend_from_slice(&record.size.to_le_bytes());
+            }
+            if record.offset >= ZIP32_LIMIT {
+                zip64_extra.extend_from_slice(&record.offset.to_le_bytes());
+            }
+            let zip64 = !zip64_extra.is_empty();
+            let mut extra = Vec::new();
+            if zip64 {
+                extra.extend_from_slice(&ZIP64_EXTRA.to_le_bytes());
+                extra.extend_from_slice(&(zip64_extra.len() as u16).to_le_bytes());
+                extra.extend_from_slice(&zip64_extra);
+            }
+            extra.extend_from_slice(&record.extra);
+
+            directory.extend_from_slice(&CENTRAL_HEADER_SIGNATURE.to_le_bytes());
+            directory.extend_from_slice(&VERSION_MADE_BY.to_le_bytes());
+            directory.extend_from_slice(&version_needed(zip64).to_le_bytes());
+            directory.extend_from_slice(&FLAG_UTF8.to_le_bytes());
+            directory.extend_from_slice(&0u16.to_le_bytes());
+            directory.extend_from_slice(&record.dos_time.to_le_bytes());
+            directory.extend_from_slice(&record.dos_date.to_le_bytes());
+            directory.extend_from_slice(&record.crc.to_le_bytes());
+            let size_field = record.size.min(ZIP32_LIMIT) as u32;
+            directory.extend_from_slice(&size_field.to_le_bytes());
+            directory.extend_from_slice(&size_field.to_le_bytes());
+            directory.extend_from_slice(&(record.name.len() as u16).to_le_bytes());
+            directory.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+            // Comment length, disk number, and internal attributes.
+            directory.extend_from_slice(&[0u8; 6]);
+            directory.extend_from_slice(&record.external_attributes.to_le_bytes());
+            directory.extend_from_slice(&(record.offset.min(ZIP32_LIMIT) as u32).to_le_bytes());
+            directory.extend_from_slice(record.name.as_bytes());
+            directory.extend_from_slice(&extra);
+        }
+        self.output.write_all(&directory)?;
+
+        let entries = self.central.len() as u64;
+        let directory_size = directory.len() as u64;
+        let zip64_end_offset = directory_offset + directory_size;
+        if entries >= 0xffff || directory_size >= ZIP32_LIMIT || directory_offset >= ZIP32_LIMIT {
+            let mut end = Vec::new();
+            end.extend_from_slice(&ZIP64_END_SIGNATURE.to_le_bytes());
+            // Size of the rest of the record.
+            end.extend_from_slice(&44u64.to_le_bytes());
+            end.extend_from_slice(&VERSION_MADE_BY.to_le_bytes());
+            end.extend_from_slice(&VERSION_NEEDED_ZIP64.to_le_bytes());
+            // This disk, and the disk with the central directory.
+            end.extend_from_slice(&[0u8; 8]);
+            end.extend_from_slice(&entries.to_le_bytes());
+            end.extend_from_slice(&entries.to_le_bytes());
+            end.extend_from_slice(&directory_size.to_le_bytes());
+            end.extend_from_slice(&directory_offset.to_le_bytes());
+
+            end.extend_from_slice(&ZIP64_LOCATOR_SIGNATURE.to_le_bytes());
+            end.extend_from_slice(&0u32.to_le_bytes());
+            end.extend_from_slice(&zip64_end_offset.to_le_bytes());
+            end.extend_from_slice(&1u32.to_le_bytes());
+            self.output.write_all(&end)?;
+        }
+
+        let mut end = Vec::new();
+        end.extend_from_slice(&END_SIGNATURE.to_le_bytes());
+        end.extend_from_slice(&[0u8; 4]);
+        let entries_field = entries.min(0xffff) as u16;
+        end.extend_from_slice(&entries_field.to_le_bytes());
+        end.extend_from_slice(&entries_field.to_le_bytes());
+        end.extend_from_slice(&(directory_size.min(ZIP32_LIMIT) as u32).to_le_bytes());
+        end.extend_from_slice(&(directory_offset.min(ZIP32_LIMIT) as u32).to_le_bytes());
+        // Comment length.
+        end.extend_from_slice(&0u16.to_le_bytes());
+        self.output.write_all(&end)?;
+        self.output.flush()?;
+        Ok(self.output)
+    }
+}
+
+fn version_needed(zip64: bool) -> u16 {
+    match zip64 {
+        true => VERSION_NEEDED_ZIP64,
+        false => VERSION_NEEDED,
+    }
+}
+
+/// The MS-DOS time and date of a Unix time, in UTC. Dates outside the
+/// range of MS-DOS, 1980 to 2107, are clamped to it. Readers that know the
+/// extended timestamp use that instead.
+fn dos_date_time(seconds: i64) -> (u16, u16) {
+    let (days, time) = (seconds.div_euclid(86_400), seconds.rem_euclid(86_400));
+    let (year, month, day) = civil_from_days(days);
+    if year < 1980 {
+        return (0, 1 << 5 | 1);
+    }
+    if year > 2107 {
+        return (23 << 11 | 59 << 5 | 29, 127 << 9 | 12 << 5 | 31);
+    }
+    let dos_time = (time / 3600) << 11 | (time / 60 % 60) << 5 | (time % 60 / 2);
+    let dos_date = (year - 1980) << 9 | month << 5 | day;
+    (dos_time as u16, dos_date as u16)
+}
+
+/// The CRC-32 used by ZIP, with the reflected polynomial 0xEDB88320.
+struct Crc32 {
+    value: u32,
+}
+
+/// The CRC of each byte value, built at compile time.
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            c = match c & 1 {
+                1 => 0xedb8_8320 ^ (c >> 1),
+                _ => c >> 1,
+            };
+            bit += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+};
+
+impl Crc32 {
+    fn new() -> Self {
+        Self { value: 0xffff_ffff }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.value =
+                CRC32_TABLE[((self.value ^ u32::from(byte)) & 0xff) as usize] ^ (self.value >> 8);
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        self.value ^ 0xffff_ffff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::zip::ZipArchive;
+    use ::zip::extra_fields::ExtraField;
+    use std::collections::BTreeMap;
+    use std::io::Cursor;
+
+    /// A seekable buffer that only stores runs of bytes with something other
+    /// than zeroes in them, so that archives with offsets and sizes past 4 GiB
+    /// fit in memory. Unwritten bytes read as zeroes.
+    #[derive(Default)]
+    struct Sparse {
+        chunks: BTreeMap<u64, Vec<u8>>,
+        length: u64,
+        position: u64,
+    }
+
+    impl Write for Sparse {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let (start, end) = (self.position, self.position + buf.len() as u64);
+            // Overwrite within an earlier chunk, as when the CRC is filled in.
+            let previous = self.chunks.range_mut(..=start).next_back();
+            if let Some((&chunk_start, chunk)) = previous
+                && end <= chunk_start + chunk.len() as u64
+            {
+                let offset = (start - chunk_start) as usize;
+                chunk[offset..offset + buf.len()].copy_from_slice(buf);
+            } else if buf.iter().any(|&b| b != 0) {
+                assert!(start >= self.length, "only appends may start chunks");
+                self.chunks.insert(start, buf.to_vec());
+            }
+            self.position = end;
+            self.length = self.length.max(end);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Read for Sparse {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let length = buf
+                .len()
+                .min(self.length.saturating_sub(self.position) as usize);
+            let buf = &mut buf[..length];
+            buf.fill(0);
+            let (start, end) = (self.position, self.position + length as u64);
+            let first = self
+                .chunks
+                .range(..=start)
+                .next_back()
+                .map_or(start, |(&chunk_start, _)| chunk_start);
+            for (&chunk_start, chunk) in self.chunks.range(first..end) {
+                let chunk_end = chunk_start + chunk.len() as u64;
+                let (from, to) = (chunk_start.max(start), chunk_end.min(end));
+                if from < to {
+                    buf[(from - start) as usize..(to - start) as usize].copy_from_slice(
+                        &chunk[(from - chunk_start) as usize..(to - chunk_start) as usize],
+                    );
+                }
+            }
+            self.position = end;
+            Ok(length)
+        }
+    }
+
+    impl Seek for Sparse {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.position = match pos {
+                SeekFrom::Start(position) => position,
+                SeekFrom::End(delta) => self.length.checked_add_signed(delta).unwrap(),
+                SeekFrom::Current(delta) => self.position.checked_add_signed(delta).unwrap(),
+            };
+            Ok(self.position)
+        }
+    }
+
+    fn file(path: &str, size: u64) -> ZipEntry {
+        let mut entry = ZipEntry::new(path, ZipEntryKind::File);
+        entry.size = size;
+        entry
+    }
+
+    fn modification_time(file: &::zip::read::ZipFile) -> Option<u32> {
+        file.extra_data_fields().find_map(|field| match field {
+            ExtraField::ExtendedTimestamp(timestamp) => timestamp.mod_time(),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut folder = ZipEntry::new("Volume", ZipEntryKind::Directory);
+        folder.mode = 0o755;
+        folder.modified = 1_700_000_000;
+        let mut hello = file("Volume/Résumé.txt", 12);
+        hello.mode = 0o600;
+        hello.modified = 1_700_000_123;
+        hello.accessed = Some(1_700_000_456);
+        let link = ZipEntry::new("Volume/link", ZipEntryKind::Symlink("Résumé.txt".into()));
+
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new())).unwrap();
+        zip.append(&folder, &mut io::empty()).unwrap();
+        zip.append(&hello, &mut b"Hello world\n".as_slice())
+            .unwrap();
+        zip.append(&link, &mut io::empty()).unwrap();
+        let mut archive = ZipArchive::new(zip.finish().unwrap()).unwrap();
+        assert_eq!(archive.len(), 3);
+
+        let folder = archive.by_index(0).unwrap();
+        assert_eq!(folder.name(), "Volume/");
+        assert!(folder.is_dir());
+        assert_eq!(folder.unix_mode(), Some(0o040755));
+        assert_eq!(modification_time(&folder), Some(1_700_000_000));
+        drop(folder);
+
+        let mut hello = archive.by_index(1).unwrap();
+        assert_eq!(hello.name(), "Volume/Résumé.txt");
+        assert_eq!(hello.size(), 12);
+        assert_eq!(hello.unix_mode(), Some(0o100600));
+        assert_eq!(modification_time(&hello), Some(1_700_000_123));
+        // 2023-11-14 22:15:23 UTC, with the seconds halved by MS-DOS.
+        let dos = hello.last_modified().unwrap();
+        assert_eq!((dos.year(), dos.month(), dos.day()), (2023, 11, 14));
+        assert_eq!((dos.hour(), dos.minute(), dos.second()), (22, 15, 22));
+        // Reading to the end also checks the CRC.
+        let mut data = Vec::new();
+        hello.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"Hello world\n");
+        drop(hello);
+
+        let mut link = archive.by_index(2).unwrap();
+        assert!(link.is_symlink());
+        let mut target = String::new();
+        link.read_to_string(&mut target).unwrap();
+        assert_eq!(target, "Résumé.txt");
+    }
+
+    #[test]
+    fn short_data_is_padded() {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new())).unwrap();
+        let (copied, error) = zip
+            .append(&file("short", 100), &mut b"data".as_slice())
+            .unwrap();
+        assert_eq!(copied, 4);
+        assert!(error.is_none());
+        zip.append(&file("next", 4), &mut b"next".as_slice())
+            .unwrap();
+        let mut archive = ZipArchive::new(zip.finish().unwrap()).unwrap();
+
+        let mut data = Vec::new();
+        archive.by_index(0).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data.len(), 100);
+        assert_eq!(&data[..4], b"data");
+        assert!(data[4..].iter().all(|&b| b == 0));
+        data.clear();
+        archive.by_index(1).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"next");
+    }
+
+    #[test]
+    fn dos_dates_are_clamped() {
+        assert_eq!(dos_date_time(0), (0, 1 << 5 | 1));
+        // 1980-01-01 00:00:00.
+        assert_eq!(dos_date_time(315_532_800), (0, 1 << 5 | 1));
+        // 2000-02-29 12:34:56.
+        assert_eq!(
+            dos_date_time(951_827_696),
+            (12 << 11 | 34 << 5 | 28, 20 << 9 | 2 << 5 | 29)
+        );
+        assert_eq!(
+            dos_date_time(i64::from(i32::MAX) * 4),
+            (23 << 11 | 59 << 5 | 29, 127 << 9 | 12 << 5 | 31)
+        );
+    }
+
+    /// Entries starting at or past 4 GiB need their offset in a ZIP64 extra
+    /// field, and the central directory needs the ZIP64 end records.
+    #[test]
+    fn zip64_offsets() {
+        for start in [ZIP32_LIMIT - 1, ZIP32_LIMIT] {
+            let mut output = Sparse::default();
+            output.seek(SeekFrom::Start(start)).unwrap();
+            let mut zip = ZipWriter::new(output).unwrap();
+            zip.append(&file("first", 5), &mut b"first".as_slice())
+                .unwrap();
+            zip.append(&file("second", 6), &mut b"second".as_slice())
+                .unwrap();
+            let mut archive = ZipArchive::new(zip.finish().unwrap()).unwrap();
+
+            assert_eq!(archive.len(), 2);
+            for (index, name) in ["first", "second"].into_iter().enumerate() {
+                let mut entry = archive.by_index(index).unwrap();
+                assert_eq!(entry.name(), name);
+                assert!(entry.header_start() >= start);
+                let mut data = String::new();
+                entry.read_to_string(&mut data).unwrap();
+                assert_eq!(data, name);
+            }
+        }
+    }
+
+    /// Counts of 65535 entries or more only fit in the ZIP64 end record.
+    #[test]
+    fn zip64_entry_count() {
+        for count in [0xFFFE, 0xFFFF] {
+            let mut zip = ZipWriter::new(Cursor::new(Vec::new())).unwrap();
+            for n in 0..count {
+                zip.append(&file(&n.to_string(), 0), &mut io::empty())
+                    .unwrap();
+            }
+            let output = zip.finish().unwrap().into_inner();
+            let has_zip64_end = output
+                .windows(4)
+                .rev()
+                .take(200)
+                .any(|window| window == ZIP64_END_SIGNATURE.to_le_bytes());
+            assert_eq!(has_zip64_end, count >= 0xFFFF);
+
+            let mut archive = ZipArchive::new(Cursor::new(output)).unwrap();
+            assert_eq!(archive.len(), count);
+            assert_eq!(
+                archive.by_index(count - 1).unwrap().name(),
+                (count - 1).to_string()
+            );
+        }
+    }
+
+    /// Sizes of 4 GiB less one byte and up are stored in ZIP64 extra fields.
+    /// Slow, as each byte passes through the CRC.
+    #[test]
+    #[ignore = "writes 4 GiB of data"]
+    fn zip64_sizes() {
+        for size in [ZIP32_LIMIT - 1, ZIP32_LIMIT] {
+            let mut zip = ZipWriter::new(Sparse::default()).unwrap();
+            zip.append(&file("large", size), &mut io::repeat(0).take(size))
+                .unwrap();
+            zip.append(&file("after", 5), &mut b"after".as_slice())
+                .unwrap();
+            let mut output = zip.finish().unwrap();
+
+            let mut header = [0u8; 30];
+            output.seek(SeekFrom::Start(0)).unwrap();
+            output.read_exact(&mut header).unwrap();
+            let version_needed = u16::from_le_bytes([header[4], header[5]]);
+            assert_eq!(version_needed == VERSION_NEEDED_ZIP64, size >= ZIP32_LIMIT);
+
+            let mut archive = ZipArchive::new(output).unwrap();
+            assert_eq!(archive.by_index_raw(0).unwrap().size(), size);
+            let mut data = String::new();
+            archive
+                .by_index(1)
+                .unwrap()
+                .read_to_string(&mut data)
+                .unwrap();
+            assert_eq!(data, "after");
+        }
+    }
+}